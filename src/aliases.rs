@@ -0,0 +1,93 @@
+//! Preferred names of payees, remembered between runs and applied in every merge
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The preferred name of each payee, added with `bank-csv alias add`
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AliasStore {
+    /// Preferred name, keyed by the payee as it is written in the bank exports or the output files
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl AliasStore {
+    /// The default location of the aliases file: `~/.config/bank-csv/aliases.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".config").join("bank-csv").join("aliases.toml"))
+    }
+
+    /// Load the aliases from a TOML file; a missing file has no aliases
+    pub fn load(file_path: &Path) -> io::Result<Self> {
+        if !file_path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(file_path)?;
+        toml::from_str(&content).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", file_path.display(), err),
+            )
+        })
+    }
+
+    /// Save the aliases to a TOML file, creating its directory if needed
+    pub fn save(&self, file_path: &Path) -> io::Result<()> {
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = toml::to_string(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        fs::write(file_path, content)
+    }
+
+    /// Add or replace the alias of a payee
+    ///
+    /// returns: Option<String> with the previous name of the payee, if it had one
+    pub fn add(&mut self, payee: &str, name: &str) -> Option<String> {
+        let previous = self.remove(payee);
+        self.aliases
+            .insert(payee.trim().to_string(), name.trim().to_string());
+        previous
+    }
+
+    /// Remove the alias of a payee, compared like in [`AliasStore::get`]
+    ///
+    /// returns: Option<String> with the removed name, if the payee had one
+    pub fn remove(&mut self, payee: &str) -> Option<String> {
+        let key = self.key(payee)?.to_string();
+        self.aliases.remove(&key)
+    }
+
+    /// The preferred name of a payee, compared ignoring case and surrounding spaces
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::aliases::AliasStore;
+    /// let mut store = AliasStore::default();
+    /// store.add("REWE MARKT GMBH HAMBURG", "REWE");
+    /// assert_eq!(store.get(" Rewe Markt GmbH Hamburg"), Some("REWE"));
+    /// assert_eq!(store.get("REWE Markt GmbH Berlin"), None);
+    /// assert_eq!(store.add("rewe markt gmbh hamburg", "Rewe"), Some("REWE".to_string()));
+    /// assert_eq!(store.aliases.len(), 1);
+    /// ```
+    pub fn get(&self, payee: &str) -> Option<&str> {
+        let key = self.key(payee)?;
+        self.aliases.get(key).map(String::as_str)
+    }
+
+    /// The key of the alias of a payee, as it was added
+    fn key(&self, payee: &str) -> Option<&str> {
+        if self.aliases.is_empty() {
+            return None;
+        }
+        let payee = payee.trim().to_lowercase();
+        self.aliases
+            .keys()
+            .find(|key| key.to_lowercase() == payee)
+            .map(String::as_str)
+    }
+}
@@ -0,0 +1,71 @@
+//! Closing balance rows at the end of each month, for spreadsheet templates that expect one
+use crate::CsvOutputRow;
+use chrono::{Datelike, Months, NaiveDate};
+
+/// Type of the synthetic rows with the closing balance of an account;
+/// these rows are not transactions, so they are ignored when output files are read again
+pub const CLOSING_BALANCE_TYPE: &str = "Closing balance";
+
+/// Closing balances of an account at the end of each month, computed back from a known balance
+/// by subtracting the transactions after each month end
+///
+/// Months go from the one of the earliest transaction to the one of the balance date,
+/// whose closing balance is the known one. Transactions after the balance date are ignored.
+///
+/// # Arguments
+///
+/// * `balance`: the known balance
+/// * `balance_date`: the date of the known balance
+/// * `transactions`: all transactions of the account up to the balance date
+///
+/// returns: Vec<(NaiveDate, f64)> with the last day of each month (the balance date for its month) and the balance
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::balance::monthly_closing_balances;
+/// use bank_csv::CsvOutputRow;
+/// use chrono::NaiveDate;
+/// let date = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+/// let row = |day: NaiveDate, amount: &str| CsvOutputRow::builder(day).amount(amount).build();
+/// let transactions = [row(date(1, 10), "-50"), row(date(2, 5), "-100"), row(date(2, 20), "200,50")];
+/// assert_eq!(
+///     monthly_closing_balances(1000.0, date(2, 25), &transactions),
+///     vec![(date(1, 31), 899.5), (date(2, 25), 1000.0)]
+/// );
+/// ```
+pub fn monthly_closing_balances(
+    balance: f64,
+    balance_date: NaiveDate,
+    transactions: &[CsvOutputRow],
+) -> Vec<(NaiveDate, f64)> {
+    let amounts: Vec<(NaiveDate, f64)> = transactions
+        .iter()
+        .filter(|transaction| transaction.date <= balance_date)
+        .filter_map(|transaction| {
+            let amount = transaction.amount.replace(',', ".").parse::<f64>().ok()?;
+            Some((transaction.date, amount))
+        })
+        .collect();
+    let Some(first_date) = amounts.iter().map(|(date, _)| *date).min() else {
+        return Vec::new();
+    };
+    let mut balances = Vec::new();
+    let mut month_start = first_date.with_day(1).unwrap_or(first_date);
+    while month_start <= balance_date {
+        let next_month = month_start + Months::new(1);
+        let month_end = next_month
+            .pred_opt()
+            .unwrap_or(month_start)
+            .min(balance_date);
+        let later: f64 = amounts
+            .iter()
+            .filter(|(date, _)| *date > month_end)
+            .map(|(_, amount)| amount)
+            .sum();
+        // Round away the floating point noise of the subtraction
+        balances.push((month_end, ((balance - later) * 100.0).round() / 100.0));
+        month_start = next_month;
+    }
+    balances
+}
@@ -0,0 +1,56 @@
+//! American Express (Germany)
+use crate::banks::negate;
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::CsvFormat;
+
+/// Charges are positive and payments of the card negative; only euro cards
+const AMEX_COLUMNS: [&str; 3] = ["Datum", "Beschreibung", "Betrag"];
+
+/// American Express (Germany) export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Amex;
+
+impl SourceSpec for Amex {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Amex)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &AMEX_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Datum",
+            // No currency column; only euros, other currencies are dropped later
+            "",
+            "Betrag",
+            "",
+            "Beschreibung",
+            "",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d/%m/%Y", "%d.%m.%Y"]
+    }
+
+    // Only euros; charges are positive and payments of the card negative
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        if upper_currency != "EUR" {
+            return Ok(false);
+        }
+        row.currency = upper_currency.to_string();
+        row.amount = negate(&row.amount);
+        Ok(true)
+    }
+
+    fn category_column(&self) -> Option<&str> {
+        Some("Kategorie")
+    }
+}
@@ -0,0 +1,69 @@
+//! Apple Card statements, only in US dollars; purchases are positive in the file
+use crate::banks::negate;
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+
+const APPLE_CARD_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Transaction Date",
+    "Clearing Date",
+    "Description",
+    "Merchant",
+    "Category",
+];
+
+/// Apple Card monthly statement
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AppleCard;
+
+impl SourceSpec for AppleCard {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::AppleCard)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &APPLE_CARD_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Transaction Date",
+            // Placeholder for the currency, always US dollars; rows are dropped for other currencies later
+            "Clearing Date",
+            "Amount (USD)",
+            "Type",
+            "Merchant",
+            "Description",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%m/%d/%Y"]
+    }
+
+    // Only US dollars; purchases are positive and payments of the card negative
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        if upper_currency != "USD" {
+            return Ok(false);
+        }
+        row.currency = upper_currency.to_string();
+        row.amount = negate(&row.amount);
+        Ok(true)
+    }
+
+    fn value_date_column(&self) -> Option<&str> {
+        Some("Clearing Date")
+    }
+
+    fn default_currency(&self, _column_names: &[&str]) -> String {
+        "USD".to_string()
+    }
+
+    fn category_column(&self) -> Option<&str> {
+        Some("Category")
+    }
+}
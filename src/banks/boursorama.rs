@@ -0,0 +1,60 @@
+//! BoursoBank (formerly Boursorama, France); only euro accounts
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+
+/// Only euro accounts; the categories are in French, e.g. "Alimentation"
+const BOURSORAMA_COLUMNS: [&str; NUM_FIRST_COLUMNS] =
+    ["dateOp", "dateVal", "label", "category", "categoryParent"];
+
+/// BoursoBank (Boursorama) export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Boursorama;
+
+impl SourceSpec for Boursorama {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Boursorama)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &BOURSORAMA_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "dateOp",
+            // No currency column; only euros, other currencies are dropped later
+            "",
+            "amount",
+            "",
+            "supplierFound",
+            "label",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%Y-%m-%d", "%d/%m/%Y"]
+    }
+
+    // Only euros; large amounts have spaces as thousands separators, e.g. "-1 234,56"
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        if upper_currency != "EUR" {
+            return Ok(false);
+        }
+        row.currency = upper_currency.to_string();
+        row.amount.retain(|c| !c.is_whitespace());
+        Ok(true)
+    }
+
+    fn value_date_column(&self) -> Option<&str> {
+        Some("dateVal")
+    }
+
+    fn category_column(&self) -> Option<&str> {
+        Some("category")
+    }
+}
@@ -0,0 +1,99 @@
+//! Coinbase crypto exchange; the fiat total is used as the amount
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{copy_from_header, CsvFormat, StatementMetadata, NUM_FIRST_COLUMNS};
+use encoding_rs::Encoding;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+const COINBASE_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Timestamp",
+    "Transaction Type",
+    "Asset",
+    "Quantity Transacted",
+    "Spot Price Currency",
+];
+
+/// Coinbase transaction report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Coinbase;
+
+impl SourceSpec for Coinbase {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Coinbase)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &COINBASE_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Timestamp",
+            "Spot Price Currency",
+            "Total (inclusive of fees)",
+            "Transaction Type",
+            "Notes",
+            // The asset (BTC, ETH, ...) goes to the memo; the amount is the fiat total
+            "Asset",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%Y-%m-%dT%H:%M:%SZ", "%Y-%m-%d %H:%M:%S UTC"]
+    }
+
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        _upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        // Coinbase totals are always positive; buying crypto is money going out
+        if row.transaction_type.contains("Buy") {
+            row.amount = format!("-{}", row.amount);
+        }
+        Ok(true)
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("Spot Price Currency")
+    }
+
+    // Coinbase adds a few lines of explanation before the header
+    fn edits_file(&self) -> bool {
+        true
+    }
+
+    fn edit_file(
+        &self,
+        file_path: &Path,
+        temp_file: &NamedTempFile,
+        encoding: &'static Encoding,
+    ) -> Result<Option<StatementMetadata>, BankCsvError> {
+        coinbase_edit_file(file_path, temp_file, encoding)?;
+        Ok(None)
+    }
+}
+
+/// Remove the explanatory lines that Coinbase adds above the header of a transaction report
+///
+/// # Arguments
+///
+/// * `original_coinbase_csv_file`: path to the original Coinbase CSV file
+/// * `temp_file`:  a temporary file to write the filtered CSV to
+/// * `encoding`: the encoding of the original file
+///
+/// returns: Result<(), BankCsvError>
+pub fn coinbase_edit_file(
+    original_coinbase_csv_file: &Path,
+    temp_file: &NamedTempFile,
+    encoding: &'static Encoding,
+) -> Result<(), BankCsvError> {
+    copy_from_header(
+        original_coinbase_csv_file,
+        temp_file,
+        encoding,
+        &["Timestamp"],
+    )?;
+    Ok(())
+}
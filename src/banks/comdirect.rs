@@ -0,0 +1,70 @@
+//! comdirect (securities)
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{securities_type, CsvFormat, NUM_FIRST_COLUMNS};
+
+/// The comdirect depot has no transaction type; buys have a negative amount
+const COMDIRECT_DEPOT_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Buchungstag",
+    "Geschäftstag",
+    "Stück / Nom.",
+    "Bezeichnung",
+    "WKN",
+];
+
+/// comdirect depot export, only read in securities mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComdirectDepot;
+
+impl SourceSpec for ComdirectDepot {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::ComdirectDepot)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &COMDIRECT_DEPOT_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Buchungstag",
+            // The amount is in euros; the currency column is the one of the price
+            "",
+            "Umsatz in EUR",
+            // No transaction type; it's taken from the sign of the amount later
+            "",
+            "Bezeichnung",
+            "WKN",
+            // Extra columns for the securities mode; no fee column
+            "Stück / Nom.",
+            "",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d.%m.%Y"]
+    }
+
+    // Only euros; buys, sells and dividends have the same type in all brokerages
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        if upper_currency != "EUR" {
+            return Ok(false);
+        }
+        row.currency = upper_currency.to_string();
+        row.transaction_type = securities_type(&row.transaction_type, &row.amount);
+        Ok(true)
+    }
+
+    fn is_securities(&self) -> bool {
+        true
+    }
+
+    // comdirect has the WKN instead of the ISIN
+    fn memo_is_isin(&self) -> bool {
+        false
+    }
+}
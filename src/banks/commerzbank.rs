@@ -0,0 +1,80 @@
+//! Commerzbank has lines above the header and below the transactions, which are skipped
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+
+const COMMERZBANK_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Buchungstag",
+    "Wertstellung",
+    "Umsatzart",
+    "Buchungstext",
+    "Betrag",
+];
+
+/// Commerzbank Umsätze export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Commerzbank;
+
+impl SourceSpec for Commerzbank {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Commerzbank)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &COMMERZBANK_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Buchungstag",
+            "Währung",
+            "Betrag",
+            "Umsatzart",
+            // The payee is part of the booking text, see `commerzbank_payee`
+            "",
+            "Buchungstext",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d.%m.%Y"]
+    }
+
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        _upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        row.payee = commerzbank_payee(&row.memo);
+        Ok(true)
+    }
+
+    fn value_date_column(&self) -> Option<&str> {
+        Some("Wertstellung")
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("Währung")
+    }
+
+    fn category_column(&self) -> Option<&str> {
+        Some("Kategorie")
+    }
+}
+
+/// The payee of a Commerzbank card payment, at the start of its booking text (e.g. "REWE Markt GmbH//Hamburg/DE ...");
+/// other booking texts have no separate payee
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::banks::commerzbank::commerzbank_payee;
+/// assert_eq!(commerzbank_payee("REWE Markt GmbH//Hamburg/DE 2024-07-01T12:34:56 Kartenzahlung"), "REWE Markt GmbH");
+/// assert_eq!(commerzbank_payee("Miete Juli End-to-End-Ref.: NOTPROVIDED"), "");
+/// ```
+pub fn commerzbank_payee(booking_text: &str) -> String {
+    booking_text
+        .split_once("//")
+        .map(|(payee, _)| payee.trim().to_string())
+        .unwrap_or_default()
+}
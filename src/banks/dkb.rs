@@ -0,0 +1,452 @@
+//! DKB exports; DKB has a weird CSV with some lines on the top that don't match the rest of the file,
+//! see [`dkb_edit_file`]. There is no currency column, everything is in euros
+use crate::banks::translate;
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{
+    copy_from_header, normalize_amount, parse_date_str, strip_currency_symbols, strip_quotes,
+    CsvFormat, StatementMetadata, NUM_FIRST_COLUMNS,
+};
+use colored::Colorize;
+use encoding_rs::Encoding;
+use regex::Regex;
+use std::path::Path;
+use std::sync::OnceLock;
+use tempfile::NamedTempFile;
+
+/// Lines containing one of these words are the header of a DKB CSV; the lines above them are skipped.
+/// "Purpose" is the memo column when the banking UI is in English
+const DKB_HEADER_MARKERS: [&str; 4] = ["Verwendungszweck", "Purpose", "ISIN", "Belegdatum"];
+/// The first line of a DKB CSV has the account type, in German or English
+pub(crate) const DKB_ACCOUNT_TYPES: [&str; 5] = [
+    "Girokonto",
+    "Tagesgeld",
+    "Depot",
+    "Kreditkarte",
+    "Checking account",
+];
+const DKB_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Buchungstag",
+    "Wertstellung",
+    "Buchungstext",
+    "Auftraggeber / Begünstigter",
+    "Verwendungszweck",
+];
+const DKB_COLUMNS_2024_09: [&str; NUM_FIRST_COLUMNS] = [
+    "Buchungsdatum",
+    "Wertstellung",
+    "Status",
+    "Zahlungspflichtige*r",
+    "Zahlungsempfänger*in",
+];
+const DKB_DEPOT_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Buchungstag",
+    "Wertstellung",
+    "Transaktion",
+    "Wertpapier",
+    "ISIN",
+];
+/// Legacy credit card exports; the first column tells if the transaction was already billed
+const DKB_CREDIT_CARD_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Umsatz abgerechnet und nicht im Saldo enthalten",
+    "Wertstellung",
+    "Belegdatum",
+    "Beschreibung",
+    "Betrag (EUR)",
+];
+/// The header of the layout used since September 2024, used to translate the English one
+const DKB_HEADER_2024_09: [&str; 12] = [
+    "Buchungsdatum",
+    "Wertstellung",
+    "Status",
+    "Zahlungspflichtige*r",
+    "Zahlungsempfänger*in",
+    "Verwendungszweck",
+    "Umsatztyp",
+    "IBAN",
+    "Betrag (€)",
+    "Gläubiger-ID",
+    "Mandatsreferenz",
+    "Kundenreferenz",
+];
+/// Exports are detected in German; the header in English (the language of the banking UI) is translated
+const DKB_HEADER_2024_09_EN: [&str; 12] = [
+    "Booking date",
+    "Value date",
+    "Status",
+    "Payer",
+    "Payee",
+    "Purpose",
+    "Transaction type",
+    "IBAN",
+    "Amount (€)",
+    "Creditor ID",
+    "Mandate reference",
+    "Customer reference",
+];
+
+/// The layouts of DKB exports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dkb {
+    /// Girokonto (and Tagesgeld) export, from the banking UI used before September 2024
+    Girokonto,
+    /// Girokonto export, layout used since September 2024
+    Sep2024,
+    /// Depot (securities) export, only read in securities mode
+    Depot,
+    /// Legacy credit card (Kreditkarte) export, from the banking UI used before 2023
+    CreditCard,
+}
+
+impl SourceSpec for Dkb {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(match self {
+            Dkb::Girokonto => CsvFormat::Dkb,
+            Dkb::Sep2024 => CsvFormat::DkbSep2024,
+            Dkb::Depot => CsvFormat::DkbDepot,
+            Dkb::CreditCard => CsvFormat::DkbCreditCard,
+        })
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        match self {
+            Dkb::Girokonto => &DKB_COLUMNS,
+            Dkb::Sep2024 => &DKB_COLUMNS_2024_09,
+            Dkb::Depot => &DKB_DEPOT_COLUMNS,
+            Dkb::CreditCard => &DKB_CREDIT_CARD_COLUMNS,
+        }
+    }
+
+    fn translate_header(&self, column_names: &[&str]) -> Option<Vec<String>> {
+        match self {
+            Dkb::Sep2024 => translate(column_names, &DKB_HEADER_2024_09, &[&DKB_HEADER_2024_09_EN]),
+            Dkb::Girokonto | Dkb::Depot | Dkb::CreditCard => None,
+        }
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        match self {
+            Dkb::Girokonto => vec![
+                "Buchungstag",
+                // Use any non-duplicated column here, otherwise polars will panic with:
+                // "column with name 'Verwendungszweck' has more than one occurrence".
+                // The memo (Verwendungszweck = "intended use") contains the foreign currency.
+                // We will filter and replace the value of this column later.
+                // Savings (Tagesgeld) exports have no direct debit columns like "Mandatsreferenz",
+                // so the value date is used, like the other DKB formats.
+                "Wertstellung",
+                "Betrag (EUR)",
+                "Buchungstext",
+                "Auftraggeber / Begünstigter",
+                "Verwendungszweck",
+            ],
+            Dkb::Sep2024 => vec![
+                "Buchungsdatum",
+                // Placeholder for the currency, like the other DKB formats (see above)
+                "Wertstellung",
+                "Betrag (€)",
+                "Umsatztyp",
+                "Zahlungsempfänger*in",
+                "Verwendungszweck",
+            ],
+            Dkb::Depot => vec![
+                "Buchungstag",
+                // Placeholder for the currency, like the other DKB formats
+                "Wertstellung",
+                "Betrag (€)",
+                "Transaktion",
+                "Wertpapier",
+                "ISIN",
+                // Extra columns for the securities mode
+                "Nominal/Stück",
+                "Gebühren (€)",
+            ],
+            Dkb::CreditCard => vec![
+                "Belegdatum",
+                // Placeholder for the currency, like the other DKB formats
+                "Wertstellung",
+                // Amounts are already negative for purchases and positive for refunds and payments
+                "Betrag (EUR)",
+                // No transaction type; the settled flag is not a type
+                "",
+                "Beschreibung",
+                // The amount in the original currency, e.g. "12,99 USD"
+                "Ursprünglicher Betrag",
+            ],
+        }
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        match self {
+            // The new DKB file format has dates with 2-digit years... ¯\_(ツ)_/¯
+            Dkb::Sep2024 => vec!["%d.%m.%y", "%d.%m.%Y"],
+            Dkb::Girokonto | Dkb::Depot | Dkb::CreditCard => vec!["%d.%m.%Y", "%d.%m.%y"],
+        }
+    }
+
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        if upper_currency == "EUR" {
+            row.currency = "EUR".to_string();
+            return Ok(true);
+        }
+        row.currency = upper_currency.to_string();
+        match dkb_extract_amount(&row.currency, &row.memo) {
+            None => Ok(false),
+            Some(extracted_amount) => {
+                // Turn the amount into a negative number
+                row.amount = if row.amount.contains('-') {
+                    format!("-{}", extracted_amount)
+                } else {
+                    extracted_amount
+                };
+                Ok(true)
+            }
+        }
+    }
+
+    fn is_securities(&self) -> bool {
+        *self == Dkb::Depot
+    }
+
+    // DKB has a weird CSV with some lines on the top that don't match the rest of the file
+    fn edits_file(&self) -> bool {
+        true
+    }
+
+    fn edit_file(
+        &self,
+        file_path: &Path,
+        temp_file: &NamedTempFile,
+        encoding: &'static Encoding,
+    ) -> Result<Option<StatementMetadata>, BankCsvError> {
+        dkb_edit_file(file_path, temp_file, encoding).map(Some)
+    }
+
+    fn conversion(&self, memo: &str) -> Option<(String, String, String)> {
+        dkb_conversion(memo)
+    }
+
+    fn value_date_column(&self) -> Option<&str> {
+        Some("Wertstellung")
+    }
+}
+
+/// Remove the first extra lines from a DKB CSV file
+///
+/// # Arguments
+///
+/// * `original_dkb_csv_file`: path to the original DKB CSV file
+/// * `temp_file`:  a temporary file to write the filtered CSV to
+/// * `encoding`: the encoding of the original file, see [`crate::Source::encoding`]
+///
+/// returns: Result<StatementMetadata, BankCsvError> with the account data found in the removed lines
+pub fn dkb_edit_file(
+    original_dkb_csv_file: &Path,
+    temp_file: &NamedTempFile,
+    encoding: &'static Encoding,
+) -> Result<StatementMetadata, BankCsvError> {
+    let preamble = copy_from_header(
+        original_dkb_csv_file,
+        temp_file,
+        encoding,
+        &DKB_HEADER_MARKERS,
+    )?;
+    Ok(dkb_parse_preamble(&preamble))
+}
+
+/// Check if a text looks like an IBAN: two letters, two digits and then letters or digits
+fn looks_like_iban(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() > 12
+        && bytes[..2].iter().all(u8::is_ascii_uppercase)
+        && bytes[2..4].iter().all(u8::is_ascii_digit)
+        && bytes.iter().all(u8::is_ascii_alphanumeric)
+}
+
+/// Parse the lines above the header of a DKB CSV into account data; unknown lines are ignored
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::banks::dkb::dkb_parse_preamble;
+/// use chrono::NaiveDate;
+/// let metadata = dkb_parse_preamble(&[
+///     "\"Girokonto\";\"DE12 1203 0000 1234 5678 90\"".to_string(),
+///     "\"\"".to_string(),
+///     "\"Zeitraum:\";\"01.01.2024 - 31.01.2024\"".to_string(),
+///     "\"Kontostand vom 31.01.2024:\";\"1.234,56 €\"".to_string(),
+/// ]);
+/// assert_eq!(metadata.account.as_deref(), Some("Girokonto"));
+/// assert_eq!(metadata.iban.as_deref(), Some("DE12120300001234567890"));
+/// assert_eq!(metadata.period_start, NaiveDate::from_ymd_opt(2024, 1, 1));
+/// assert_eq!(metadata.period_end, NaiveDate::from_ymd_opt(2024, 1, 31));
+/// assert_eq!(metadata.balance.as_deref(), Some("1234,56"));
+/// assert_eq!(metadata.balance_date, NaiveDate::from_ymd_opt(2024, 1, 31));
+///
+/// // Older exports have one line per field
+/// let old = dkb_parse_preamble(&[
+///     "\"Kontonummer:\";\"DE12120300001234567890 / Girokonto\";".to_string(),
+///     "\"Von:\";\"01.12.2023\";".to_string(),
+///     "\"Bis:\";\"31.12.2023\";".to_string(),
+/// ]);
+/// assert_eq!(old.account.as_deref(), Some("Girokonto"));
+/// assert_eq!(old.iban.as_deref(), Some("DE12120300001234567890"));
+/// assert_eq!(old.period_end, NaiveDate::from_ymd_opt(2023, 12, 31));
+///
+/// // Savings accounts
+/// let savings = dkb_parse_preamble(&["\"Tagesgeld\";\"DE98 1203 0000 9876 5432 10\"".to_string()]);
+/// assert_eq!(savings.account.as_deref(), Some("Tagesgeld"));
+/// assert_eq!(savings.iban.as_deref(), Some("DE98120300009876543210"));
+///
+/// // Legacy credit card exports
+/// let card = dkb_parse_preamble(&[
+///     "\"Kreditkarte:\";\"4998********1234 Kreditkarte\";".to_string(),
+///     "\"Saldo:\";\"-123,45 EUR\";".to_string(),
+///     "\"Datum:\";\"15.03.2021\";".to_string(),
+/// ]);
+/// assert_eq!(card.account.as_deref(), Some("Kreditkarte 4998********1234"));
+/// assert_eq!(card.iban, None);
+/// assert_eq!(card.balance.as_deref(), Some("-123,45"));
+/// assert_eq!(card.balance_date, NaiveDate::from_ymd_opt(2021, 3, 15));
+/// ```
+pub fn dkb_parse_preamble(lines: &[String]) -> StatementMetadata {
+    let date_formats = CsvFormat::DkbSep2024.spec().date_formats();
+    let parse_date = |text: &str| parse_date_str(text, &date_formats);
+    let mut metadata = StatementMetadata::default();
+    for line in lines {
+        let fields: Vec<String> = line
+            .split(';')
+            .map(|field| strip_quotes(field.trim().to_string()).trim().to_string())
+            .collect();
+        let key = fields[0].trim_end_matches(':');
+        let value = fields.get(1).map(String::as_str).unwrap_or_default();
+        if key == "Kontonummer" {
+            let (iban, account) = value.split_once(" / ").unwrap_or((value, ""));
+            metadata.iban = Some(iban.replace(' ', ""));
+            if !account.is_empty() {
+                metadata.account = Some(account.to_string());
+            }
+        } else if DKB_ACCOUNT_TYPES.contains(&key) {
+            // Credit cards repeat the type after the masked number: "4998********1234 Kreditkarte"
+            let number = value.trim_end_matches(key).replace(' ', "");
+            if looks_like_iban(&number) {
+                metadata.account = Some(key.to_string());
+                metadata.iban = Some(number);
+            } else {
+                metadata.account = Some(format!("{} {}", key, number).trim().to_string());
+            }
+        } else if key == "Saldo" {
+            metadata.balance = Some(normalize_amount(&strip_currency_symbols(value), ','));
+        } else if key == "Datum" {
+            metadata.balance_date = parse_date(value);
+        } else if key == "Von" {
+            metadata.period_start = parse_date(value);
+        } else if key == "Bis" {
+            metadata.period_end = parse_date(value);
+        } else if key == "Zeitraum" || key == "Period" {
+            if let Some((start, end)) = value.split_once(" - ") {
+                metadata.period_start = parse_date(start);
+                metadata.period_end = parse_date(end);
+            }
+        } else if let Some(date) = key
+            .strip_prefix("Kontostand vom ")
+            .or_else(|| key.strip_prefix("Balance as of "))
+        {
+            metadata.balance_date = parse_date(date);
+            metadata.balance = Some(normalize_amount(&strip_currency_symbols(value), ','));
+        }
+    }
+    metadata
+}
+
+/// Extract the amount from a DKB memo
+///
+/// # Arguments
+///
+/// * `currency`: 3-letter currency code
+/// * `memo`: The memo or description of the transaction
+///
+/// returns: `Option<String>`
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::banks::dkb::dkb_extract_amount;
+/// assert_eq!(dkb_extract_amount("BRL", "2023-12-12      Debitk.44 Original 6,99 BRL 1 Euro=5,29545460 BRL VISA Debit"), Some("6,99".to_string()));
+/// assert_eq!(dkb_extract_amount("BRL", "Nothing here"), None);
+/// assert_eq!(dkb_extract_amount("BRL", "VISA Debitkartenumsatz in Fremdwährung / Ursprungsbetrag in Fremdwährung 19,90 BRL / Umrechnungsrate: 1 Euro=6,03030470 BRL"), Some("19,90".to_string()));
+pub fn dkb_extract_amount(currency: &str, memo: &str) -> Option<String> {
+    if !memo.contains(" 1 Euro=") {
+        return None;
+    }
+
+    let original_keyword = "Original ";
+    let fremdwaehrung = "Ursprungsbetrag in Fremdwährung ";
+    let start;
+    let word_length;
+    if memo.contains(original_keyword) {
+        start = memo.find(original_keyword)?;
+        word_length = original_keyword.len();
+    } else if memo.contains(fremdwaehrung) {
+        start = memo.find(fremdwaehrung)?;
+        word_length = fremdwaehrung.len();
+    } else {
+        eprintln!(
+            "{}",
+            format!("Could not extract amount from DKB memo: {}", memo).yellow()
+        );
+        return None;
+    }
+
+    let end = memo.find(currency)?;
+    if end <= start {
+        return None;
+    }
+
+    let amount_start = start + word_length;
+    let amount = &memo[amount_start..end].trim();
+
+    Some(amount.to_string())
+}
+
+/// Extract the currency conversion of a DKB card payment in a foreign currency from its memo
+///
+/// returns: `Option<(String, String, String)>` with the original amount (without sign),
+/// its currency and the exchange rate (foreign currency per euro)
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::banks::dkb::dkb_conversion;
+/// let conversion = |amount: &str, currency: &str, rate: &str| {
+///     Some((amount.to_string(), currency.to_string(), rate.to_string()))
+/// };
+/// assert_eq!(
+///     dkb_conversion("2023-12-12      Debitk.44 Original 6,99 BRL 1 Euro=5,29545460 BRL VISA Debit"),
+///     conversion("6,99", "BRL", "5,29545460")
+/// );
+/// assert_eq!(
+///     dkb_conversion("VISA Debitkartenumsatz in Fremdwährung / Ursprungsbetrag in Fremdwährung 19,90 BRL / Umrechnungsrate: 1 Euro=6,03030470 BRL"),
+///     conversion("19,90", "BRL", "6,03030470")
+/// );
+/// assert_eq!(dkb_conversion("Miete Januar"), None);
+/// ```
+pub fn dkb_conversion(memo: &str) -> Option<(String, String, String)> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(
+            r"(?:Original|Ursprungsbetrag in Fremdwährung) ([\d.,]+) ([A-Z]{3}).*? 1 Euro=([\d.,]+) ([A-Z]{3})",
+        )
+        .unwrap()
+    });
+    let captures = pattern.captures(memo)?;
+    Some((
+        captures[1].to_string(),
+        captures[2].to_string(),
+        captures[3].to_string(),
+    ))
+}
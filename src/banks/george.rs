@@ -0,0 +1,49 @@
+//! George, the online banking of Erste Bank and the Austrian Sparkassen
+use crate::sources::SourceSpec;
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+
+const GEORGE_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Buchungsdatum",
+    "Partnername",
+    "Partner IBAN",
+    "BIC/SWIFT",
+    "Partner Kontonummer",
+];
+
+/// George (Erste Bank, Sparkasse) export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct George;
+
+impl SourceSpec for George {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::George)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &GEORGE_COLUMNS
+    }
+
+    // No transaction type; the booking info is the memo
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Buchungsdatum",
+            "Währung",
+            "Betrag",
+            "",
+            "Partnername",
+            "Buchungs-Info",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d.%m.%Y"]
+    }
+
+    fn value_date_column(&self) -> Option<&str> {
+        Some("Valutadatum")
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("Währung")
+    }
+}
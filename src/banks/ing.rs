@@ -0,0 +1,49 @@
+//! ING (Germany), with the account details above the header
+use crate::sources::SourceSpec;
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+
+/// The amount has a second "Währung" column after it, with the same currency
+const ING_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Buchung",
+    "Valuta",
+    "Auftraggeber/Empfänger",
+    "Buchungstext",
+    "Verwendungszweck",
+];
+
+/// ING Germany export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ing;
+
+impl SourceSpec for Ing {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Ing)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &ING_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Buchung",
+            "Währung",
+            "Betrag",
+            "Buchungstext",
+            "Auftraggeber/Empfänger",
+            "Verwendungszweck",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d.%m.%Y"]
+    }
+
+    fn value_date_column(&self) -> Option<&str> {
+        Some("Valuta")
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("Währung")
+    }
+}
@@ -0,0 +1,61 @@
+//! ING (Netherlands); only euro accounts
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+
+/// Amounts are without a sign; the "Af Bij" column tells debits ("Af") from credits ("Bij")
+const ING_NL_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Datum",
+    "Naam / Omschrijving",
+    "Rekening",
+    "Tegenrekening",
+    "Code",
+];
+
+/// ING Netherlands export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IngNl;
+
+impl SourceSpec for IngNl {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::IngNl)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &ING_NL_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Datum",
+            // No currency column; only euros, other currencies are dropped later
+            "",
+            "Bedrag (EUR)",
+            "Mutatiesoort",
+            "Naam / Omschrijving",
+            "Mededelingen",
+            // The sign of the amount
+            "Af Bij",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%Y%m%d"]
+    }
+
+    // Only euros; debits are in the "Af Bij" column, next to an amount without a sign
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        if upper_currency != "EUR" {
+            return Ok(false);
+        }
+        row.currency = upper_currency.to_string();
+        if row.extra.first().is_some_and(|sign| sign == "Af") {
+            row.amount = format!("-{}", row.amount);
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,107 @@
+//! Kraken crypto exchange ledgers; the asset (EUR, BTC, ...) is used as the currency
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+use polars::prelude::*;
+
+const KRAKEN_COLUMNS: [&str; NUM_FIRST_COLUMNS] = ["txid", "refid", "time", "type", "subtype"];
+
+/// Kraken ledgers export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Kraken;
+
+impl SourceSpec for Kraken {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Kraken)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &KRAKEN_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec!["time", "asset", "amount", "type", "", "refid"]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M:%S%.f"]
+    }
+
+    fn currency_filter(&self, _column_names: &[&str], upper_currency: &str) -> Option<Expr> {
+        kraken_asset_codes(upper_currency)
+            .into_iter()
+            .map(|code| col("asset").eq(lit(code)))
+            .reduce(|left, right| left.or(right))
+    }
+
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        // Use the requested currency instead of Kraken's asset codes (ZEUR, XXBT, ...)
+        row.currency = upper_currency.to_string();
+        Ok(true)
+    }
+
+    fn memo_is_id(&self) -> bool {
+        true
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("asset")
+    }
+
+    fn currency_code(&self, value: &str) -> String {
+        kraken_currency(&value.trim().to_uppercase())
+    }
+}
+
+/// The asset codes used by Kraken for a currency
+///
+/// Kraken prefixes fiat currencies with "Z" and crypto currencies with "X" in older exports,
+/// and calls Bitcoin "XBT".
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::banks::kraken::kraken_asset_codes;
+/// assert_eq!(kraken_asset_codes("EUR"), vec!["EUR", "ZEUR", "XEUR"]);
+/// assert_eq!(kraken_asset_codes("BTC"), vec!["BTC", "ZBTC", "XBTC", "XBT", "XXBT"]);
+/// ```
+pub fn kraken_asset_codes(upper_currency: &str) -> Vec<String> {
+    let mut codes = vec![
+        upper_currency.to_string(),
+        format!("Z{}", upper_currency),
+        format!("X{}", upper_currency),
+    ];
+    if upper_currency == "BTC" {
+        codes.push("XBT".to_string());
+        codes.push("XXBT".to_string());
+    }
+    codes
+}
+
+/// The currency of a Kraken asset code, the reverse of [`kraken_asset_codes`]
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::banks::kraken::kraken_currency;
+/// assert_eq!(kraken_currency("ZEUR"), "EUR");
+/// assert_eq!(kraken_currency("XXBT"), "BTC");
+/// assert_eq!(kraken_currency("XETH"), "ETH");
+/// assert_eq!(kraken_currency("DOT"), "DOT");
+/// ```
+pub fn kraken_currency(asset_code: &str) -> String {
+    let code = asset_code.to_uppercase();
+    let code = match code.strip_prefix(['Z', 'X']) {
+        Some(stripped) if code.len() == 4 => stripped.to_string(),
+        _ => code,
+    };
+    if code == "XBT" {
+        "BTC".to_string()
+    } else {
+        code
+    }
+}
@@ -0,0 +1,74 @@
+//! The built-in formats: one [`crate::sources::SourceSpec`] per bank, with the layouts of its exports
+//!
+//! Each [`crate::CsvFormat`] is read with the spec of its bank (see [`crate::CsvFormat::spec`]), and all of them
+//! are registered in the default [`crate::sources::SourceRegistry`], which detects the format of the files.
+use crate::sources::SourceRow;
+use crate::NUM_FIRST_COLUMNS;
+use polars::prelude::*;
+
+pub mod amex;
+pub mod apple;
+pub mod boursorama;
+pub mod coinbase;
+pub mod comdirect;
+pub mod commerzbank;
+pub mod dkb;
+pub mod george;
+pub mod ing;
+pub mod ing_nl;
+pub mod kraken;
+pub mod monzo;
+pub mod n26;
+pub mod paypal;
+pub mod postfinance;
+pub mod revolut;
+pub mod sparkasse;
+pub mod starling;
+pub mod stripe;
+pub mod trade_republic;
+pub mod ubs;
+pub mod wise;
+
+/// Translate a localized header column by column, if it starts like one of the translations of `header`
+fn translate(
+    column_names: &[&str],
+    header: &[&str],
+    translations: &[&[&str]],
+) -> Option<Vec<String>> {
+    let localized = translations
+        .iter()
+        .find(|localized| column_names.starts_with(&localized[..NUM_FIRST_COLUMNS]))?;
+    Some(
+        column_names
+            .iter()
+            .map(|column| {
+                localized
+                    .iter()
+                    .position(|name| name == column)
+                    .map_or(column.to_string(), |index| header[index].to_string())
+            })
+            .collect(),
+    )
+}
+
+/// The amount with the opposite sign
+fn negate(amount: &str) -> String {
+    match amount.strip_prefix('-') {
+        Some(positive) => positive.to_string(),
+        None => format!("-{}", amount),
+    }
+}
+
+/// Rows without a credit are debits, in the first extra column, written with or without a minus sign
+fn debit_without_credit(row: &mut SourceRow) {
+    if row.amount.is_empty() {
+        let debit = row.extra.first().map_or("", String::as_str);
+        row.amount = format!("-{}", debit.trim_start_matches('-'));
+    }
+}
+
+/// Drop all rows unless the currency of the account (in the header) is the requested one
+fn account_currency_filter(account_currency: Option<&str>, upper_currency: &str) -> Option<Expr> {
+    (!account_currency.is_some_and(|currency| currency.eq_ignore_ascii_case(upper_currency)))
+        .then(|| lit(false))
+}
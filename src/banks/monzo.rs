@@ -0,0 +1,48 @@
+//! Monzo (UK); rows are read in their local currency, e.g. euros spent from a pound account
+use crate::sources::SourceSpec;
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+
+const MONZO_COLUMNS: [&str; NUM_FIRST_COLUMNS] = ["Transaction ID", "Date", "Time", "Type", "Name"];
+
+/// Monzo export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Monzo;
+
+impl SourceSpec for Monzo {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Monzo)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &MONZO_COLUMNS
+    }
+
+    // Local amounts are in the currency of the place, e.g. euros spent from a pound account
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Date",
+            "Local currency",
+            "Local amount",
+            "Type",
+            "Name",
+            "Description",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d/%m/%Y"]
+    }
+
+    // Moves between the account and its pots (savings)
+    fn is_internal_transfer(&self, transaction_type: &str, _memo: &str) -> bool {
+        transaction_type.eq_ignore_ascii_case("Pot transfer")
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("Local currency")
+    }
+
+    fn category_column(&self) -> Option<&str> {
+        Some("Category")
+    }
+}
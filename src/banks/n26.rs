@@ -0,0 +1,260 @@
+//! N26 exports, in the layouts used over the years and in the languages of the app
+use crate::banks::translate;
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+use regex::Regex;
+use std::sync::OnceLock;
+
+const N26_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Date",
+    "Payee",
+    "Account number",
+    "Transaction type",
+    "Payment reference",
+];
+/// Exports from before 2020 don't have the account number
+const N26_COLUMNS_LEGACY: [&str; NUM_FIRST_COLUMNS] = [
+    "Date",
+    "Payee",
+    "Transaction type",
+    "Payment reference",
+    "Category",
+];
+const N26_COLUMNS_2024_09: [&str; NUM_FIRST_COLUMNS] = [
+    "Booking Date",
+    "Value Date",
+    "Partner Name",
+    "Partner Iban",
+    "Type",
+];
+/// N26 Business (Mastercard) exports have the same columns as the personal ones, plus cashback
+const N26_BUSINESS_COLUMNS: [&str; 12] = [
+    "Booking Date",
+    "Value Date",
+    "Partner Name",
+    "Partner Iban",
+    "Type",
+    "Payment Reference",
+    "Account Name",
+    "Amount (EUR)",
+    "Original Amount",
+    "Original Currency",
+    "Exchange Rate",
+    "Cashback (EUR)",
+];
+/// Full English N26 headers, used to translate localized ones
+const N26_HEADER: [&str; 10] = [
+    "Date",
+    "Payee",
+    "Account number",
+    "Transaction type",
+    "Payment reference",
+    "Category",
+    "Amount (EUR)",
+    "Amount (Foreign Currency)",
+    "Type Foreign Currency",
+    "Exchange Rate",
+];
+const N26_HEADER_DE: [&str; 10] = [
+    "Datum",
+    "Empfänger",
+    "Kontonummer",
+    "Transaktionstyp",
+    "Verwendungszweck",
+    "Kategorie",
+    "Betrag (EUR)",
+    "Betrag (Fremdwährung)",
+    "Fremdwährung",
+    "Wechselkurs",
+];
+const N26_HEADER_ES: [&str; 10] = [
+    "Fecha",
+    "Beneficiario",
+    "Número de cuenta",
+    "Tipo de transacción",
+    "Referencia de pago",
+    "Categoría",
+    "Cantidad (EUR)",
+    "Cantidad (Divisa extranjera)",
+    "Tipo de divisa extranjera",
+    "Tipo de cambio",
+];
+const N26_HEADER_2024_09: [&str; 11] = [
+    "Booking Date",
+    "Value Date",
+    "Partner Name",
+    "Partner Iban",
+    "Type",
+    "Payment Reference",
+    "Account Name",
+    "Amount (EUR)",
+    "Original Amount",
+    "Original Currency",
+    "Exchange Rate",
+];
+const N26_HEADER_2024_09_DE: [&str; 11] = [
+    "Buchungsdatum",
+    "Wertstellungsdatum",
+    "Name des Partners",
+    "IBAN des Partners",
+    "Typ",
+    "Verwendungszweck",
+    "Kontoname",
+    "Betrag (EUR)",
+    "Ursprünglicher Betrag",
+    "Ursprüngliche Währung",
+    "Wechselkurs",
+];
+const N26_HEADER_2024_09_ES: [&str; 11] = [
+    "Fecha de reserva",
+    "Fecha valor",
+    "Nombre del socio",
+    "IBAN del socio",
+    "Tipo",
+    "Referencia de pago",
+    "Nombre de la cuenta",
+    "Importe (EUR)",
+    "Importe original",
+    "Divisa original",
+    "Tipo de cambio",
+];
+
+/// The layouts of N26 exports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum N26 {
+    /// The layout used until September 2024
+    Classic,
+    /// The layout from before 2020, without the account number column
+    Legacy,
+    /// The layout used since September 2024
+    Sep2024,
+    /// N26 Business (Mastercard), the layout of September 2024 with cashback columns
+    Business,
+}
+
+impl SourceSpec for N26 {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(match self {
+            N26::Classic => CsvFormat::N26,
+            N26::Legacy => CsvFormat::N26Legacy,
+            N26::Sep2024 => CsvFormat::N26Sep2024,
+            N26::Business => CsvFormat::N26Business,
+        })
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        match self {
+            N26::Classic => &N26_COLUMNS,
+            N26::Legacy => &N26_COLUMNS_LEGACY,
+            N26::Sep2024 => &N26_COLUMNS_2024_09,
+            N26::Business => &N26_BUSINESS_COLUMNS,
+        }
+    }
+
+    fn translate_header(&self, column_names: &[&str]) -> Option<Vec<String>> {
+        match self {
+            N26::Classic => translate(column_names, &N26_HEADER, &[&N26_HEADER_DE, &N26_HEADER_ES]),
+            N26::Sep2024 => translate(
+                column_names,
+                &N26_HEADER_2024_09,
+                &[&N26_HEADER_2024_09_DE, &N26_HEADER_2024_09_ES],
+            ),
+            N26::Legacy | N26::Business => None,
+        }
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], upper_currency: &str) -> Vec<&'a str> {
+        match self {
+            N26::Classic | N26::Legacy => vec![
+                "Date",
+                "Type Foreign Currency",
+                if upper_currency == "EUR" {
+                    "Amount (EUR)"
+                } else {
+                    "Amount (Foreign Currency)"
+                },
+                "Transaction type",
+                "Payee",
+                "Payment reference",
+            ],
+            N26::Sep2024 | N26::Business => vec![
+                "Booking Date",
+                "Original Currency",
+                if upper_currency == "EUR" {
+                    "Amount (EUR)"
+                } else {
+                    "Original Amount"
+                },
+                "Type",
+                "Partner Name",
+                "Payment Reference",
+            ],
+        }
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%Y-%m-%d"]
+    }
+
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        _upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        // The new file format doesn't seem to have negative amounts anymore,
+        // but different transaction types instead, e.g. A refund is "Presentment Refund"
+        if row.transaction_type == "Presentment" {
+            row.amount = format!("-{}", row.amount);
+        }
+        Ok(true)
+    }
+
+    fn is_internal_transfer(&self, transaction_type: &str, memo: &str) -> bool {
+        is_n26_space_transfer(transaction_type, memo)
+    }
+
+    fn value_date_column(&self) -> Option<&str> {
+        match self {
+            N26::Sep2024 | N26::Business => Some("Value Date"),
+            N26::Classic | N26::Legacy => None,
+        }
+    }
+
+    // Rows in euros can have an empty currency (N26 is not consistent); they are read as euros by default
+    fn currency_column(&self) -> Option<&str> {
+        match self {
+            N26::Classic | N26::Legacy => Some("Type Foreign Currency"),
+            N26::Sep2024 | N26::Business => Some("Original Currency"),
+        }
+    }
+
+    fn account_column(&self) -> Option<&str> {
+        match self {
+            N26::Sep2024 | N26::Business => Some("Account Name"),
+            N26::Classic | N26::Legacy => None,
+        }
+    }
+}
+
+/// Check if an N26 transaction moves money between the main account and a Space (a sub-account):
+/// either its type mentions Spaces, or its memo is like "From Main Account to Savings" (in English or German)
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::banks::n26::is_n26_space_transfer;
+/// assert!(is_n26_space_transfer("Spaces transaction", ""));
+/// assert!(is_n26_space_transfer("Outgoing Transfer", "From Main Account to Savings"));
+/// assert!(is_n26_space_transfer("Income", "Von Urlaub nach Hauptkonto"));
+/// assert!(!is_n26_space_transfer("Outgoing Transfer", "Rent for the main account holder"));
+/// ```
+pub fn is_n26_space_transfer(transaction_type: &str, memo: &str) -> bool {
+    static MEMO_PATTERN: OnceLock<Regex> = OnceLock::new();
+    let memo_pattern =
+        MEMO_PATTERN.get_or_init(|| Regex::new(r"(?i)^\s*(from|von) .+ (to|nach|zu) .+$").unwrap());
+    let memo_lower = memo.to_lowercase();
+    transaction_type.to_lowercase().contains("space")
+        || (memo_pattern.is_match(memo)
+            && (memo_lower.contains("main account") || memo_lower.contains("hauptkonto")))
+}
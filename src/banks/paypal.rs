@@ -0,0 +1,156 @@
+//! PayPal activity reports; PayPal has changed the CSV format at least once
+use crate::banks::translate;
+use crate::sources::SourceSpec;
+use crate::{any_of, BalanceImpact, CsvFormat, NUM_FIRST_COLUMNS};
+use polars::prelude::*;
+
+/// Values of localized PayPal exports used in filters: balance impacts and currency conversions
+pub(crate) const PAYPAL_DEBIT: [&str; 2] = ["Debit", "Soll"];
+pub(crate) const PAYPAL_CREDIT: [&str; 2] = ["Credit", "Haben"];
+pub(crate) const PAYPAL_MEMO: [&str; 1] = ["Memo"];
+pub(crate) const PAYPAL_CURRENCY_CONVERSION: [&str; 2] = [
+    "General Currency Conversion",
+    "Allgemeine Währungsumrechnung",
+];
+const PAYPAL_COLUMNS: [&str; NUM_FIRST_COLUMNS] = ["Date", "Time", "TimeZone", "Name", "Type"];
+const PAYPAL_COLUMNS_OLD: [&str; NUM_FIRST_COLUMNS] =
+    ["Date", "Time", "Time Zone", "Description", "Currency"];
+/// PayPal columns that are read, after the first ones; used to translate localized headers
+const PAYPAL_HEADER: [&str; 9] = [
+    "Date",
+    "Time",
+    "TimeZone",
+    "Name",
+    "Type",
+    "Currency",
+    "Gross",
+    "Balance Impact",
+    "Transaction ID",
+];
+const PAYPAL_HEADER_OLD: [&str; 8] = [
+    "Date",
+    "Time",
+    "Time Zone",
+    "Description",
+    "Currency",
+    "Gross",
+    "Name",
+    "Transaction ID",
+];
+/// Exports are detected in English; the headers in German are translated column by column
+const PAYPAL_HEADER_DE: [&str; 9] = [
+    "Datum",
+    "Uhrzeit",
+    "Zeitzone",
+    "Name",
+    "Typ",
+    "Währung",
+    "Brutto",
+    "Auswirkung auf Guthaben",
+    "Transaktionscode",
+];
+const PAYPAL_HEADER_OLD_DE: [&str; 8] = [
+    "Datum",
+    "Uhrzeit",
+    "Zeitzone",
+    "Beschreibung",
+    "Währung",
+    "Brutto",
+    "Name",
+    "Transaktionscode",
+];
+
+/// The layouts of PayPal activity reports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayPal {
+    /// The current layout, with the balance impact of each row
+    Current,
+    /// The old layout, with a description instead of a type
+    Old,
+}
+
+impl SourceSpec for PayPal {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(match self {
+            PayPal::Current => CsvFormat::PayPal,
+            PayPal::Old => CsvFormat::PayPalOld,
+        })
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        match self {
+            PayPal::Current => &PAYPAL_COLUMNS,
+            PayPal::Old => &PAYPAL_COLUMNS_OLD,
+        }
+    }
+
+    fn translate_header(&self, column_names: &[&str]) -> Option<Vec<String>> {
+        match self {
+            PayPal::Current => translate(column_names, &PAYPAL_HEADER, &[&PAYPAL_HEADER_DE]),
+            PayPal::Old => translate(column_names, &PAYPAL_HEADER_OLD, &[&PAYPAL_HEADER_OLD_DE]),
+        }
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        let type_column = match self {
+            PayPal::Current => "Type",
+            PayPal::Old => "Description",
+        };
+        vec![
+            "Date",
+            "Currency",
+            "Gross",
+            type_column,
+            "Name",
+            "Transaction ID",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d/%m/%Y", "%d.%m.%Y", "%m/%d/%Y"]
+    }
+
+    fn row_filter(
+        &self,
+        column_names: &[&str],
+        upper_currency: &str,
+        balance_impact: &[BalanceImpact],
+    ) -> Option<Expr> {
+        let currency_is = self.currency_filter(column_names, upper_currency)?;
+        match self {
+            PayPal::Current => {
+                let balance_impact_values: Vec<&str> = if balance_impact.is_empty() {
+                    PAYPAL_DEBIT.to_vec()
+                } else {
+                    balance_impact
+                        .iter()
+                        .flat_map(|impact| impact.values().iter().copied())
+                        .collect()
+                };
+                Some(
+                    currency_is
+                        .and(any_of("Balance Impact", &balance_impact_values))
+                        .and(any_of("Type", &PAYPAL_CURRENCY_CONVERSION).not()),
+                )
+            }
+            PayPal::Old => {
+                Some(currency_is.and(any_of("Description", &PAYPAL_CURRENCY_CONVERSION).not()))
+            }
+        }
+    }
+
+    fn memo_is_id(&self) -> bool {
+        true
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("Currency")
+    }
+
+    fn time_columns(&self) -> Option<(&str, &str)> {
+        match self {
+            PayPal::Current => Some(("Time", "TimeZone")),
+            PayPal::Old => Some(("Time", "Time Zone")),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! PostFinance (Switzerland); credits and debits are in separate columns, named after the currency of the account
+use crate::banks::{account_currency_filter, debit_without_credit};
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::CsvFormat;
+use polars::prelude::*;
+
+/// The amounts are in the "Gutschrift in CHF" and "Lastschrift in CHF" columns, named after the currency of the account
+const POSTFINANCE_COLUMNS: [&str; 3] = ["Datum", "Bewegungstyp", "Avisierungstext"];
+
+/// PostFinance e-finance export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PostFinance;
+
+impl SourceSpec for PostFinance {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::PostFinance)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &POSTFINANCE_COLUMNS
+    }
+
+    // Credits are selected as the amount, and debits after the usual columns.
+    // No currency column; it's taken from the amount columns, and other currencies are dropped
+    fn columns<'a>(&self, column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        let starting_with = |prefix: &str| {
+            column_names
+                .iter()
+                .find(|column| column.starts_with(prefix))
+                .copied()
+                .unwrap_or_default()
+        };
+        vec![
+            "Datum",
+            "",
+            starting_with("Gutschrift in "),
+            "Bewegungstyp",
+            "",
+            "Avisierungstext",
+            starting_with("Lastschrift in "),
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d.%m.%Y", "%Y-%m-%d"]
+    }
+
+    // Each export has the currency of its account, in the name of the amount columns
+    fn currency_filter(&self, column_names: &[&str], upper_currency: &str) -> Option<Expr> {
+        account_currency_filter(self.header_currency(column_names), upper_currency)
+    }
+
+    // The rows were already filtered by the currency in the header
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        row.currency = upper_currency.to_string();
+        debit_without_credit(row);
+        Ok(true)
+    }
+
+    fn header_currency<'a>(&self, column_names: &[&'a str]) -> Option<&'a str> {
+        column_names
+            .iter()
+            .find_map(|column| column.strip_prefix("Gutschrift in "))
+    }
+
+    fn category_column(&self) -> Option<&str> {
+        Some("Kategorie")
+    }
+}
@@ -0,0 +1,63 @@
+//! Revolut, with the accounts of all currencies in one statement
+use crate::sources::SourceSpec;
+use crate::{BalanceImpact, CsvFormat, NUM_FIRST_COLUMNS};
+use polars::prelude::*;
+
+/// Amounts are without the fee, which is in its own column
+const REVOLUT_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Type",
+    "Product",
+    "Started Date",
+    "Completed Date",
+    "Description",
+];
+
+/// Revolut statement
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Revolut;
+
+impl SourceSpec for Revolut {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Revolut)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &REVOLUT_COLUMNS
+    }
+
+    // The product (current account, savings) is the memo
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Completed Date",
+            "Currency",
+            "Amount",
+            "Type",
+            "Description",
+            "Product",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+    }
+
+    // Pending, reverted, declined and failed transactions didn't move any money
+    fn row_filter(
+        &self,
+        column_names: &[&str],
+        upper_currency: &str,
+        _balance_impact: &[BalanceImpact],
+    ) -> Option<Expr> {
+        self.currency_filter(column_names, upper_currency)
+            .map(|currency_is| currency_is.and(col("State").eq(lit("COMPLETED"))))
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("Currency")
+    }
+
+    // The fee is written as a separate expense
+    fn fee_column(&self) -> Option<&str> {
+        Some("Fee")
+    }
+}
@@ -0,0 +1,49 @@
+//! Sparkasse (Germany)
+use crate::sources::SourceSpec;
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+
+/// Sparkasse "CSV-CAMT" export; the column names are written without umlauts ("Waehrung")
+const SPARKASSE_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Auftragskonto",
+    "Buchungstag",
+    "Valutadatum",
+    "Buchungstext",
+    "Verwendungszweck",
+];
+
+/// Sparkasse export in the CAMT layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sparkasse;
+
+impl SourceSpec for Sparkasse {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Sparkasse)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &SPARKASSE_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Buchungstag",
+            "Waehrung",
+            "Betrag",
+            "Buchungstext",
+            "Beguenstigter/Zahlungspflichtiger",
+            "Verwendungszweck",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d.%m.%y", "%d.%m.%Y"]
+    }
+
+    fn value_date_column(&self) -> Option<&str> {
+        Some("Valutadatum")
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("Waehrung")
+    }
+}
@@ -0,0 +1,71 @@
+//! Starling (UK); each file has the currency of its account
+use crate::banks::account_currency_filter;
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::CsvFormat;
+use polars::prelude::*;
+
+/// The next column is the amount, named after the currency of the account, e.g. "Amount (GBP)"
+const STARLING_COLUMNS: [&str; 4] = ["Date", "Counter Party", "Reference", "Type"];
+
+/// Starling statement export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Starling;
+
+impl SourceSpec for Starling {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Starling)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &STARLING_COLUMNS
+    }
+
+    fn columns<'a>(&self, column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Date",
+            // No currency column; it's taken from the amount column, and other currencies are dropped
+            "",
+            // The amount column is named after the currency of the account
+            column_names
+                .iter()
+                .find(|column| column.starts_with("Amount ("))
+                .copied()
+                .unwrap_or_default(),
+            "Type",
+            "Counter Party",
+            "Reference",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d/%m/%Y"]
+    }
+
+    // Each export has the currency of its account, in the name of the amount column
+    fn currency_filter(&self, column_names: &[&str], upper_currency: &str) -> Option<Expr> {
+        account_currency_filter(self.header_currency(column_names), upper_currency)
+    }
+
+    // The rows were already filtered by the currency in the header
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        row.currency = upper_currency.to_string();
+        Ok(true)
+    }
+
+    fn header_currency<'a>(&self, column_names: &[&'a str]) -> Option<&'a str> {
+        column_names.iter().find_map(|column| {
+            column
+                .strip_prefix("Amount (")
+                .and_then(|rest| rest.strip_suffix(')'))
+        })
+    }
+
+    fn category_column(&self) -> Option<&str> {
+        Some("Spending Category")
+    }
+}
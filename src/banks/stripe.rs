@@ -0,0 +1,60 @@
+//! Stripe balance reports; fees are written as separate transactions
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+use polars::prelude::*;
+
+const STRIPE_COLUMNS: [&str; NUM_FIRST_COLUMNS] = ["id", "type", "amount", "fee", "net"];
+
+/// Stripe balance report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stripe;
+
+impl SourceSpec for Stripe {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Stripe)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &STRIPE_COLUMNS
+    }
+
+    // Payee is empty: balance transactions are payments, payouts and fees of the account itself
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec!["available_on", "currency", "amount", "type", "", "id"]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S"]
+    }
+
+    // Stripe writes currencies in lowercase
+    fn currency_filter(&self, _column_names: &[&str], upper_currency: &str) -> Option<Expr> {
+        Some(
+            col("currency")
+                .eq(lit(upper_currency))
+                .or(col("currency").eq(lit(upper_currency.to_lowercase()))),
+        )
+    }
+
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        row.currency = upper_currency.to_string();
+        Ok(true)
+    }
+
+    fn memo_is_id(&self) -> bool {
+        true
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("currency")
+    }
+
+    fn fee_column(&self) -> Option<&str> {
+        Some("fee")
+    }
+}
@@ -0,0 +1,52 @@
+//! Trade Republic (securities)
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{securities_type, CsvFormat, NUM_FIRST_COLUMNS};
+
+/// Transactions exported by pytr (the Trade Republic command line client), in English
+const TRADE_REPUBLIC_COLUMNS: [&str; NUM_FIRST_COLUMNS] = ["Date", "Type", "Value", "Note", "ISIN"];
+
+/// Trade Republic transactions exported by pytr, only read in securities mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TradeRepublic;
+
+impl SourceSpec for TradeRepublic {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::TradeRepublic)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &TRADE_REPUBLIC_COLUMNS
+    }
+
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Date", // No currency column; only euros, other currencies are dropped later
+            "", "Value", "Type", "Note", "ISIN",
+            // Extra columns for the securities mode
+            "Shares", "Fees",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%Y-%m-%d", "%Y-%m-%dT%H:%M:%S"]
+    }
+
+    // Only euros; buys, sells and dividends have the same type in all brokerages
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        if upper_currency != "EUR" {
+            return Ok(false);
+        }
+        row.currency = upper_currency.to_string();
+        row.transaction_type = securities_type(&row.transaction_type, &row.amount);
+        Ok(true)
+    }
+
+    fn is_securities(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,61 @@
+//! UBS (Switzerland); credits and debits are in separate columns
+use crate::banks::debit_without_credit;
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+
+const UBS_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "Abschlussdatum",
+    "Abschlusszeit",
+    "Buchungsdatum",
+    "Valutadatum",
+    "Währung",
+];
+
+/// UBS e-banking export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ubs;
+
+impl SourceSpec for Ubs {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Ubs)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &UBS_COLUMNS
+    }
+
+    // Credits are selected as the amount, and debits after the usual columns
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Buchungsdatum",
+            "Währung",
+            "Gutschrift",
+            "",
+            "Beschreibung1",
+            "Beschreibung2",
+            "Belastung",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%Y-%m-%d", "%d.%m.%Y"]
+    }
+
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        _upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        debit_without_credit(row);
+        Ok(true)
+    }
+
+    fn value_date_column(&self) -> Option<&str> {
+        Some("Valutadatum")
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("Währung")
+    }
+}
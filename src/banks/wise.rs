@@ -0,0 +1,87 @@
+//! Wise (formerly TransferWise)
+use crate::error::BankCsvError;
+use crate::sources::{SourceRow, SourceSpec};
+use crate::{CsvFormat, NUM_FIRST_COLUMNS};
+
+/// Type of Wise conversions between the balances of the account, see [`wise_transaction_type`]
+const WISE_CONVERSION: &str = "BALANCE";
+/// The type of each row is the prefix of its ID, see [`wise_transaction_type`]
+const WISE_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
+    "TransferWise ID",
+    "Date",
+    "Amount",
+    "Currency",
+    "Description",
+];
+
+/// Wise account statement
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Wise;
+
+impl SourceSpec for Wise {
+    fn format(&self) -> Option<CsvFormat> {
+        Some(CsvFormat::Wise)
+    }
+
+    fn first_columns(&self) -> &[&str] {
+        &WISE_COLUMNS
+    }
+
+    // Card payments have a merchant and incoming transfers a payer, used when there is no payee
+    fn columns<'a>(&self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        vec![
+            "Date",
+            "Currency",
+            "Amount",
+            "TransferWise ID",
+            "Payee Name",
+            "Description",
+            "Merchant",
+            "Payer Name",
+        ]
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        vec!["%d-%m-%Y", "%d-%m-%Y %H:%M:%S"]
+    }
+
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        _upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        // Like PayPal's currency conversions, conversions between balances are not expenses
+        row.transaction_type = wise_transaction_type(&row.transaction_type).to_string();
+        if row.transaction_type == WISE_CONVERSION {
+            return Ok(false);
+        }
+        if row.payee.is_empty() {
+            row.payee = row
+                .extra
+                .iter()
+                .find(|name| !name.is_empty())
+                .cloned()
+                .unwrap_or_default();
+        }
+        Ok(true)
+    }
+
+    fn currency_column(&self) -> Option<&str> {
+        Some("Currency")
+    }
+}
+
+/// The type of a Wise transaction, from the prefix of its ID; conversions between the balances
+/// of the account are "BALANCE"
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::banks::wise::wise_transaction_type;
+/// assert_eq!(wise_transaction_type("CARD-123456789"), "CARD");
+/// assert_eq!(wise_transaction_type("BALANCE_CASHBACK-42"), "BALANCE_CASHBACK");
+/// assert_eq!(wise_transaction_type("42"), "42");
+/// ```
+pub fn wise_transaction_type(id: &str) -> &str {
+    id.rsplit_once('-').map_or(id, |(prefix, _)| prefix)
+}
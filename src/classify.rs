@@ -0,0 +1,142 @@
+//! Suggest categories for new transactions, learned from the categories of previous output files
+//!
+//! The classifier is a naive Bayes over the words of the payee and memo. It learns from the
+//! `Normalized Type` column of the monthly files, so categories corrected by hand in these files
+//! are learned as well.
+use crate::{CsvOutputRow, ExtraColumn, OTHER_TYPE};
+use std::collections::{HashMap, HashSet};
+
+/// Word counts per category, learned from categorized transactions
+#[derive(Clone, Debug, Default)]
+pub struct Classifier {
+    /// Number of transactions per category
+    transactions: HashMap<String, usize>,
+    /// Number of occurrences of each word per category
+    words: HashMap<String, HashMap<String, usize>>,
+    /// Total number of words per category
+    word_totals: HashMap<String, usize>,
+    /// All words seen in any category
+    vocabulary: HashSet<String>,
+}
+
+/// Lowercase words of the payee and memo of a transaction, without numbers and single letters
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::classify::words;
+/// use bank_csv::CsvOutputRow;
+/// use chrono::NaiveDate;
+/// let transaction = CsvOutputRow::builder(NaiveDate::from_ymd_opt(2024, 7, 1).unwrap())
+///     .payee("REWE Markt GmbH")
+///     .memo("Kartenzahlung 2024-07-01 a 4711")
+///     .build();
+/// assert_eq!(words(&transaction), ["rewe", "markt", "gmbh", "kartenzahlung"]);
+/// ```
+pub fn words(transaction: &CsvOutputRow) -> Vec<String> {
+    [&transaction.payee, &transaction.memo]
+        .into_iter()
+        .flat_map(|text| text.split(|char: char| !char.is_alphanumeric()))
+        .filter(|word| word.chars().count() > 1 && !word.chars().all(|char| char.is_numeric()))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+impl Classifier {
+    /// Learn from transactions with a category; uncategorized ones (`other` or no category) are ignored
+    pub fn train<'a>(transactions: impl IntoIterator<Item = &'a CsvOutputRow>) -> Self {
+        let mut classifier = Self::default();
+        for transaction in transactions {
+            if let Some(category) = transaction.extra.get(&ExtraColumn::NormalizedType) {
+                if !category.is_empty() && category != OTHER_TYPE {
+                    classifier.learn(&words(transaction), category);
+                }
+            }
+        }
+        classifier
+    }
+
+    /// Learn the words of one transaction in a category
+    pub fn learn(&mut self, words: &[String], category: &str) {
+        *self.transactions.entry(category.to_string()).or_default() += 1;
+        let counts = self.words.entry(category.to_string()).or_default();
+        for word in words {
+            *counts.entry(word.clone()).or_default() += 1;
+            self.vocabulary.insert(word.clone());
+        }
+        *self.word_totals.entry(category.to_string()).or_default() += words.len();
+    }
+
+    /// Check if nothing was learned, so there is nothing to suggest
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// The most likely category of a transaction and its probability (between 0 and 1)
+    ///
+    /// Transactions without any known word have no suggestion.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::classify::Classifier;
+    /// use bank_csv::{CsvOutputRow, ExtraColumn};
+    /// use chrono::NaiveDate;
+    /// let date = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+    /// let row = |payee: &str, category: &str| {
+    ///     CsvOutputRow::builder(date)
+    ///         .payee(payee)
+    ///         .extra(ExtraColumn::NormalizedType, category)
+    ///         .build()
+    /// };
+    /// let classifier = Classifier::train(&[
+    ///     row("REWE Markt", "groceries"),
+    ///     row("Edeka Markt", "groceries"),
+    ///     row("Deutsche Bahn", "travel"),
+    /// ]);
+    /// let (category, confidence) = classifier.suggest(&row("REWE City", "")).unwrap();
+    /// assert_eq!(category, "groceries");
+    /// assert!(confidence > 0.5);
+    /// assert_eq!(classifier.suggest(&row("Bakery", "")), None);
+    /// ```
+    pub fn suggest(&self, transaction: &CsvOutputRow) -> Option<(String, f64)> {
+        let words: Vec<String> = words(transaction)
+            .into_iter()
+            .filter(|word| self.vocabulary.contains(word))
+            .collect();
+        if words.is_empty() {
+            return None;
+        }
+        let total: usize = self.transactions.values().sum();
+        let vocabulary_size = self.vocabulary.len() as f64;
+        // Log probabilities with Laplace smoothing, so unknown combinations don't rule out a category
+        let scores: Vec<(&String, f64)> = self
+            .transactions
+            .iter()
+            .map(|(category, count)| {
+                let counts = &self.words[category];
+                let word_total = self.word_totals[category] as f64;
+                let prior = (*count as f64 / total as f64).ln();
+                let likelihood: f64 = words
+                    .iter()
+                    .map(|word| {
+                        let count = counts.get(word).copied().unwrap_or_default() as f64;
+                        ((count + 1.0) / (word_total + vocabulary_size)).ln()
+                    })
+                    .sum();
+                (category, prior + likelihood)
+            })
+            .collect();
+        let (best_category, best_score) = scores
+            .iter()
+            .max_by(|(category_a, a), (category_b, b)| {
+                a.total_cmp(b).then_with(|| category_b.cmp(category_a))
+            })
+            .copied()?;
+        let sum: f64 = scores
+            .iter()
+            .map(|(_, score)| (score - best_score).exp())
+            .sum();
+        Some((best_category.clone(), 1.0 / sum))
+    }
+}
@@ -0,0 +1,153 @@
+//! Cloud storage (s3://, gs://, az://) as input and output, available with the `cloud` feature
+//!
+//! Credentials are read from the usual environment variables of each provider
+//! (`AWS_ACCESS_KEY_ID`, `GOOGLE_SERVICE_ACCOUNT`, `AZURE_STORAGE_ACCOUNT_NAME`, ...).
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "cloud")]
+use object_store::{
+    aws::AmazonS3Builder, azure::MicrosoftAzureBuilder, gcp::GoogleCloudStorageBuilder,
+    path::Path as ObjectPath, ObjectStore,
+};
+
+/// URL prefixes of the supported cloud storages
+const CLOUD_SCHEMES: [&str; 5] = ["s3://", "gs://", "gcs://", "az://", "azure://"];
+
+/// Check if a location is a cloud storage URL instead of a local path
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::cloud::is_cloud_url;
+/// assert!(is_cloud_url("s3://my-bucket/statements/n26.csv"));
+/// assert!(is_cloud_url("gcs://my-bucket/output"));
+/// assert!(!is_cloud_url("~/Downloads/n26.csv"));
+/// ```
+pub fn is_cloud_url(location: &str) -> bool {
+    CLOUD_SCHEMES
+        .iter()
+        .any(|scheme| location.starts_with(scheme))
+}
+
+/// Run a cloud storage operation to completion, outside of an async context
+#[cfg(feature = "cloud")]
+fn block_on<T>(
+    future: impl std::future::Future<Output = object_store::Result<T>>,
+) -> io::Result<T> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(future)
+        .map_err(io::Error::other)
+}
+
+/// Connect to the storage of a URL, configured from the environment
+#[cfg(feature = "cloud")]
+fn open(location: &str) -> io::Result<(Box<dyn ObjectStore>, ObjectPath)> {
+    // object_store only knows the gs:// scheme for Google Cloud Storage
+    let normalized = location.replacen("gcs://", "gs://", 1);
+    let url = url::Url::parse(&normalized).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {}", location, err),
+        )
+    })?;
+    let store: Box<dyn ObjectStore> = match url.scheme() {
+        "s3" => Box::new(
+            AmazonS3Builder::from_env()
+                .with_url(url.as_str())
+                .build()
+                .map_err(io::Error::other)?,
+        ),
+        "gs" => Box::new(
+            GoogleCloudStorageBuilder::from_env()
+                .with_url(url.as_str())
+                .build()
+                .map_err(io::Error::other)?,
+        ),
+        _ => Box::new(
+            MicrosoftAzureBuilder::from_env()
+                .with_url(url.as_str())
+                .build()
+                .map_err(io::Error::other)?,
+        ),
+    };
+    let path = ObjectPath::from_url_path(url.path()).map_err(io::Error::other)?;
+    Ok((store, path))
+}
+
+/// Download a file from cloud storage into a local directory, keeping its file name
+///
+/// # Arguments
+///
+/// * `location`: URL of the file, e.g. `s3://my-bucket/statements/n26.csv`
+/// * `target_dir`: local directory to download the file to, usually a temporary one
+///
+/// returns: Result<PathBuf, Error> with the path of the downloaded file
+#[cfg(feature = "cloud")]
+pub fn download(location: &str, target_dir: &Path) -> io::Result<PathBuf> {
+    let (store, path) = open(location)?;
+    let content = block_on(async { store.get(&path).await?.bytes().await })?;
+    let local_path = target_dir.join(path.filename().unwrap_or("download.csv"));
+    std::fs::write(&local_path, content)?;
+    Ok(local_path)
+}
+
+/// Upload a local file to a directory in cloud storage, keeping its file name
+///
+/// # Arguments
+///
+/// * `local_path`: the file to upload
+/// * `location_dir`: URL of the directory, e.g. `s3://my-bucket/bank`
+/// * `overwrite`: replace the file if it already exists; otherwise, it's kept
+///
+/// returns: Result<bool, Error>, false if the file already existed and was kept
+#[cfg(feature = "cloud")]
+pub fn upload(local_path: &Path, location_dir: &str, overwrite: bool) -> io::Result<bool> {
+    let file_name = local_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (store, path) = open(&format!(
+        "{}/{}",
+        location_dir.trim_end_matches('/'),
+        file_name
+    ))?;
+    let content = std::fs::read(local_path)?;
+    block_on(async {
+        if !overwrite {
+            match store.head(&path).await {
+                Ok(_) => return Ok(false),
+                Err(object_store::Error::NotFound { .. }) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        store.put(&path, content.into()).await?;
+        Ok(true)
+    })
+}
+
+/// Error returned when bank-csv was compiled without the `cloud` feature
+#[cfg(not(feature = "cloud"))]
+fn feature_disabled(location: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{}: cloud storage needs bank-csv installed with the `cloud` feature",
+            location
+        ),
+    )
+}
+
+/// Download a file from cloud storage; needs the `cloud` feature
+#[cfg(not(feature = "cloud"))]
+pub fn download(location: &str, _target_dir: &Path) -> io::Result<PathBuf> {
+    Err(feature_disabled(location))
+}
+
+/// Upload a local file to cloud storage; needs the `cloud` feature
+#[cfg(not(feature = "cloud"))]
+pub fn upload(_local_path: &Path, location_dir: &str, _overwrite: bool) -> io::Result<bool> {
+    Err(feature_disabled(location_dir))
+}
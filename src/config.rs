@@ -0,0 +1,209 @@
+//! Configuration file, used to set defaults that would otherwise be passed as command line flags
+use crate::split::SplitRule;
+use crate::tags::TagRule;
+use crate::{AmountTolerance, BalanceImpact, DateBasis, GroupBy, Locale};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Settings read from the TOML configuration file
+///
+/// Every setting is optional; command line flags and environment variables take precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Currency to filter when `--currency` is not given
+    pub currency: Option<String>,
+    /// Output directory when `--output-dir` is not given
+    pub output_dir: Option<PathBuf>,
+    /// Language of the output column names when `--locale` is not given
+    pub locale: Option<Locale>,
+    /// Date used to assign transactions to months when `--date-basis` is not given
+    pub date_basis: Option<DateBasis>,
+    /// Period of the output files when `--group-by` is not given
+    pub group_by: Option<GroupBy>,
+    /// Time zone of the dates and times with `--with-time`, when `--time-zone` is not given
+    pub time_zone: Option<String>,
+    /// PayPal rows to read by their balance impact when `--paypal-balance-impact` is not given
+    pub paypal_balance_impact: Option<Vec<BalanceImpact>>,
+    /// Templates for the memo column per source, by [`crate::source_key`] (e.g. `paypal` or `ing_nl`).
+    /// Placeholders are the output fields (`{memo}`, `{payee}`, `{type}`, ...)
+    /// or the columns of the original CSV file (`{Transaction ID}`).
+    pub memo_templates: HashMap<String, String>,
+    /// State file recording the latest exported date per source, when `--state-file` is not given
+    pub state_file: Option<PathBuf>,
+    /// History file where each merge run is appended, when `--history-file` is not given
+    pub history_file: Option<PathBuf>,
+    /// File with the preferred names of payees (see `bank-csv alias`).
+    /// Default: ~/.config/bank-csv/aliases.toml
+    pub aliases_file: Option<PathBuf>,
+    /// Directory with the descriptors of other bank formats, when `--formats-dir` is not given
+    pub formats_dir: Option<PathBuf>,
+    /// Rhai script applied to each transaction, when `--script` is not given
+    pub script: Option<PathBuf>,
+    /// Tolerance when matching amounts of the same transaction in `duplicates`, when `--tolerance` is not given.
+    /// Either an absolute value (`"0.05"`) or a percentage (`"1%"`).
+    pub amount_tolerance: Option<AmountTolerance>,
+    /// Regular expressions to extract invoice numbers from memos with `--invoice`, tried in order.
+    /// If a pattern has a capture group, only the group is used.
+    pub invoice_patterns: Vec<String>,
+    /// Secret key for `--pseudonymize`, when `--pseudonym-key` is not given
+    pub pseudonym_key: Option<String>,
+    /// Rules to split transactions into several rows; the first matching rule is applied
+    pub split_rules: Vec<SplitRule>,
+    /// Rules to rewrite payees with `--clean-payees`, tried in order before the built-in ones
+    pub payee_rules: Vec<PayeeRule>,
+    /// Rules to tag transactions with `--tags`; the tags of all matching rules are added
+    pub tag_rules: Vec<TagRule>,
+    /// Mappings of transaction types to canonical ones for `--normalize-types`,
+    /// added to (and taking precedence over) the built-in ones
+    pub type_mappings: BTreeMap<String, String>,
+    /// Rows to drop per source, by [`crate::source_key`] (e.g. `[sources.paypal]` or `[sources.ing_nl]`), on top of the built-in filters
+    pub sources: HashMap<String, SourceFilter>,
+    /// Named profiles selected with `--profile`, e.g. one per person or bookkeeping setup.
+    /// A profile has the same settings as the top level, which are used for anything it doesn't set
+    pub profiles: HashMap<String, Config>,
+}
+
+/// A rule to rewrite payees that match a regular expression
+#[derive(Clone, Debug, Deserialize)]
+pub struct PayeeRule {
+    /// Regular expression matched against the payee
+    pub pattern: String,
+    /// The new payee; capture groups of the pattern can be used as `$1`, `$2`, ...
+    pub replacement: String,
+}
+
+/// Rows of a source to drop when reading its files
+///
+/// Values are compared with the columns of the original file, exactly as they are written there.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceFilter {
+    /// Transaction types to drop, e.g. `["Account Hold", "Reserve Hold"]` for PayPal
+    pub exclude_types: Vec<String>,
+    /// Payees to drop, e.g. `["Main Account"]` for N26
+    pub exclude_payees: Vec<String>,
+}
+
+impl SourceFilter {
+    /// Whether a row with this type and payee is dropped; empty values are kept
+    pub fn excludes(&self, transaction_type: &str, payee: &str) -> bool {
+        let excluded = |values: &[String], value: &str| {
+            !value.is_empty() && values.iter().any(|excluded| excluded == value)
+        };
+        excluded(&self.exclude_types, transaction_type) || excluded(&self.exclude_payees, payee)
+    }
+}
+
+impl Config {
+    /// The default location of the configuration file: `~/.config/bank-csv/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".config").join("bank-csv").join("config.toml"))
+    }
+
+    /// Load the configuration from a TOML file
+    ///
+    /// # Arguments
+    ///
+    /// * `file_path`: path to the configuration file
+    ///
+    /// returns: Result<Config, Error>
+    pub fn load(file_path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(file_path)?;
+        toml::from_str(&content).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", file_path.display(), err),
+            )
+        })
+    }
+
+    /// Apply the settings of a profile on top of the top-level ones
+    ///
+    /// Single values of the profile replace the top-level ones; lists and tables
+    /// (rules, templates, mappings) replace them as a whole when the profile has any.
+    /// An unknown profile is an error.
+    pub fn with_profile(mut self, name: &str) -> io::Result<Self> {
+        let profile = self.profiles.remove(name).ok_or_else(|| {
+            let mut known: Vec<&String> = self.profiles.keys().collect();
+            known.sort();
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "unknown profile {:?}, the configuration has {:?}",
+                    name, known
+                ),
+            )
+        })?;
+        fn non_empty<T: IsEmpty>(profile: T, base: T) -> T {
+            if profile.is_empty() {
+                base
+            } else {
+                profile
+            }
+        }
+        Ok(Self {
+            currency: profile.currency.or(self.currency),
+            output_dir: profile.output_dir.or(self.output_dir),
+            locale: profile.locale.or(self.locale),
+            date_basis: profile.date_basis.or(self.date_basis),
+            time_zone: profile.time_zone.or(self.time_zone),
+            group_by: profile.group_by.or(self.group_by),
+            paypal_balance_impact: profile.paypal_balance_impact.or(self.paypal_balance_impact),
+            memo_templates: non_empty(profile.memo_templates, self.memo_templates),
+            state_file: profile.state_file.or(self.state_file),
+            history_file: profile.history_file.or(self.history_file),
+            aliases_file: profile.aliases_file.or(self.aliases_file),
+            formats_dir: profile.formats_dir.or(self.formats_dir),
+            script: profile.script.or(self.script),
+            amount_tolerance: profile.amount_tolerance.or(self.amount_tolerance),
+            invoice_patterns: non_empty(profile.invoice_patterns, self.invoice_patterns),
+            pseudonym_key: profile.pseudonym_key.or(self.pseudonym_key),
+            split_rules: non_empty(profile.split_rules, self.split_rules),
+            payee_rules: non_empty(profile.payee_rules, self.payee_rules),
+            tag_rules: non_empty(profile.tag_rules, self.tag_rules),
+            type_mappings: non_empty(profile.type_mappings, self.type_mappings),
+            sources: non_empty(profile.sources, self.sources),
+            profiles: HashMap::new(),
+        })
+    }
+
+    /// Load the configuration from an explicit path, or from the default path if it exists
+    ///
+    /// An explicit path that doesn't exist is an error; a missing default file is not.
+    pub fn load_or_default(file_path: Option<&Path>) -> io::Result<Self> {
+        match file_path {
+            Some(path) => Self::load(path),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => Self::load(&path),
+                _ => Ok(Self::default()),
+            },
+        }
+    }
+}
+
+/// Collections that a profile can leave empty to use the top-level ones
+trait IsEmpty {
+    fn is_empty(&self) -> bool;
+}
+
+impl<T> IsEmpty for Vec<T> {
+    fn is_empty(&self) -> bool {
+        Vec::is_empty(self)
+    }
+}
+
+impl<K, V> IsEmpty for HashMap<K, V> {
+    fn is_empty(&self) -> bool {
+        HashMap::is_empty(self)
+    }
+}
+
+impl<K, V> IsEmpty for BTreeMap<K, V> {
+    fn is_empty(&self) -> bool {
+        BTreeMap::is_empty(self)
+    }
+}
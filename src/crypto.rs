@@ -0,0 +1,84 @@
+//! Encryption of output files and decryption of input files with [age](https://age-encryption.org)
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use tempfile::NamedTempFile;
+
+/// Extension of files encrypted with age, added after `.csv`
+pub const AGE_EXTENSION: &str = "age";
+
+/// Check if a file is encrypted with age, by its extension
+pub fn is_encrypted(file_path: &Path) -> bool {
+    file_path
+        .extension()
+        .is_some_and(|extension| extension == AGE_EXTENSION)
+}
+
+/// Encrypt data to one or more age recipients (public keys starting with `age1`)
+///
+/// # Arguments
+///
+/// * `data`: the content to encrypt
+/// * `recipients`: public keys that will be able to decrypt the content
+///
+/// returns: Result<Vec<u8>, Error> with the encrypted content, in the binary age format
+pub fn encrypt(data: &[u8], recipients: &[String]) -> io::Result<Vec<u8>> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| {
+            age::x25519::Recipient::from_str(recipient).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid age recipient {}: {}", recipient, err),
+                )
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let encryptor = age::Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| recipient as &dyn age::Recipient),
+    )
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(data)?;
+    writer.finish()?;
+    Ok(encrypted)
+}
+
+/// Decrypt an age file to a temporary file, which is deleted when it goes out of scope
+///
+/// # Arguments
+///
+/// * `encrypted_file`: path to the file encrypted with age
+/// * `identity_file`: path to an age identity file with the private key(s)
+///
+/// returns: Result<NamedTempFile, Error>
+pub fn decrypt_file(encrypted_file: &Path, identity_file: &Path) -> io::Result<NamedTempFile> {
+    let invalid_data = |err: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", encrypted_file.display(), err),
+        )
+    };
+    let identities = age::IdentityFile::from_file(identity_file.to_string_lossy().to_string())?
+        .into_identities()
+        .map_err(|err| invalid_data(err.to_string()))?;
+    let decryptor = age::Decryptor::new(File::open(encrypted_file)?)
+        .map_err(|err| invalid_data(err.to_string()))?;
+    if decryptor.is_scrypt() {
+        return Err(invalid_data(
+            "files encrypted with a passphrase are not supported".to_string(),
+        ));
+    }
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|identity| identity.as_ref() as _))
+        .map_err(|err| invalid_data(err.to_string()))?;
+
+    let mut temp_file = NamedTempFile::new()?;
+    io::copy(&mut reader, &mut temp_file)?;
+    Ok(temp_file)
+}
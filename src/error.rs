@@ -0,0 +1,198 @@
+//! Errors of reading bank files, so library users and the CLI can handle each file on its own
+use polars::prelude::PolarsError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Why a bank file could not be read
+#[derive(Debug)]
+pub enum BankCsvError {
+    /// The format of a file could not be detected, e.g. the header doesn't match any known format
+    UnknownFormat {
+        /// The file with the unknown format
+        file_path: PathBuf,
+        /// Why the format is unknown (without the file path)
+        reason: String,
+        /// The columns of the header, if it could be read
+        columns: Vec<String>,
+    },
+    /// The content of a file is not valid in its encoding
+    Encoding {
+        /// The file that could not be decoded
+        file_path: PathBuf,
+        /// Name of the encoding, e.g. `windows-1252`
+        encoding: &'static str,
+    },
+    /// A date doesn't match any of the formats of its source, nor the ISO fallbacks
+    DateParse {
+        /// The file with the date
+        file_path: PathBuf,
+        /// The first line of the file with the date (starting at 1), if it could be found
+        line: Option<usize>,
+        /// The date as it is in the file
+        value: String,
+        /// Name of the format of the file, whose date formats were tried
+        format: String,
+        /// The date formats that were tried
+        date_formats: Vec<String>,
+    },
+    /// A column of the format is not in the file
+    MissingColumn {
+        /// The file without the column
+        file_path: PathBuf,
+        /// Name of the missing column
+        column: String,
+        /// The columns of the file
+        columns: Vec<String>,
+    },
+    /// The file is not read on purpose, e.g. a securities export without the securities mode
+    Skipped {
+        /// The file that was skipped
+        file_path: PathBuf,
+        /// Why it was skipped (without the file path)
+        reason: String,
+    },
+    /// The file has the same content as another one that was already read
+    Duplicate {
+        /// The file that was not read
+        file_path: PathBuf,
+        /// The file with the same content that was read
+        first_path: PathBuf,
+    },
+    /// The file could not be read or written
+    Io(io::Error),
+    /// The data of the file could not be loaded or filtered
+    Polars(PolarsError),
+}
+
+impl BankCsvError {
+    /// The error without the file path, for messages that already show it
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::error::BankCsvError;
+    /// let err = BankCsvError::UnknownFormat {
+    ///     file_path: "export.csv".into(),
+    ///     reason: "unknown CSV format".to_string(),
+    ///     columns: vec!["Datum".to_string(), "Betrag".to_string()],
+    /// };
+    /// assert_eq!(err.to_string(), r#"export.csv: unknown CSV format. These are the columns: ["Datum", "Betrag"]"#);
+    /// assert_eq!(err.reason(), r#"unknown CSV format. These are the columns: ["Datum", "Betrag"]"#);
+    /// ```
+    pub fn reason(&self) -> String {
+        let message = self.to_string();
+        let file_path = match self {
+            BankCsvError::UnknownFormat { file_path, .. }
+            | BankCsvError::Encoding { file_path, .. }
+            | BankCsvError::DateParse { file_path, .. }
+            | BankCsvError::MissingColumn { file_path, .. }
+            | BankCsvError::Skipped { file_path, .. }
+            | BankCsvError::Duplicate { file_path, .. } => file_path,
+            _ => return message,
+        };
+        let prefix = format!("{}: ", file_path.display());
+        match message.strip_prefix(&prefix) {
+            Some(reason) => reason.to_string(),
+            None => message,
+        }
+    }
+}
+
+impl fmt::Display for BankCsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BankCsvError::UnknownFormat {
+                file_path,
+                reason,
+                columns,
+            } => {
+                write!(f, "{}: {}", file_path.display(), reason)?;
+                if !columns.is_empty() {
+                    write!(f, ". These are the columns: {:?}", columns)?;
+                }
+                Ok(())
+            }
+            BankCsvError::Encoding {
+                file_path,
+                encoding,
+            } => write!(
+                f,
+                "{}: the content is not valid {}",
+                file_path.display(),
+                encoding
+            ),
+            BankCsvError::DateParse {
+                file_path,
+                line,
+                value,
+                format,
+                date_formats,
+            } => {
+                write!(f, "{}", file_path.display())?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                write!(
+                    f,
+                    ": invalid date {:?}, expected one of the {} formats {:?}",
+                    value, format, date_formats
+                )
+            }
+            BankCsvError::MissingColumn {
+                file_path,
+                column,
+                columns,
+            } => write!(
+                f,
+                "{}: missing column {:?}. These are the columns: {:?}",
+                file_path.display(),
+                column,
+                columns
+            ),
+            BankCsvError::Skipped { file_path, reason } => {
+                write!(f, "{}: {}", file_path.display(), reason)
+            }
+            BankCsvError::Duplicate {
+                file_path,
+                first_path,
+            } => write!(
+                f,
+                "{}: same content as {}",
+                file_path.display(),
+                first_path.display()
+            ),
+            BankCsvError::Io(err) => err.fmt(f),
+            BankCsvError::Polars(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for BankCsvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BankCsvError::Io(err) => Some(err),
+            BankCsvError::Polars(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BankCsvError {
+    fn from(err: io::Error) -> Self {
+        BankCsvError::Io(err)
+    }
+}
+
+impl From<csv::Error> for BankCsvError {
+    fn from(err: csv::Error) -> Self {
+        BankCsvError::Io(err.into())
+    }
+}
+
+impl From<PolarsError> for BankCsvError {
+    fn from(err: PolarsError) -> Self {
+        BankCsvError::Polars(err)
+    }
+}
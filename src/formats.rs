@@ -0,0 +1,274 @@
+//! Formats of other banks, described in TOML files instead of being built in
+use crate::error::BankCsvError;
+use crate::script::RowScript;
+use crate::sources::{SourceRow, SourceSpec};
+use encoding_rs::Encoding;
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A bank format read from a descriptor file, e.g. `~/.config/bank-csv/formats/bunq.toml`
+#[derive(Clone, Debug)]
+pub struct FormatDescriptor {
+    /// Name of the format, shown in messages and in the history
+    pub name: String,
+    /// Source written to the output, e.g. `bunq`
+    pub source: String,
+    /// The header of the file starts with these columns
+    pub columns: Vec<String>,
+    /// Date formats (chrono syntax), tried in order before the ISO ones
+    pub date_formats: Vec<String>,
+    /// Columns of the original file for each output field
+    pub fields: FieldColumns,
+    /// Currency of all rows, for files without a currency column
+    pub currency: Option<String>,
+    /// Encoding of the files, e.g. `windows-1252` or `utf-16le`; detected if not declared
+    pub encoding: Option<&'static Encoding>,
+    /// Types of rows whose amounts are written without a sign, but are money going out
+    pub negate_types: Vec<String>,
+    /// Regular expressions to rewrite fields, applied in order
+    transforms: Vec<(Field, Regex, String)>,
+    /// Rhai script run on each row after the transforms, e.g. to parse the memo (`scripting` feature)
+    script: Option<RowScript>,
+}
+
+/// Columns of the original file for each output field; fields without a column are empty
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldColumns {
+    /// Column with the date
+    pub date: String,
+    /// Column with the amount
+    pub amount: String,
+    /// Column with the currency; see [`FormatDescriptor::currency`] for files without one
+    pub currency: Option<String>,
+    /// Column with the transaction type
+    #[serde(rename = "type")]
+    pub transaction_type: Option<String>,
+    /// Column with the payee
+    pub payee: Option<String>,
+    /// Column with the memo
+    pub memo: Option<String>,
+}
+
+/// A field that can be rewritten by a transform
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    /// The transaction type
+    Type,
+    /// The payee
+    Payee,
+    /// The memo
+    Memo,
+}
+
+/// A transform as it is written in the descriptor
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTransform {
+    field: Field,
+    pattern: String,
+    replacement: String,
+}
+
+/// A descriptor as it is written in the file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDescriptor {
+    name: String,
+    source: String,
+    columns: Vec<String>,
+    #[serde(default)]
+    date_formats: Vec<String>,
+    fields: FieldColumns,
+    currency: Option<String>,
+    encoding: Option<String>,
+    #[serde(default)]
+    negate_types: Vec<String>,
+    #[serde(default)]
+    transforms: Vec<RawTransform>,
+    script: Option<String>,
+}
+
+impl FormatDescriptor {
+    /// The default directory of the descriptors: `~/.config/bank-csv/formats`
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".config").join("bank-csv").join("formats"))
+    }
+
+    /// Parse a descriptor from the content of a TOML file
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::formats::{Field, FormatDescriptor};
+    /// let descriptor = FormatDescriptor::parse(r#"
+    ///     name = "bunq"
+    ///     source = "bunq"
+    ///     columns = ["Date", "Interest Date", "Amount", "Account", "Counterparty", "Name"]
+    ///     currency = "EUR"
+    ///     encoding = "windows-1252"
+    ///     fields = { date = "Date", amount = "Amount", payee = "Name", memo = "Description" }
+    ///     [[transforms]]
+    ///     field = "payee"
+    ///     pattern = '^(.+) B\.V\.$'
+    ///     replacement = "$1"
+    /// "#).unwrap();
+    /// assert!(descriptor.matches(&["Date", "Interest Date", "Amount", "Account", "Counterparty", "Name", "Description"]));
+    /// assert!(!descriptor.matches(&["Date", "Payee"]));
+    /// assert_eq!(descriptor.transform(Field::Payee, "Albert Heijn B.V."), "Albert Heijn");
+    /// assert_eq!(descriptor.encoding.unwrap().name(), "windows-1252");
+    /// ```
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let raw: RawDescriptor = toml::from_str(content).map_err(|err| err.to_string())?;
+        if raw.columns.is_empty() {
+            return Err("the list of columns to detect the format is empty".to_string());
+        }
+        let encoding = raw
+            .encoding
+            .map(|label| {
+                Encoding::for_label(label.as_bytes())
+                    .ok_or_else(|| format!("unknown encoding {:?}", label))
+            })
+            .transpose()?;
+        let transforms = raw
+            .transforms
+            .into_iter()
+            .map(|transform| {
+                Regex::new(&transform.pattern)
+                    .map(|regex| (transform.field, regex, transform.replacement))
+                    .map_err(|err| err.to_string())
+            })
+            .collect::<Result<_, _>>()?;
+        let script = raw
+            .script
+            .map(|code| {
+                RowScript::compile(&format!("script of the {} format", raw.name), &code)
+                    .map_err(|err| err.to_string())
+            })
+            .transpose()?;
+        Ok(Self {
+            name: raw.name,
+            source: raw.source,
+            columns: raw.columns,
+            date_formats: raw.date_formats,
+            fields: raw.fields,
+            currency: raw.currency,
+            encoding,
+            negate_types: raw.negate_types,
+            transforms,
+            script,
+        })
+    }
+
+    /// Check if a header has the columns of this format at its start
+    pub fn matches(&self, column_names: &[&str]) -> bool {
+        column_names.len() >= self.columns.len()
+            && self
+                .columns
+                .iter()
+                .zip(column_names)
+                .all(|(expected, column)| expected == column)
+    }
+
+    /// Apply the transforms of a field to its value, in order
+    pub fn transform(&self, field: Field, value: &str) -> String {
+        self.transforms
+            .iter()
+            .filter(|(transform_field, _, _)| *transform_field == field)
+            .fold(value.to_string(), |value, (_, regex, replacement)| {
+                regex.replace_all(&value, replacement.as_str()).to_string()
+            })
+    }
+}
+
+impl SourceSpec for FormatDescriptor {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn source_name(&self) -> String {
+        self.source.clone()
+    }
+
+    fn matches_header(&self, column_names: &[&str]) -> bool {
+        self.matches(column_names)
+    }
+
+    fn encoding(&self) -> Option<&'static Encoding> {
+        self.encoding
+    }
+
+    fn columns<'a>(&'a self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+        let fields = &self.fields;
+        [
+            Some(&fields.date),
+            fields.currency.as_ref(),
+            Some(&fields.amount),
+            fields.transaction_type.as_ref(),
+            fields.payee.as_ref(),
+            fields.memo.as_ref(),
+        ]
+        .into_iter()
+        .map(|column| column.map_or("", String::as_str))
+        .collect()
+    }
+
+    fn date_formats(&self) -> Vec<&str> {
+        self.date_formats.iter().map(String::as_str).collect()
+    }
+
+    /// Apply the currency of the descriptor, its transforms, its script and its negated types
+    fn post_process(
+        &self,
+        row: &mut SourceRow,
+        _upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        if row.currency.is_empty() {
+            row.currency = self.currency.clone().unwrap_or_default();
+        }
+        row.transaction_type = self.transform(Field::Type, &row.transaction_type);
+        row.payee = self.transform(Field::Payee, &row.payee);
+        row.memo = self.transform(Field::Memo, &row.memo);
+        if let Some(script) = &self.script {
+            if !script.apply_to_source_row(row)? {
+                return Ok(false);
+            }
+        }
+        if self.negate_types.contains(&row.transaction_type) && !row.amount.starts_with('-') {
+            row.amount = format!("-{}", row.amount);
+        }
+        Ok(true)
+    }
+}
+
+/// Load the descriptors of a directory (`*.toml` files, sorted by name); a missing directory has none
+///
+/// returns: Result<Vec<FormatDescriptor>, Error>; an error if a descriptor is invalid
+pub fn load_formats(dir: &Path) -> io::Result<Vec<FormatDescriptor>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "toml")
+        })
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            FormatDescriptor::parse(&fs::read_to_string(path)?).map_err(|message| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), message),
+                )
+            })
+        })
+        .collect()
+}
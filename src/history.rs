@@ -0,0 +1,73 @@
+//! History of merge runs, appended to a JSON Lines file to audit which exports produced which files
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// An input file of a run
+#[derive(Debug, Serialize)]
+pub struct HistoryInput {
+    /// Path of the file, as it was read (after downloading or extracting it)
+    pub path: PathBuf,
+    /// SHA-256 of the file content
+    pub sha256: String,
+    /// Detected format of the file; none for output files of previous runs and skipped files
+    pub format: Option<String>,
+    /// Number of transactions read from the file, in the requested currency
+    pub rows: usize,
+    /// Why the file was skipped, if it was
+    pub skipped: Option<String>,
+}
+
+/// An output file of a run
+#[derive(Debug, Serialize)]
+pub struct HistoryOutput {
+    /// Path or cloud storage URL of the file
+    pub path: String,
+    /// Number of transactions in the file
+    pub rows: usize,
+    /// Whether the file was written; existing files are kept without `--force`
+    pub written: bool,
+}
+
+/// One line of the history file
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    /// When the run finished, in RFC 3339 format with the local offset
+    pub timestamp: String,
+    /// The subcommand, e.g. `merge`
+    pub command: String,
+    /// The input files
+    pub inputs: Vec<HistoryInput>,
+    /// The output files
+    pub outputs: Vec<HistoryOutput>,
+    /// The settings of the run after applying the configuration (currency, locale, ...)
+    pub settings: BTreeMap<String, String>,
+}
+
+impl HistoryEntry {
+    /// Create an entry for a command, with the current time
+    pub fn new(command: &str) -> Self {
+        Self {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            command: command.to_string(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            settings: BTreeMap::new(),
+        }
+    }
+
+    /// Append the entry as one JSON line to the history file, creating the file and its directory if needed
+    pub fn append_to(&self, file_path: &Path) -> io::Result<()> {
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let line = serde_json::to_string(self).map_err(io::Error::other)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_path)?;
+        writeln!(file, "{}", line)
+    }
+}
@@ -0,0 +1,98 @@
+//! Index of the monthly files in the output directory, so other tools can find them without globbing
+use crate::CsvOutputRow;
+use serde::Serialize;
+use std::io;
+
+/// Name of the index file in the output directory
+pub const INDEX_FILE_NAME: &str = "index.csv";
+
+/// Number of transactions and totals of an output file
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Totals {
+    /// Number of transactions
+    pub rows: usize,
+    /// Sum of the positive amounts
+    pub income: f64,
+    /// Sum of the negative amounts (a negative number)
+    pub expenses: f64,
+}
+
+impl Totals {
+    /// Count and sum transactions; amounts that are not numbers are counted but not summed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::index::Totals;
+    /// use bank_csv::CsvOutputRow;
+    /// use chrono::NaiveDate;
+    /// let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+    /// let row = |amount: &str| {
+    ///     let text = String::new;
+    ///     CsvOutputRow::new(date, text(), "EUR".into(), amount.into(), text(), text(), text())
+    /// };
+    /// let totals = Totals::of(&[row("100,50"), row("-20"), row("-0,5")]);
+    /// assert_eq!(totals, Totals { rows: 3, income: 100.5, expenses: -20.5 });
+    /// ```
+    pub fn of<'a>(transactions: impl IntoIterator<Item = &'a CsvOutputRow>) -> Self {
+        let mut totals = Self::default();
+        for transaction in transactions {
+            totals.add(transaction);
+        }
+        totals
+    }
+
+    /// Count and sum one more transaction
+    pub fn add(&mut self, transaction: &CsvOutputRow) {
+        self.rows += 1;
+        match transaction.amount.replace(',', ".").parse::<f64>() {
+            Ok(amount) if amount > 0.0 => self.income += amount,
+            Ok(amount) => self.expenses += amount,
+            Err(_) => {}
+        }
+    }
+}
+
+/// One line of the index
+#[derive(Clone, Debug, Serialize)]
+pub struct IndexEntry {
+    /// File name, relative to the output directory
+    pub file: String,
+    /// Period of the transactions in the file, e.g. `2024-01` or `2024-W05`
+    pub period: String,
+    /// Currency of the transactions
+    pub currency: String,
+    /// Number of transactions
+    pub rows: usize,
+    /// Sum of the positive amounts, with a decimal comma like the output files
+    pub income: String,
+    /// Sum of the negative amounts
+    pub expenses: String,
+    /// Sum of all amounts
+    pub total: String,
+}
+
+impl IndexEntry {
+    /// Create the index line of a file from its totals
+    pub fn new(file: String, period: String, currency: String, totals: Totals) -> Self {
+        let amount = |value: f64| format!("{:.2}", value).replace('.', ",");
+        Self {
+            file,
+            period,
+            currency,
+            rows: totals.rows,
+            income: amount(totals.income),
+            expenses: amount(totals.expenses),
+            total: amount(totals.income + totals.expenses),
+        }
+    }
+}
+
+/// The content of the index file: a CSV with a header and one line per output file
+pub fn index_csv(entries: &[IndexEntry]) -> io::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for entry in entries {
+        writer.serialize(entry)?;
+    }
+    writer.into_inner().map_err(io::Error::other)
+}
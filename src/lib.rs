@@ -1,59 +1,113 @@
 //! Detect CSV files from a couple of German banks (N26, DKB) and PayPal,
 //! filter out transactions in a specific currency and generate a CSV file with these transactions
-use chrono::NaiveDate;
+use crate::banks::amex::Amex;
+use crate::banks::apple::AppleCard;
+use crate::banks::boursorama::Boursorama;
+use crate::banks::coinbase::Coinbase;
+use crate::banks::comdirect::ComdirectDepot;
+use crate::banks::commerzbank::Commerzbank;
+use crate::banks::dkb::{Dkb, DKB_ACCOUNT_TYPES};
+use crate::banks::george::George;
+use crate::banks::ing::Ing;
+use crate::banks::ing_nl::IngNl;
+use crate::banks::kraken::Kraken;
+use crate::banks::monzo::Monzo;
+use crate::banks::n26::N26;
+use crate::banks::paypal::{PayPal, PAYPAL_CREDIT, PAYPAL_DEBIT, PAYPAL_MEMO};
+use crate::banks::postfinance::PostFinance;
+use crate::banks::revolut::Revolut;
+use crate::banks::sparkasse::Sparkasse;
+use crate::banks::starling::Starling;
+use crate::banks::stripe::Stripe;
+use crate::banks::trade_republic::TradeRepublic;
+use crate::banks::ubs::Ubs;
+use crate::banks::wise::Wise;
+use crate::error::BankCsvError;
+use crate::merge::MergeOptions;
+use crate::sources::SourceSpec;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use colored::Colorize;
 use csv::StringRecord;
-use encoding_rs::ISO_8859_10;
+use encoding_rs::{Encoding, ISO_8859_10, UTF_8, WINDOWS_1252};
+use hmac::{Hmac, Mac};
 use polars::prelude::*;
+use regex::Regex;
+use sha2::{Digest, Sha256};
 use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::fmt::Display;
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::Read;
-use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::ops::Deref;
 use std::path::Path;
+use std::sync::OnceLock;
 use tempfile::NamedTempFile;
 
+pub mod aliases;
+pub mod balance;
+pub mod banks;
+pub mod classify;
+pub mod cloud;
+pub mod config;
+pub mod crypto;
+pub mod error;
+pub mod formats;
+pub mod history;
+pub mod index;
+pub mod lock;
+pub mod manifest;
+pub mod merge;
+pub mod package;
+pub mod payees;
+pub mod report;
+pub mod rules;
+pub mod schema;
+pub mod script;
+pub mod sources;
+pub mod split;
+pub mod state;
+pub mod tags;
+pub mod unzip;
+
+// Kept at the root of the crate, where they were before the banks had their own modules
+pub use banks::dkb::{dkb_edit_file, dkb_extract_amount};
+
 const CHAR_COMMA: &str = ",";
 const CHAR_DOT: &str = ".";
 const CHAR_DOUBLE_QUOTE: char = '"';
+const CHAR_CARRIAGE_RETURN: char = '\r';
+/// Byte order mark that some Windows programs add at the start of UTF-8 files
+const UTF8_BOM: &str = "\u{feff}";
 /// The number of first columns to read from the CSV file; used to detect the source
 pub const NUM_FIRST_COLUMNS: usize = 5;
-/// The number of columns to select from the CSV file
+/// The number of standard columns in the CSV output, before the extra columns
+pub const NUM_OUTPUT_COLUMNS: usize = 7;
+/// The number of columns to select from the CSV file; some formats select extra columns after these
 pub const NUM_SELECT_COLUMNS: usize = 6;
-const PAYPAL_COLUMNS: [&str; NUM_FIRST_COLUMNS] = ["Date", "Time", "TimeZone", "Name", "Type"];
-const PAYPAL_COLUMNS_OLD: [&str; NUM_FIRST_COLUMNS] =
-    ["Date", "Time", "Time Zone", "Description", "Currency"];
-const N26_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
-    "Date",
-    "Payee",
-    "Account number",
-    "Transaction type",
-    "Payment reference",
-];
-const N26_COLUMNS_2024_09: [&str; NUM_FIRST_COLUMNS] = [
-    "Booking Date",
-    "Value Date",
-    "Partner Name",
-    "Partner Iban",
-    "Type",
-];
-const DKB_COLUMNS: [&str; NUM_FIRST_COLUMNS] = [
-    "Buchungstag",
-    "Wertstellung",
-    "Buchungstext",
-    "Auftraggeber / Begünstigter",
-    "Verwendungszweck",
-];
-const DKB_COLUMNS_2024_09: [&str; NUM_FIRST_COLUMNS] = [
-    "Buchungsdatum",
-    "Wertstellung",
-    "Status",
-    "Zahlungspflichtige*r",
-    "Zahlungsempfänger*in",
+/// Sources whose exports can have lines above the header, with the start of their header (without quotes)
+const HEADERS_AFTER_PREAMBLE: [(Source, &str); 4] = [
+    (
+        Source::Commerzbank,
+        "Buchungstag;Wertstellung;Umsatzart;Buchungstext;Betrag",
+    ),
+    (Source::PostFinance, "Datum;Bewegungstyp;Avisierungstext"),
+    (
+        Source::Ubs,
+        "Abschlussdatum;Abschlusszeit;Buchungsdatum;Valutadatum",
+    ),
+    // Only the ASCII part of the header, since older exports are in ISO-8859-1
+    (Source::Ing, "Buchung;Valuta;Auftraggeber/"),
 ];
+/// Number of lines above the header that are searched for one of [`HEADERS_AFTER_PREAMBLE`]
+const PREAMBLE_LINES: usize = 15;
 
 /// The source of a CSV file
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Source {
     /// N26 CSV
     N26,
@@ -61,6 +115,73 @@ pub enum Source {
     PayPal,
     /// DKB has a weird CSV with some lines on the top that don't match the rest of the file
     DKB,
+    /// Kraken crypto exchange; the asset (EUR, BTC, ...) is used as the currency
+    Kraken,
+    /// Coinbase crypto exchange; the fiat total is used as the amount
+    Coinbase,
+    /// Stripe balance report; fees are written as separate transactions
+    Stripe,
+    /// Apple Card statement, only in US dollars; purchases are positive in the file
+    Apple,
+    /// Monzo (UK); rows are read in their local currency, e.g. euros spent from a pound account
+    Monzo,
+    /// Starling (UK); each file has the currency of its account
+    Starling,
+    /// Commerzbank has lines above the header and below the transactions, which are skipped
+    Commerzbank,
+    /// PostFinance (Switzerland); credits and debits are in separate columns, named after the currency of the account
+    PostFinance,
+    /// UBS (Switzerland); credits and debits are in separate columns
+    Ubs,
+    /// George, the online banking of Erste Bank and the Austrian Sparkassen
+    George,
+    /// ING (Germany), with the account details above the header
+    Ing,
+    /// ING (Netherlands); only euro accounts
+    IngNl,
+    /// Sparkasse (Germany)
+    Sparkasse,
+    /// BoursoBank (formerly Boursorama, France); only euro accounts
+    Boursorama,
+    /// Revolut, with the accounts of all currencies in one statement
+    Revolut,
+    /// Wise (formerly TransferWise)
+    Wise,
+    /// American Express (Germany)
+    Amex,
+    /// Trade Republic (securities)
+    TradeRepublic,
+    /// comdirect (securities)
+    Comdirect,
+}
+
+impl Source {
+    /// The encoding of the exports of this source, when it's always the same;
+    /// the encoding of other files is detected, see [`detect_encoding`]
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        match self {
+            Source::DKB => Some(ISO_8859_10),
+            _ => None,
+        }
+    }
+
+    /// The start of the header of the exports of this source that can have lines above it,
+    /// see [`preamble_edit_file`]
+    pub fn header_after_preamble(&self) -> Option<&'static str> {
+        HEADERS_AFTER_PREAMBLE
+            .iter()
+            .find(|(source, _)| source == self)
+            .map(|(_, header)| *header)
+    }
+
+    /// The spec of the first format of this source (see [`CsvFormat::ALL`]), to read its files
+    /// before their format is detected
+    pub fn spec(&self) -> Option<&'static dyn SourceSpec> {
+        CsvFormat::ALL
+            .iter()
+            .find(|format| format.source() == *self)
+            .map(CsvFormat::spec)
+    }
 }
 
 impl Display for Source {
@@ -69,27 +190,345 @@ impl Display for Source {
             Source::N26 => "N26".to_string(),
             Source::PayPal => "PayPal".to_string(),
             Source::DKB => "DKB".to_string(),
+            Source::Kraken => "Kraken".to_string(),
+            Source::Coinbase => "Coinbase".to_string(),
+            Source::Stripe => "Stripe".to_string(),
+            Source::Apple => "Apple".to_string(),
+            Source::Monzo => "Monzo".to_string(),
+            Source::Starling => "Starling".to_string(),
+            Source::Commerzbank => "Commerzbank".to_string(),
+            Source::PostFinance => "PostFinance".to_string(),
+            Source::Ubs => "UBS".to_string(),
+            Source::George => "George".to_string(),
+            Source::Ing => "ING".to_string(),
+            Source::IngNl => "ING NL".to_string(),
+            Source::Sparkasse => "Sparkasse".to_string(),
+            Source::Boursorama => "Boursorama".to_string(),
+            Source::Revolut => "Revolut".to_string(),
+            Source::Wise => "Wise".to_string(),
+            Source::Amex => "Amex".to_string(),
+            Source::TradeRepublic => "Trade Republic".to_string(),
+            Source::Comdirect => "comdirect".to_string(),
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// Key of a source in the configuration (`[sources.<key>]` and `[memo_templates]`):
+/// its name in lowercase, with underscores instead of spaces
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::{source_key, Source};
+///
+/// assert_eq!(source_key(&Source::IngNl.to_string()), "ing_nl");
+/// assert_eq!(source_key("Trade Republic"), "trade_republic");
+/// ```
+pub fn source_key(source_name: &str) -> String {
+    source_name.trim().to_lowercase().replace(' ', "_")
+}
+
+/// A known CSV layout, identified by the first columns of the header
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CsvFormat {
+    /// PayPal activity report
+    #[value(name = "paypal")]
+    PayPal,
+    /// PayPal activity report, old layout
+    #[value(name = "paypal-old")]
+    PayPalOld,
+    /// N26 export
+    #[value(name = "n26")]
+    N26,
+    /// N26 export from before 2020, without the account number column
+    #[value(name = "n26-legacy")]
+    N26Legacy,
+    /// N26 export, layout used since September 2024
+    #[value(name = "n26-2024-09")]
+    N26Sep2024,
+    /// N26 Business (Mastercard) export, with cashback columns
+    #[value(name = "n26-business")]
+    N26Business,
+    /// DKB Girokonto export
+    #[value(name = "dkb")]
+    Dkb,
+    /// DKB Girokonto export, layout used since September 2024
+    #[value(name = "dkb-2024-09")]
+    DkbSep2024,
+    /// DKB Depot (securities) export, only read in securities mode
+    #[value(name = "dkb-depot")]
+    DkbDepot,
+    /// Legacy DKB credit card (Kreditkarte) export, from the banking UI used before 2023
+    #[value(name = "dkb-credit-card")]
+    DkbCreditCard,
+    /// Kraken ledgers export
+    #[value(name = "kraken")]
+    Kraken,
+    /// Coinbase transaction report
+    #[value(name = "coinbase")]
+    Coinbase,
+    /// Stripe balance report
+    #[value(name = "stripe")]
+    Stripe,
+    /// Apple Card monthly statement
+    #[value(name = "apple-card")]
+    AppleCard,
+    /// Monzo export
+    #[value(name = "monzo")]
+    Monzo,
+    /// Starling statement export
+    #[value(name = "starling")]
+    Starling,
+    /// Commerzbank Umsätze export
+    #[value(name = "commerzbank")]
+    Commerzbank,
+    /// PostFinance e-finance export
+    #[value(name = "postfinance")]
+    PostFinance,
+    /// UBS e-banking export
+    #[value(name = "ubs")]
+    Ubs,
+    /// George (Erste Bank, Sparkasse) export
+    #[value(name = "george")]
+    George,
+    /// ING Germany export
+    #[value(name = "ing")]
+    Ing,
+    /// ING Netherlands export
+    #[value(name = "ing-nl")]
+    IngNl,
+    /// Sparkasse export in the CAMT layout
+    #[value(name = "sparkasse")]
+    Sparkasse,
+    /// BoursoBank (Boursorama) export
+    #[value(name = "boursorama")]
+    Boursorama,
+    /// Revolut statement
+    #[value(name = "revolut")]
+    Revolut,
+    /// Wise account statement
+    #[value(name = "wise")]
+    Wise,
+    /// American Express (Germany) export
+    #[value(name = "amex")]
+    Amex,
+    /// Trade Republic transactions exported by pytr, only read in securities mode
+    #[value(name = "trade-republic")]
+    TradeRepublic,
+    /// comdirect depot export, only read in securities mode
+    #[value(name = "comdirect-depot")]
+    ComdirectDepot,
+}
+
+impl CsvFormat {
+    /// All known formats, in the order they are tried during detection;
+    /// layouts with extra columns come before the ones they extend
+    pub const ALL: [CsvFormat; 29] = [
+        CsvFormat::PayPal,
+        CsvFormat::PayPalOld,
+        CsvFormat::N26,
+        CsvFormat::N26Legacy,
+        CsvFormat::N26Business,
+        CsvFormat::N26Sep2024,
+        CsvFormat::Dkb,
+        CsvFormat::DkbSep2024,
+        CsvFormat::DkbDepot,
+        CsvFormat::DkbCreditCard,
+        CsvFormat::Kraken,
+        CsvFormat::Coinbase,
+        CsvFormat::Stripe,
+        CsvFormat::AppleCard,
+        CsvFormat::Monzo,
+        CsvFormat::Starling,
+        CsvFormat::Commerzbank,
+        CsvFormat::PostFinance,
+        CsvFormat::Ubs,
+        CsvFormat::George,
+        CsvFormat::Ing,
+        CsvFormat::IngNl,
+        CsvFormat::Sparkasse,
+        CsvFormat::Boursorama,
+        CsvFormat::Revolut,
+        CsvFormat::Wise,
+        CsvFormat::Amex,
+        CsvFormat::TradeRepublic,
+        CsvFormat::ComdirectDepot,
+    ];
+
+    /// The spec of the bank that exports this format, see [`crate::banks`]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::CsvFormat;
+    /// let spec = CsvFormat::Starling.spec();
+    /// assert_eq!(spec.format(), Some(CsvFormat::Starling));
+    /// assert_eq!(spec.source_name(), "Starling");
+    /// let header = ["Date", "Counter Party", "Reference", "Type", "Amount (GBP)", "Balance (GBP)"];
+    /// assert_eq!(spec.columns(&header, "GBP")[2], "Amount (GBP)");
+    /// // Files without a currency column have it in the name of their amount columns
+    /// assert_eq!(spec.header_currency(&header), Some("GBP"));
+    /// let header = ["Datum", "Bewegungstyp", "Avisierungstext", "Gutschrift in CHF", "Lastschrift in CHF"];
+    /// assert_eq!(CsvFormat::PostFinance.spec().header_currency(&header), Some("CHF"));
+    /// assert_eq!(spec.header_currency(&["Date", "Amount"]), None);
+    /// assert!(CsvFormat::DkbDepot.spec().is_securities());
+    /// ```
+    pub fn spec(&self) -> &'static dyn SourceSpec {
+        match self {
+            CsvFormat::PayPal => &PayPal::Current,
+            CsvFormat::PayPalOld => &PayPal::Old,
+            CsvFormat::N26 => &N26::Classic,
+            CsvFormat::N26Legacy => &N26::Legacy,
+            CsvFormat::N26Sep2024 => &N26::Sep2024,
+            CsvFormat::N26Business => &N26::Business,
+            CsvFormat::Dkb => &Dkb::Girokonto,
+            CsvFormat::DkbSep2024 => &Dkb::Sep2024,
+            CsvFormat::DkbDepot => &Dkb::Depot,
+            CsvFormat::DkbCreditCard => &Dkb::CreditCard,
+            CsvFormat::Kraken => &Kraken,
+            CsvFormat::Coinbase => &Coinbase,
+            CsvFormat::Stripe => &Stripe,
+            CsvFormat::AppleCard => &AppleCard,
+            CsvFormat::Monzo => &Monzo,
+            CsvFormat::Starling => &Starling,
+            CsvFormat::Commerzbank => &Commerzbank,
+            CsvFormat::PostFinance => &PostFinance,
+            CsvFormat::Ubs => &Ubs,
+            CsvFormat::George => &George,
+            CsvFormat::Ing => &Ing,
+            CsvFormat::IngNl => &IngNl,
+            CsvFormat::Sparkasse => &Sparkasse,
+            CsvFormat::Boursorama => &Boursorama,
+            CsvFormat::Revolut => &Revolut,
+            CsvFormat::Wise => &Wise,
+            CsvFormat::Amex => &Amex,
+            CsvFormat::TradeRepublic => &TradeRepublic,
+            CsvFormat::ComdirectDepot => &ComdirectDepot,
+        }
+    }
+
+    /// The source (bank) that exports this format
+    pub fn source(&self) -> Source {
+        match self {
+            CsvFormat::PayPal | CsvFormat::PayPalOld => Source::PayPal,
+            CsvFormat::N26
+            | CsvFormat::N26Legacy
+            | CsvFormat::N26Sep2024
+            | CsvFormat::N26Business => Source::N26,
+            CsvFormat::Dkb
+            | CsvFormat::DkbSep2024
+            | CsvFormat::DkbDepot
+            | CsvFormat::DkbCreditCard => Source::DKB,
+            CsvFormat::Kraken => Source::Kraken,
+            CsvFormat::Coinbase => Source::Coinbase,
+            CsvFormat::Stripe => Source::Stripe,
+            CsvFormat::AppleCard => Source::Apple,
+            CsvFormat::Monzo => Source::Monzo,
+            CsvFormat::Starling => Source::Starling,
+            CsvFormat::Commerzbank => Source::Commerzbank,
+            CsvFormat::PostFinance => Source::PostFinance,
+            CsvFormat::Ubs => Source::Ubs,
+            CsvFormat::George => Source::George,
+            CsvFormat::Ing => Source::Ing,
+            CsvFormat::IngNl => Source::IngNl,
+            CsvFormat::Sparkasse => Source::Sparkasse,
+            CsvFormat::Boursorama => Source::Boursorama,
+            CsvFormat::Revolut => Source::Revolut,
+            CsvFormat::Wise => Source::Wise,
+            CsvFormat::Amex => Source::Amex,
+            CsvFormat::TradeRepublic => Source::TradeRepublic,
+            CsvFormat::ComdirectDepot => Source::Comdirect,
+        }
+    }
+}
+
+impl Display for CsvFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            CsvFormat::PayPal => "PayPal",
+            CsvFormat::PayPalOld => "PayPal (old layout)",
+            CsvFormat::N26 => "N26",
+            CsvFormat::N26Legacy => "N26 (before 2020)",
+            CsvFormat::N26Sep2024 => "N26 (since 2024-09)",
+            CsvFormat::N26Business => "N26 Business",
+            CsvFormat::Dkb => "DKB",
+            CsvFormat::DkbSep2024 => "DKB (since 2024-09)",
+            CsvFormat::DkbDepot => "DKB Depot",
+            CsvFormat::DkbCreditCard => "DKB Kreditkarte",
+            CsvFormat::Kraken => "Kraken",
+            CsvFormat::Coinbase => "Coinbase",
+            CsvFormat::Stripe => "Stripe",
+            CsvFormat::AppleCard => "Apple Card",
+            CsvFormat::Monzo => "Monzo",
+            CsvFormat::Starling => "Starling",
+            CsvFormat::Commerzbank => "Commerzbank",
+            CsvFormat::PostFinance => "PostFinance",
+            CsvFormat::Ubs => "UBS",
+            CsvFormat::George => "George",
+            CsvFormat::Ing => "ING",
+            CsvFormat::IngNl => "ING NL",
+            CsvFormat::Sparkasse => "Sparkasse",
+            CsvFormat::Boursorama => "Boursorama",
+            CsvFormat::Revolut => "Revolut",
+            CsvFormat::Wise => "Wise",
+            CsvFormat::Amex => "Amex",
+            CsvFormat::TradeRepublic => "Trade Republic",
+            CsvFormat::ComdirectDepot => "comdirect Depot",
         };
         write!(f, "{}", str)
     }
 }
 
+/// Detect the encoding of the content of a file: from its byte order mark (UTF-8 or UTF-16),
+/// otherwise UTF-8 if it's valid, or Windows-1252 (a superset of ISO-8859-1) if it isn't
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::detect_encoding;
+/// assert_eq!(detect_encoding("Währung;Betrag".as_bytes()).name(), "UTF-8");
+/// assert_eq!(detect_encoding(b"W\xe4hrung;Betrag").name(), "windows-1252");
+/// assert_eq!(detect_encoding(b"\xff\xfeD\x00a\x00").name(), "UTF-16LE");
+/// ```
+pub fn detect_encoding(content: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(content) {
+        return encoding;
+    }
+    if std::str::from_utf8(content).is_ok() {
+        UTF_8
+    } else {
+        WINDOWS_1252
+    }
+}
+
 /// Detect the separator of a CSV file
 ///
 /// # Arguments
 ///
 /// * `file_path`: Path to the CSV file
 ///
-/// returns: Result<(u8, Option<Source>), Error>
-pub fn detect_separator(file_path: &Path) -> io::Result<(u8, Option<Source>)> {
-    let file = File::open(file_path)?;
-    let reader = io::BufReader::new(file);
-    if let Some(line) = reader.lines().next() {
-        let first_line = line?;
-
+/// returns: Result<(u8, Option<Source>), BankCsvError>; an `UnknownFormat` error if the file is empty
+/// or its first line has no separator
+pub fn detect_separator(file_path: &Path) -> Result<(u8, Option<Source>), BankCsvError> {
+    let content = std::fs::read(file_path)?;
+    let (text, _, _) = detect_encoding(&content).decode(&content);
+    // Some banks add lines above the header, and they can mention a "Girokonto" like DKB
+    if let Some(source) = source_after_preamble(&text) {
+        return Ok((b';', Some(source)));
+    }
+    if let Some(first_line) = text.lines().next() {
         // DKB has a weird CSV with some lines on the top that don't match the rest of the file
-        let source = if first_line.contains("Girokonto") {
+        let source = if DKB_ACCOUNT_TYPES
+            .iter()
+            .any(|account_type| first_line.contains(account_type))
+        {
             Some(Source::DKB)
+        } else if first_line.starts_with("You can use this transaction report")
+            || first_line.trim() == "Transactions"
+        {
+            // Coinbase also adds a few lines of explanation before the header
+            Some(Source::Coinbase)
         } else {
             None
         };
@@ -101,291 +540,1340 @@ pub fn detect_separator(file_path: &Path) -> io::Result<(u8, Option<Source>)> {
         } else if first_line.contains('\t') {
             Ok((b'\t', source))
         } else {
-            Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!(
-                    "{}: No separator found in the first line",
-                    file_path.display()
-                ),
-            ))
+            Err(BankCsvError::UnknownFormat {
+                file_path: file_path.to_path_buf(),
+                reason: "no separator found in the first line".to_string(),
+                columns: Vec::new(),
+            })
         }
     } else {
-        Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!("{}: Error reading the first line", file_path.display()),
-        ))
+        Err(BankCsvError::UnknownFormat {
+            file_path: file_path.to_path_buf(),
+            reason: "the file is empty".to_string(),
+            columns: Vec::new(),
+        })
     }
 }
 
-/// Remove the first extra lines from a DKB CSV file
+/// Copy a CSV file to a temporary file, skipping the lines above the header
 ///
 /// # Arguments
 ///
-/// * `original_dkb_csv_file`: path to the original DKB CSV file
-/// * `temp_file`:  a temporary file to write the filtered CSV to
+/// * `original_csv_file`: path to the original CSV file
+/// * `temp_file`: a temporary file to write the filtered CSV to
+/// * `encoding`: the encoding of the original file; the temporary file is always UTF-8
+/// * `header_markers`: the header is the first line containing one of these words
 ///
-/// returns: Result<(), Error>
-pub fn dkb_edit_file(
-    original_dkb_csv_file: &Path,
+/// returns: Result<Vec<String>, Error> with the skipped lines above the header
+pub(crate) fn copy_from_header(
+    original_csv_file: &Path,
     mut temp_file: &NamedTempFile,
-) -> io::Result<()> {
-    let input_file = File::open(original_dkb_csv_file)?;
+    encoding: &'static Encoding,
+    header_markers: &[&str],
+) -> io::Result<Vec<String>> {
+    let input_file = File::open(original_csv_file)?;
     let input_reader = BufReader::new(input_file);
     let mut temp_writer = BufWriter::new(&mut temp_file);
 
     let mut buffer = Vec::new();
     input_reader.take(u64::MAX).read_to_end(&mut buffer)?;
-    let (decoded, _, _) = ISO_8859_10.decode(&buffer);
+    let (decoded, _, _) = encoding.decode(&buffer);
     let mut write_lines = false;
+    let mut preamble = Vec::new();
     for line_content in decoded.lines() {
-        if line_content.contains("Verwendungszweck") {
+        if header_markers
+            .iter()
+            .any(|marker| line_content.contains(marker))
+        {
             write_lines = true;
         }
         if write_lines {
             writeln!(temp_writer, "{}", line_content)?;
+        } else {
+            preamble.push(line_content.to_string());
         }
     }
 
     // Flush the writer to make sure everything is written to the temporary file
     temp_writer.flush()?;
 
+    Ok(preamble)
+}
+
+/// Account data from the lines above the header of a statement (only DKB has them)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StatementMetadata {
+    /// Type or name of the account, e.g. "Girokonto" or "Depot 1234567"
+    pub account: Option<String>,
+    /// IBAN of the account, without spaces
+    pub iban: Option<String>,
+    /// First day of the exported period
+    pub period_start: Option<NaiveDate>,
+    /// Last day of the exported period
+    pub period_end: Option<NaiveDate>,
+    /// Closing balance, with a decimal comma like the amounts of the output
+    pub balance: Option<String>,
+    /// Date of the closing balance
+    pub balance_date: Option<NaiveDate>,
+}
+
+impl Display for StatementMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        if let Some(account) = &self.account {
+            parts.push(account.clone());
+        }
+        if let Some(iban) = &self.iban {
+            parts.push(iban.clone());
+        }
+        if let (Some(start), Some(end)) = (self.period_start, self.period_end) {
+            parts.push(format!("from {} to {}", start, end));
+        }
+        if let Some(balance) = &self.balance {
+            match self.balance_date {
+                Some(date) => parts.push(format!("balance {} on {}", balance, date)),
+                None => parts.push(format!("balance {}", balance)),
+            }
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// The source of a file whose header is in one of its first lines, see [`HEADERS_AFTER_PREAMBLE`]
+fn source_after_preamble(text: &str) -> Option<Source> {
+    text.lines().take(PREAMBLE_LINES + 1).find_map(|line| {
+        let line = line.trim_start_matches(UTF8_BOM).replace('"', "");
+        HEADERS_AFTER_PREAMBLE
+            .iter()
+            .find(|(_, header)| line.starts_with(header))
+            .map(|(source, _)| *source)
+    })
+}
+
+/// Remove the lines that some banks add above the header and below the transactions
+/// (Commerzbank, PostFinance, UBS, ING, and the registered sources with a preamble)
+///
+/// Only the header and the rows starting with a date are kept.
+///
+/// # Arguments
+///
+/// * `original_csv_file`: path to the original CSV file
+/// * `temp_file`:  a temporary file to write the filtered CSV to
+/// * `header_start`: the start of the header line, without quotes, see [`Source::header_after_preamble`]
+/// * `encoding`: the encoding of the original file; the temporary file is UTF-8
+///
+/// returns: Result<(), BankCsvError>
+pub fn preamble_edit_file(
+    original_csv_file: &Path,
+    temp_file: &NamedTempFile,
+    header_start: &str,
+    encoding: &'static Encoding,
+) -> Result<(), BankCsvError> {
+    static DATE_PATTERN: OnceLock<Regex> = OnceLock::new();
+    let date_pattern = DATE_PATTERN.get_or_init(|| {
+        Regex::new(r#"^"?(\d{2}\.\d{2}\.\d{4}|\d{4}-\d{2}-\d{2})"?[;,\t]"#).unwrap()
+    });
+    let buffer = std::fs::read(original_csv_file)?;
+    let (decoded, _, _) = encoding.decode(&buffer);
+    let mut temp_writer = BufWriter::new(temp_file);
+    let mut lines = decoded
+        .lines()
+        .map(|line| line.trim_start_matches(UTF8_BOM))
+        .skip_while(|line| !line.replace('"', "").starts_with(header_start));
+    if let Some(header) = lines.next() {
+        writeln!(temp_writer, "{}", header)?;
+    }
+    for line in lines.filter(|line| date_pattern.is_match(line)) {
+        writeln!(temp_writer, "{}", line)?;
+    }
+    temp_writer.flush()?;
     Ok(())
 }
 
-/// Filter the data frame by currency and determine the source based on the first columns of the CSV
+/// The type of a securities transaction, the same in all brokerages: "Buy", "Sell" or "Dividend";
+/// without a type, buys and sells are told apart by the sign of the amount. Other types are kept
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::securities_type;
+/// assert_eq!(securities_type("Kauf", "-1000,00"), "Buy");
+/// assert_eq!(securities_type("Ausschüttung", "12,34"), "Dividend");
+/// assert_eq!(securities_type("", "-1000,00"), "Buy");
+/// assert_eq!(securities_type("", "1050,00"), "Sell");
+/// assert_eq!(securities_type("Deposit", "500,00"), "Deposit");
+/// ```
+pub fn securities_type(transaction_type: &str, amount: &str) -> String {
+    match transaction_type.trim().to_lowercase().as_str() {
+        "buy" | "kauf" | "savings plan" | "sparplan" => "Buy",
+        "sell" | "verkauf" => "Sell",
+        "dividend" | "dividende" | "ausschüttung" | "distribution" => "Dividend",
+        "" if amount.starts_with('-') => "Buy",
+        "" => "Sell",
+        _ => transaction_type,
+    }
+    .to_string()
+}
+
+/// An expression that is true when a column has any of the values
+pub(crate) fn any_of(column: &str, values: &[&str]) -> Expr {
+    values
+        .iter()
+        .map(|value| col(column).eq(lit(*value)))
+        .reduce(|left, right| left.or(right))
+        .unwrap_or_else(|| lit(false))
+}
+
+/// Filter the data frame by currency, selecting the columns of the given format
 ///
 /// # Arguments
 ///
+/// * `file_path`: the file the data frame was read from, for the errors
 /// * `df`: the data frame to filter
-/// * `upper_currency`: the currency to filter by, in uppercase (EUR, USD, ...)
-///
-/// returns: (Source, DataFrame)
-pub fn filter_data_frame(df: &DataFrame, upper_currency: String) -> (Source, DataFrame) {
-    let schema = df.schema();
-    let first_columns: Vec<&str> = schema
-        .iter_names()
-        .take(NUM_FIRST_COLUMNS)
-        .map(|field| field.as_str())
+/// * `format`: the format of the CSV file, see [`sources::SourceRegistry::detect_format`]
+/// * `options`: the currency to filter by, the date basis, the PayPal balance impact
+///   and the types and payees to drop for the source of the format
+/// * `additional_columns`: other columns of the original file to select at the end (e.g. for memo templates)
+///
+/// returns: Result<DataFrame, BankCsvError> with [`NUM_SELECT_COLUMNS`] columns (date, currency, amount, type, payee, memo),
+/// followed by the extra columns of the format, if any, and then by the additional columns.
+/// Formats without one of the columns have an empty string in its place.
+/// An error if a column of the format is not in the data frame.
+pub fn filter_data_frame(
+    file_path: &Path,
+    df: &DataFrame,
+    format: CsvFormat,
+    options: &MergeOptions,
+    additional_columns: &[String],
+) -> Result<DataFrame, BankCsvError> {
+    let upper_currency = options.currency.to_uppercase();
+    let balance_impact = &options.paypal_balance_impact;
+    let source_filter = options
+        .source_filters
+        .get(&source_key(&format.source().to_string()));
+    let column_names = df.get_column_names();
+    let spec = format.spec();
+    let mut columns_to_select = spec.columns(&column_names, &upper_currency);
+    let mut lazy_frame = df.clone().lazy();
+    // Without filters, only the currency is selected
+    let row_filter = if options.no_filter {
+        spec.currency_filter(&column_names, &upper_currency)
+    } else {
+        spec.row_filter(&column_names, &upper_currency, balance_impact)
+    };
+    if let Some(filter) = row_filter {
+        lazy_frame = lazy_frame.filter(filter);
+    }
+
+    if let Some(filter) = source_filter {
+        // Formats without a type or payee column have an empty name in its place
+        for (column, excluded) in [
+            (columns_to_select[3], &filter.exclude_types),
+            (columns_to_select[4], &filter.exclude_payees),
+        ] {
+            if column.is_empty() || excluded.is_empty() {
+                continue;
+            }
+            let values: Vec<&str> = excluded.iter().map(String::as_str).collect();
+            // Rows with an empty value are kept
+            lazy_frame = lazy_frame.filter(any_of(column, &values).not().or(col(column).is_null()));
+        }
+    }
+
+    if options.date_basis == DateBasis::Value {
+        if let Some(value_column) = spec.value_date_column() {
+            // Swap the dates if the value date is already selected as a placeholder
+            let booking_column = columns_to_select[0];
+            for column in columns_to_select.iter_mut() {
+                if *column == value_column {
+                    *column = booking_column;
+                }
+            }
+            columns_to_select[0] = value_column;
+        }
+    }
+
+    // polars would only notice a missing column when collecting
+    if let Some(missing) = columns_to_select
+        .iter()
+        .copied()
+        .chain(additional_columns.iter().map(String::as_str))
+        .find(|column| !column.is_empty() && !column_names.contains(column))
+    {
+        return Err(BankCsvError::MissingColumn {
+            file_path: file_path.to_path_buf(),
+            column: missing.to_string(),
+            columns: column_names.iter().map(|name| name.to_string()).collect(),
+        });
+    }
+
+    let mut select_expressions: Vec<Expr> = columns_to_select
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            if column.is_empty() {
+                lit("").alias(&format!("empty_{}", index))
+            } else {
+                col(column)
+            }
+        })
         .collect();
+    // Aliases avoid clashes with columns that were already selected
+    select_expressions.extend(
+        additional_columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| col(column).alias(&format!("additional_{}", index))),
+    );
+    Ok(lazy_frame.select(select_expressions).collect()?)
+}
 
-    let columns_to_select: [&str; NUM_SELECT_COLUMNS];
-    let source: Source;
-    let lazy_frame: LazyFrame;
-    let cloned_df = df.clone();
-
-    // TODO: move these configs to separate structs or enums instead of "if" statements
-    if first_columns == PAYPAL_COLUMNS {
-        source = Source::PayPal;
-        columns_to_select = [
-            "Date",
-            "Currency",
-            "Gross",
-            "Type",
-            "Name",
-            "Transaction ID",
-        ];
-        lazy_frame = cloned_df
-            .lazy()
-            .filter(col("Currency").eq(lit(upper_currency.as_str())))
-            .filter(col("Balance Impact").eq(lit("Debit")))
-            .filter(col("Type").neq(lit("General Currency Conversion")));
-    } else if first_columns == PAYPAL_COLUMNS_OLD {
-        source = Source::PayPal;
-        columns_to_select = [
-            "Date",
-            "Currency",
-            "Gross",
-            "Description",
-            "Name",
-            "Transaction ID",
-        ];
-        lazy_frame = cloned_df
-            .lazy()
-            .filter(col("Currency").eq(lit(upper_currency.as_str())))
-            .filter(col("Description").neq(lit("General Currency Conversion")));
-    } else if first_columns == N26_COLUMNS || first_columns == N26_COLUMNS_2024_09 {
-        source = Source::N26;
-        let amount_column = if upper_currency == "EUR" {
-            "Amount (EUR)"
-        } else if first_columns == N26_COLUMNS {
-            "Amount (Foreign Currency)"
-        } else {
-            "Original Amount"
-        };
-        let currency_column;
-        if first_columns == N26_COLUMNS {
-            currency_column = "Type Foreign Currency";
-            columns_to_select = [
-                "Date",
-                currency_column,
-                amount_column,
-                "Transaction type",
-                "Payee",
-                "Payment reference",
-            ];
-        } else {
-            currency_column = "Original Currency";
-            columns_to_select = [
-                "Booking Date",
-                currency_column,
-                amount_column,
-                "Type",
-                "Partner Name",
-                "Payment Reference",
-            ];
-        }
-        lazy_frame = if upper_currency == "EUR" {
-            // For euros, select also rows with empty currency (N26 is not consistent)
-            cloned_df.lazy().filter(
-                col(currency_column)
-                    .eq(lit(upper_currency.as_str()))
-                    .or(col(currency_column).eq(lit("")))
-                    .or(col(currency_column).is_null()),
-            )
-        } else {
-            cloned_df
-                .lazy()
-                .filter(col(currency_column).eq(lit(upper_currency.as_str())))
-        }
-    } else if first_columns == DKB_COLUMNS {
-        source = Source::DKB;
-        columns_to_select = [
-            "Buchungstag",
-            // Use any non-duplicated column here, otherwise polars will panic with:
-            // "column with name 'Verwendungszweck' has more than one occurrence".
-            // The memo (Verwendungszweck = "intended use") contains the foreign currency.
-            // We will filter and replace the value of this column later.
-            "Mandatsreferenz",
-            "Betrag (EUR)",
-            "Buchungstext",
-            "Auftraggeber / Begünstigter",
-            "Verwendungszweck",
-        ];
-        // Filtering will be done manually because DKB doesn't have a currency column
-        lazy_frame = cloned_df.lazy()
-    } else if first_columns == DKB_COLUMNS_2024_09 {
-        source = Source::DKB;
-        columns_to_select = [
-            "Buchungsdatum",
-            // Use any non-duplicated column here, otherwise polars will panic with:
-            // "column with name 'Verwendungszweck' has more than one occurrence".
-            // The memo (Verwendungszweck = "intended use") contains the foreign currency.
-            // We will filter and replace the value of this column later.
-            "Mandatsreferenz",
-            "Betrag (€)",
-            "Umsatztyp",
-            "Zahlungsempfänger*in",
-            "Verwendungszweck",
-        ];
-        // Filtering will be done manually because DKB doesn't have a currency column
-        lazy_frame = cloned_df.lazy()
-    } else {
-        panic!(
-            "Unknown CSV format. These are the first columns: {:?}",
-            first_columns
-        );
+/// The placeholders in a template like `"{Type} | {Transaction ID}"`, in order of appearance
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::template_placeholders;
+/// assert_eq!(template_placeholders("{Type} | {Transaction ID}"), vec!["Type", "Transaction ID"]);
+/// assert_eq!(template_placeholders("no placeholders"), Vec::<&str>::new());
+/// ```
+pub fn template_placeholders(template: &str) -> Vec<&str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+        .collect()
+}
+
+/// Replace the placeholders of a template with their values; unknown placeholders become empty
+///
+/// The template is read once from left to right, so braces in the values are kept as they are.
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::render_template;
+/// use std::collections::HashMap;
+/// let values = HashMap::from([("memo", "Invoice 42".to_string()), ("Partner Iban", "DE89".to_string())]);
+/// assert_eq!(render_template("{memo} | {Partner Iban}", &values), "Invoice 42 | DE89");
+/// assert_eq!(render_template("{memo} {unknown}", &values), "Invoice 42 ");
+/// let values = HashMap::from([("memo", "{payee}".to_string()), ("payee", "Bakery".to_string())]);
+/// assert_eq!(render_template("{memo} {payee} {", &values), "{payee} Bakery {");
+/// ```
+pub fn render_template(template: &str, values: &HashMap<&str, String>) -> String {
+    let mut parts = template.split('{');
+    let mut rendered = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        match part.split_once('}') {
+            Some((placeholder, rest)) => {
+                rendered.push_str(values.get(placeholder).map_or("", String::as_str));
+                rendered.push_str(rest);
+            }
+            None => {
+                rendered.push('{');
+                rendered.push_str(part);
+            }
+        }
     }
+    rendered
+}
 
+/// Built-in payee cleanup rules for common merchants, as regular expressions and replacements;
+/// card payments often have a reference number or phone number after the merchant name
+pub const DEFAULT_PAYEE_RULES: [(&str, &str); 10] = [
     (
-        source,
-        lazy_frame
-            .select([cols(columns_to_select)])
-            .collect()
-            .unwrap(),
-    )
+        r"(?i)^(AMZN Mktp|AMZN|Amazon\.\w+|Amazon Marketplace|AMAZON PAYMENTS)\b.*",
+        "Amazon",
+    ),
+    (r"(?i)^NETFLIX\b.*", "Netflix"),
+    (r"(?i)^Spotify\b.*", "Spotify"),
+    (r"(?i)^UBER\s*\*?\s*(TRIP|EATS)?\b.*", "Uber"),
+    (r"(?i)^APPLE\.COM/BILL\b.*", "Apple"),
+    (r"(?i)^GOOGLE\s*\*\s*(\w+).*", "Google $1"),
+    (r"(?i)^PAYPAL\s*\*\s*(\w+).*", "$1"),
+    (r"(?i)^REWE\b.*", "REWE"),
+    (r"(?i)^LIDL\b.*", "Lidl"),
+    (r"(?i)^EDEKA\b.*", "EDEKA"),
+];
+
+/// Rewrite a payee with the first matching rule (a regular expression and its replacement,
+/// which can refer to capture groups as `$1`); payees without a matching rule are only trimmed
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::{clean_payee, DEFAULT_PAYEE_RULES};
+/// use regex::Regex;
+/// let rules: Vec<(Regex, String)> = DEFAULT_PAYEE_RULES
+///     .iter()
+///     .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement.to_string()))
+///     .collect();
+/// assert_eq!(clean_payee("AMZN Mktp DE*2K4T 800-279-6620", &rules), "Amazon");
+/// assert_eq!(clean_payee("NETFLIX.COM 866-579-7172", &rules), "Netflix");
+/// assert_eq!(clean_payee("GOOGLE *YouTube g.co/helppay#", &rules), "Google YouTube");
+/// assert_eq!(clean_payee(" Hausverwaltung Müller ", &rules), "Hausverwaltung Müller");
+/// ```
+pub fn clean_payee(payee: &str, rules: &[(Regex, String)]) -> String {
+    let payee = payee.trim();
+    rules
+        .iter()
+        .find(|(pattern, _)| pattern.is_match(payee))
+        .map_or(payee.to_string(), |(pattern, replacement)| {
+            pattern
+                .replace(payee, replacement.as_str())
+                .trim()
+                .to_string()
+        })
+}
+
+/// Canonical transaction type for types that are not in the mapping
+pub const OTHER_TYPE: &str = "other";
+
+/// Built-in mapping of the transaction types of each source to a canonical set:
+/// `income`, `payment`, `card_payment`, `direct_debit`, `transfer`, `refund`, `fee`, `interest` and `trade`.
+/// The same word can mean different things in different banks, so the types are only mapped for their sources
+const BUILT_IN_TYPE_MAPPINGS: [(&[Source], &[(&str, &str)]); 14] = [
+    (
+        &[Source::N26],
+        &[
+            ("Income", "income"),
+            ("Presentment", "card_payment"),
+            ("Presentment Refund", "refund"),
+            ("MasterCard Payment", "card_payment"),
+            ("Direct Debit", "direct_debit"),
+            ("Direct Debit Reversal", "refund"),
+            ("Outgoing Transfer", "transfer"),
+            ("Credit Transfer", "transfer"),
+            ("Debit Transfer", "transfer"),
+            ("N26 Referral", "income"),
+            // Before 2020
+            ("Maestro Payment", "card_payment"),
+            ("MoneyBeam", "transfer"),
+            ("Outgoing Transfer (SEPA)", "transfer"),
+            ("Incoming Transfer (SEPA)", "income"),
+        ],
+    ),
+    (
+        &[Source::DKB],
+        &[
+            ("Gutschrift", "income"),
+            ("Eingang", "income"),
+            ("Ausgang", "payment"),
+            ("Lastschrift", "direct_debit"),
+            ("Kartenzahlung", "card_payment"),
+            ("Überweisung", "transfer"),
+            ("Dauerauftrag", "transfer"),
+            ("Abschluss", "fee"),
+            ("Zinsen", "interest"),
+        ],
+    ),
+    (
+        &[Source::PayPal],
+        &[
+            ("Express Checkout Payment", "payment"),
+            ("General Payment", "payment"),
+            ("Mobile Payment", "payment"),
+            ("Website Payment", "payment"),
+            ("PreApproved Payment Bill User Payment", "direct_debit"),
+            ("Payment Refund", "refund"),
+            ("General Card Deposit", "transfer"),
+            ("Bank Deposit to PP Account", "transfer"),
+        ],
+    ),
+    (
+        &[Source::Kraken, Source::Coinbase],
+        &[
+            ("deposit", "transfer"),
+            ("withdrawal", "transfer"),
+            ("staking", "interest"),
+            ("Convert", "trade"),
+            ("Send", "transfer"),
+            ("Receive", "transfer"),
+            ("Rewards Income", "interest"),
+        ],
+    ),
+    // Coinbase, and the types of the securities exports
+    (
+        &[
+            Source::Coinbase,
+            Source::DKB,
+            Source::TradeRepublic,
+            Source::Comdirect,
+        ],
+        &[("Buy", "trade"), ("Sell", "trade"), ("Dividend", "income")],
+    ),
+    (
+        &[Source::Stripe],
+        &[
+            ("charge", "income"),
+            ("payment", "income"),
+            ("refund", "refund"),
+            ("payout", "transfer"),
+            ("stripe_fee", "fee"),
+        ],
+    ),
+    (
+        &[Source::Commerzbank],
+        &[("Zinsen/Entgelt", "fee"), ("Entgelt", "fee")],
+    ),
+    (
+        &[Source::Ing],
+        &[("Gehalt/Rente", "income"), ("Abbuchung", "direct_debit")],
+    ),
+    (
+        &[Source::Sparkasse],
+        &[
+            ("Folgelastschrift", "direct_debit"),
+            ("Erstlastschrift", "direct_debit"),
+            ("Online-Ueberweisung", "transfer"),
+            ("Gutschr. Ueberweisung", "income"),
+            ("Entgeltabschluss", "fee"),
+        ],
+    ),
+    (
+        &[Source::IngNl],
+        &[
+            ("Betaalautomaat", "card_payment"),
+            ("Incasso", "direct_debit"),
+            ("Overschrijving", "transfer"),
+            ("Online bankieren", "transfer"),
+            ("Verzamelbetaling", "transfer"),
+            ("iDEAL", "payment"),
+        ],
+    ),
+    (
+        &[Source::Monzo, Source::Starling],
+        &[
+            ("Card payment", "card_payment"),
+            ("Contactless", "card_payment"),
+            ("Online payment", "card_payment"),
+            ("Faster payment", "transfer"),
+            ("Pot transfer", "transfer"),
+        ],
+    ),
+    (
+        &[Source::Revolut],
+        &[
+            ("CARD_PAYMENT", "card_payment"),
+            ("CARD_REFUND", "refund"),
+            ("TOPUP", "transfer"),
+            ("EXCHANGE", "trade"),
+            ("FEE", "fee"),
+        ],
+    ),
+    (
+        &[Source::Wise],
+        &[
+            ("CARD", "card_payment"),
+            ("TRANSFER", "transfer"),
+            ("DIRECT_DEBIT", "direct_debit"),
+        ],
+    ),
+    (
+        &[Source::Apple],
+        &[
+            ("Purchase", "card_payment"),
+            ("Credit", "refund"),
+            ("Installment", "card_payment"),
+            ("Interest", "interest"),
+        ],
+    ),
+];
+
+/// Map a transaction type to a canonical one, with the custom mappings first and then the built-in ones
+/// of its source; types are compared without case. Unknown types are mapped to [`OTHER_TYPE`]
+///
+/// # Arguments
+///
+/// * `transaction_type`: the type of the transaction, as written by the source
+/// * `source`: the name of the source (e.g. `N26`), for the built-in mappings
+/// * `custom_mappings`: the mappings of the configuration; ordered, so that types differing only in case
+///   are always resolved the same way
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::normalize_type;
+/// use std::collections::BTreeMap;
+/// let custom = BTreeMap::from([("Dauerauftrag".to_string(), "rent".to_string())]);
+/// assert_eq!(normalize_type("Lastschrift", "DKB", &custom), "direct_debit");
+/// assert_eq!(normalize_type("Presentment", "N26", &custom), "card_payment");
+/// assert_eq!(normalize_type("DAUERAUFTRAG", "DKB", &custom), "rent");
+/// assert_eq!(normalize_type("stripe_fee", "Stripe", &custom), "fee");
+/// assert_eq!(normalize_type("payment", "Stripe", &custom), "income");
+/// assert_eq!(normalize_type("payment", "Monzo", &custom), "other");
+/// assert_eq!(normalize_type("Something new", "N26", &custom), "other");
+/// ```
+pub fn normalize_type(
+    transaction_type: &str,
+    source: &str,
+    custom_mappings: &BTreeMap<String, String>,
+) -> String {
+    let transaction_type = transaction_type.trim().to_lowercase();
+    let built_in = BUILT_IN_TYPE_MAPPINGS
+        .iter()
+        .filter(|(sources, _)| sources.iter().any(|known| known.to_string() == source))
+        .flat_map(|(_, mappings)| mappings.iter().copied());
+    custom_mappings
+        .iter()
+        .map(|(original, canonical)| (original.as_str(), canonical.as_str()))
+        .chain(built_in)
+        .find(|(original, _)| original.to_lowercase() == transaction_type)
+        .map_or(OTHER_TYPE, |(_, canonical)| canonical)
+        .to_string()
 }
 
-/// Extract the amount from a DKB memo
+/// Pattern for invoice numbers like "RE-2024-001", "RG123456" or "INV-42",
+/// used when no patterns are configured
+pub const DEFAULT_INVOICE_PATTERN: &str = r"\b(?:RE|RG|INV)-?\d+(?:-\d+)*\b";
+
+/// Extract an invoice or reference number from a memo, with the first pattern that matches
+///
+/// If a pattern has a capture group, only the first group is returned, otherwise the whole match.
 ///
 /// # Arguments
 ///
-/// * `currency`: 3-letter currency code
 /// * `memo`: The memo or description of the transaction
+/// * `patterns`: Regular expressions to try, in order
 ///
 /// returns: `Option<String>`
 ///
 /// # Examples
 ///
 /// ```
-/// use bank_csv::dkb_extract_amount;
-/// assert_eq!(dkb_extract_amount("BRL", "2023-12-12      Debitk.44 Original 6,99 BRL 1 Euro=5,29545460 BRL VISA Debit"), Some("6,99".to_string()));
-/// assert_eq!(dkb_extract_amount("BRL", "Nothing here"), None);
-/// assert_eq!(dkb_extract_amount("BRL", "VISA Debitkartenumsatz in Fremdwährung / Ursprungsbetrag in Fremdwährung 19,90 BRL / Umrechnungsrate: 1 Euro=6,03030470 BRL"), Some("19,90".to_string()));
-pub fn dkb_extract_amount(currency: &str, memo: &str) -> Option<String> {
-    if !memo.contains(" 1 Euro=") {
-        return None;
+/// use bank_csv::{extract_invoice_number, DEFAULT_INVOICE_PATTERN};
+/// use regex::Regex;
+/// let patterns = [Regex::new(DEFAULT_INVOICE_PATTERN).unwrap()];
+/// assert_eq!(extract_invoice_number("Zahlung RE-2024-001 Danke", &patterns), Some("RE-2024-001".to_string()));
+/// assert_eq!(extract_invoice_number("RG123456", &patterns), Some("RG123456".to_string()));
+/// assert_eq!(extract_invoice_number("PREIS 123", &patterns), None);
+/// let custom = [Regex::new(r"Kundennr\. (\d+)").unwrap()];
+/// assert_eq!(extract_invoice_number("Kundennr. 98765 Mai", &custom), Some("98765".to_string()));
+/// ```
+pub fn extract_invoice_number(memo: &str, patterns: &[Regex]) -> Option<String> {
+    patterns.iter().find_map(|pattern| {
+        let captures = pattern.captures(memo)?;
+        captures
+            .get(1)
+            .or_else(|| captures.get(0))
+            .map(|found| found.as_str().to_string())
+    })
+}
+
+/// Number of characters left visible at the end of masked personal data
+const PII_VISIBLE_CHARS: usize = 4;
+
+/// Patterns of personal data in memos: mandate references (the value is in the second group),
+/// IBANs and card numbers. Mandate references come first, because their values can look like card numbers.
+fn pii_patterns() -> &'static [Regex; 3] {
+    static PATTERNS: OnceLock<[Regex; 3]> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            Regex::new(
+                r"(?i)\b(mandatsreferenz|mandate reference|mref)[:.]?\s*([A-Z0-9][A-Z0-9-]{4,})",
+            )
+            .unwrap(),
+            Regex::new(r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,3})?\b").unwrap(),
+            Regex::new(r"\b\d(?:[ -]?\d){11,18}\b").unwrap(),
+        ]
+    })
+}
+
+/// Replace all letters and digits of a text with "*", except the last ones; separators are kept
+fn mask_keeping_last(text: &str, visible: usize) -> String {
+    let total = text.chars().filter(char::is_ascii_alphanumeric).count();
+    let mut seen = 0;
+    text.chars()
+        .map(|c| {
+            if !c.is_ascii_alphanumeric() {
+                return c;
+            }
+            seen += 1;
+            if seen > total.saturating_sub(visible) {
+                c
+            } else {
+                '*'
+            }
+        })
+        .collect()
+}
+
+/// Mask IBANs, card numbers and mandate references in a memo, keeping only their last 4 characters
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::mask_pii;
+/// assert_eq!(mask_pii("Transfer from DE89370400440532013000"), "Transfer from ******************3000");
+/// assert_eq!(mask_pii("IBAN DE89 3704 0044 0532 0130 00 thanks"), "IBAN **** **** **** **** **30 00 thanks");
+/// assert_eq!(mask_pii("Card 4111 1111 1111 1234"), "Card **** **** **** 1234");
+/// assert_eq!(mask_pii("Mandatsreferenz: M-2024-778899 Gläubiger-ID"), "Mandatsreferenz: *-****-**8899 Gläubiger-ID");
+/// assert_eq!(mask_pii("Invoice 2024-001"), "Invoice 2024-001");
+/// ```
+pub fn mask_pii(memo: &str) -> String {
+    let [mandate, iban, card] = pii_patterns();
+    let masked = mandate.replace_all(memo, |captures: &regex::Captures| {
+        let value = &captures[2];
+        let keyword_and_separator = &captures[0][..captures[0].len() - value.len()];
+        format!(
+            "{}{}",
+            keyword_and_separator,
+            mask_keeping_last(value, PII_VISIBLE_CHARS)
+        )
+    });
+    let masked = iban.replace_all(&masked, |captures: &regex::Captures| {
+        mask_keeping_last(&captures[0], PII_VISIBLE_CHARS)
+    });
+    card.replace_all(&masked, |captures: &regex::Captures| {
+        mask_keeping_last(&captures[0], PII_VISIBLE_CHARS)
+    })
+    .into_owned()
+}
+
+/// Number of hexadecimal characters of the HMAC used in a pseudonym
+const PSEUDONYM_LENGTH: usize = 8;
+
+/// Replace a payee with a stable pseudonym, derived from an HMAC-SHA256 of the payee with a secret key
+///
+/// The same payee (ignoring case and surrounding spaces) and key always give the same pseudonym,
+/// so datasets stay consistent across runs. Empty payees are kept empty.
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::pseudonymize;
+/// let pseudonym = pseudonymize("Bakery Müller", "secret");
+/// assert!(pseudonym.starts_with("Payee "));
+/// assert_eq!(pseudonym.len(), "Payee ".len() + 8);
+/// assert_eq!(pseudonymize(" BAKERY MÜLLER", "secret"), pseudonym);
+/// assert_ne!(pseudonymize("Bakery Müller", "other key"), pseudonym);
+/// assert_eq!(pseudonymize("", "secret"), "");
+/// ```
+pub fn pseudonymize(payee: &str, key: &str) -> String {
+    let normalized = payee.trim().to_lowercase();
+    if normalized.is_empty() {
+        return String::new();
     }
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(normalized.as_bytes());
+    let hash: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("Payee {}", &hash[..PSEUDONYM_LENGTH])
+}
 
-    let original_keyword = "Original ";
-    let fremdwaehrung = "Ursprungsbetrag in Fremdwährung ";
-    let start;
-    let word_length;
-    if memo.contains(original_keyword) {
-        start = memo.find(original_keyword)?;
-        word_length = original_keyword.len();
-    } else if memo.contains(fremdwaehrung) {
-        start = memo.find(fremdwaehrung)?;
-        word_length = fremdwaehrung.len();
-    } else {
-        eprintln!("Could not extract amount from DKB memo: {}", memo);
-        return None;
+/// Words that mark a VAT rate in a memo, in lowercase
+const VAT_KEYWORDS: [&str; 5] = ["mwst", "ust", "vat", "mehrwertsteuer", "umsatzsteuer"];
+/// Maximum number of characters between a percentage and a VAT keyword
+const VAT_KEYWORD_DISTANCE: usize = 20;
+
+/// Extract the VAT rate from a memo like "inkl. 19% MwSt" and calculate the VAT included in the amount
+///
+/// # Arguments
+///
+/// * `memo`: The memo or description of the transaction
+/// * `amount`: The gross amount of the transaction, with a decimal comma or dot
+///
+/// returns: `Option<(String, String)>` with the rate in percent and the VAT amount
+/// (empty if the amount is not a number)
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::extract_vat;
+/// assert_eq!(extract_vat("Rechnung 2024-17 inkl. 19% MwSt", "-119,00"), Some(("19".to_string(), "-19,00".to_string())));
+/// assert_eq!(extract_vat("darin enthalten 7 % USt.", "10,70"), Some(("7".to_string(), "0,70".to_string())));
+/// assert_eq!(extract_vat("VAT 20%", "abc"), Some(("20".to_string(), "".to_string())));
+/// assert_eq!(extract_vat("10% Rabatt im August", "-50,00"), None);
+/// ```
+pub fn extract_vat(memo: &str, amount: &str) -> Option<(String, String)> {
+    let chars: Vec<char> = memo.to_lowercase().chars().collect();
+    for (percent_index, _) in chars.iter().enumerate().filter(|(_, c)| **c == '%') {
+        let mut rate_start = percent_index;
+        while rate_start > 0 && chars[rate_start - 1] == ' ' {
+            rate_start -= 1;
+        }
+        let rate_end = rate_start;
+        while rate_start > 0
+            && (chars[rate_start - 1].is_ascii_digit() || chars[rate_start - 1] == ',')
+        {
+            rate_start -= 1;
+        }
+        let rate: String = chars[rate_start..rate_end].iter().collect();
+        let Ok(rate_value) = rate.replace(CHAR_COMMA, ".").parse::<f64>() else {
+            continue;
+        };
+        if rate_value <= 0.0 || rate_value >= 100.0 {
+            continue;
+        }
+
+        // A VAT keyword must be close to the percentage, as a separate word
+        let window_start = rate_start.saturating_sub(VAT_KEYWORD_DISTANCE);
+        let window_end = (percent_index + 1 + VAT_KEYWORD_DISTANCE).min(chars.len());
+        let window: String = chars[window_start..window_end].iter().collect();
+        let has_keyword = VAT_KEYWORDS.iter().any(|keyword| {
+            window.match_indices(keyword).any(|(index, _)| {
+                !window[..index]
+                    .chars()
+                    .next_back()
+                    .is_some_and(char::is_alphabetic)
+            })
+        });
+        if !has_keyword {
+            continue;
+        }
+
+        let vat_amount = amount
+            .replace(CHAR_COMMA, ".")
+            .parse::<f64>()
+            .map(|gross| {
+                format!("{:.2}", gross * rate_value / (100.0 + rate_value))
+                    .replace(CHAR_DOT, CHAR_COMMA)
+            })
+            .unwrap_or_default();
+        return Some((rate, vat_amount));
     }
+    None
+}
 
-    let end = memo.find(currency)?;
-    if end <= start {
-        return None;
+/// The net amount of a transaction: the gross amount without the VAT included in it
+///
+/// returns: String with a decimal comma; the gross amount as it is when there is no VAT,
+/// empty if an amount is not a number
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::net_amount;
+/// assert_eq!(net_amount("-119,00", "-19,00"), "-100,00");
+/// assert_eq!(net_amount("10.70", "0,70"), "10,00");
+/// assert_eq!(net_amount("-25,00", ""), "-25,00");
+/// assert_eq!(net_amount("abc", "1,00"), "");
+/// ```
+pub fn net_amount(amount: &str, vat_amount: &str) -> String {
+    let parse = |value: &str| value.replace(CHAR_COMMA, ".").parse::<f64>();
+    if vat_amount.is_empty() {
+        return amount.replace(CHAR_DOT, CHAR_COMMA);
     }
+    match (parse(amount), parse(vat_amount)) {
+        (Ok(gross), Ok(vat)) => format!("{:.2}", gross - vat).replace(CHAR_DOT, CHAR_COMMA),
+        _ => String::new(),
+    }
+}
 
-    let amount_start = start + word_length;
-    let amount = &memo[amount_start..end].trim();
+/// How much two amounts may differ and still be considered the same transaction,
+/// e.g. because of rounding in currency conversions; only used to find duplicates
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum AmountTolerance {
+    /// Amounts must be exactly the same
+    #[default]
+    Exact,
+    /// Maximum absolute difference, e.g. `0.05`
+    Absolute(f64),
+    /// Maximum difference in percent of the larger amount, e.g. `1%`
+    Percentage(f64),
+}
 
-    Some(amount.to_string())
+impl AmountTolerance {
+    /// Check if two amounts are equal within the tolerance
+    ///
+    /// Amounts can have a decimal comma or dot; amounts that are not numbers are only equal
+    /// if the strings are the same.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::AmountTolerance;
+    /// let absolute: AmountTolerance = "0.05".parse().unwrap();
+    /// assert!(absolute.matches("-10,00", "-10,04"));
+    /// assert!(!absolute.matches("-10,00", "-10,10"));
+    /// let percentage: AmountTolerance = "1%".parse().unwrap();
+    /// assert!(percentage.matches("200.00", "198.50"));
+    /// assert!(!percentage.matches("200.00", "-200.00"));
+    /// assert!(AmountTolerance::Exact.matches("1.5", "1.50"));
+    /// assert!("NaN".parse::<AmountTolerance>().is_err());
+    /// assert!("inf%".parse::<AmountTolerance>().is_err());
+    /// ```
+    pub fn matches(&self, amount: &str, other: &str) -> bool {
+        let parse = |value: &str| value.replace(CHAR_COMMA, ".").parse::<f64>();
+        let (Ok(amount_value), Ok(other_value)) = (parse(amount), parse(other)) else {
+            return amount == other;
+        };
+        let difference = (amount_value - other_value).abs();
+        match self {
+            AmountTolerance::Exact => difference == 0.0,
+            AmountTolerance::Absolute(max) => difference <= *max,
+            AmountTolerance::Percentage(percent) => {
+                difference <= amount_value.abs().max(other_value.abs()) * percent / 100.0
+            }
+        }
+    }
 }
 
-/// A row in the CSV output
-#[derive(PartialEq, Eq)]
-pub struct CsvOutputRow {
-    /// The date of the transaction
-    pub date: NaiveDate,
-    /// The source of the transaction (PayPal, N26, DKB)
-    pub source: String,
-    /// The currency of the transaction, 3 letters (EUR, USD, ...)
-    pub currency: String,
-    /// The amount of the transaction
-    pub amount: String,
-    /// The type of the transaction, read from the original CSV
-    pub transaction_type: String,
-    /// The payee of the transaction
-    pub payee: String,
-    /// The memo or description of the transaction
-    pub memo: String,
+impl std::str::FromStr for AmountTolerance {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (number, percentage) = match value.strip_suffix('%') {
+            Some(number) => (number.trim(), true),
+            None => (value, false),
+        };
+        let tolerance: f64 = number
+            .replace(',', ".")
+            .parse()
+            .ok()
+            .filter(|tolerance: &f64| tolerance.is_finite())
+            .ok_or_else(|| format!("invalid amount tolerance: {}", value))?;
+        if tolerance < 0.0 {
+            return Err(format!("amount tolerance can't be negative: {}", value));
+        }
+        Ok(if percentage {
+            AmountTolerance::Percentage(tolerance)
+        } else {
+            AmountTolerance::Absolute(tolerance)
+        })
+    }
 }
 
-impl PartialOrd for CsvOutputRow {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl TryFrom<String> for AmountTolerance {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
     }
 }
 
-impl Ord for CsvOutputRow {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match self.date.cmp(&other.date) {
-            Ordering::Equal => match self.currency.cmp(&other.currency) {
-                Ordering::Equal => match self.amount.cmp(&other.amount) {
-                    Ordering::Equal => match self.transaction_type.cmp(&other.transaction_type) {
-                        Ordering::Equal => self.payee.cmp(&other.payee),
-                        other => other,
-                    },
-                    other => other,
-                },
-                other => other,
-            },
-            other => other,
+/// Which date of a transaction is used, when the source has more than one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateBasis {
+    /// Booking date (Buchungstag), when the bank registered the transaction
+    #[default]
+    Booking,
+    /// Value date (Wertstellung), when the money was actually moved; used in bank statements
+    Value,
+}
+
+/// Balance impact of a PayPal transaction, used to select which rows are read
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BalanceImpact {
+    /// Money going out: payments and withdrawals
+    Debit,
+    /// Money coming in: refunds and incoming payments
+    Credit,
+    /// No impact on the balance: holds and authorizations
+    Memo,
+}
+
+impl BalanceImpact {
+    /// Values of the "Balance Impact" column in the English and German exports
+    pub fn values(self) -> &'static [&'static str] {
+        match self {
+            BalanceImpact::Debit => &PAYPAL_DEBIT,
+            BalanceImpact::Credit => &PAYPAL_CREDIT,
+            BalanceImpact::Memo => &PAYPAL_MEMO,
         }
     }
 }
 
-impl fmt::Display for CsvOutputRow {
+/// Period of the output files: each file has the transactions of one period
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    /// One file per calendar month
+    #[default]
+    Month,
+    /// One file per ISO week (Monday to Sunday), e.g. for weekly invoicing
+    Week,
+    /// One file per calendar quarter, e.g. for the Umsatzsteuervoranmeldung
+    Quarter,
+}
+
+impl GroupBy {
+    /// The period of a date, used in the output file names: `2024-01`, `2024-W05` or `2024-Q1`.
+    /// ISO weeks belong to the year of their Thursday, so the first days of January may be in the last week of the previous year
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::GroupBy;
+    /// use chrono::NaiveDate;
+    /// let date = NaiveDate::from_ymd_opt(2024, 12, 30).unwrap();
+    /// assert_eq!(GroupBy::Month.period(date), "2024-12");
+    /// assert_eq!(GroupBy::Week.period(date), "2025-W01");
+    /// assert_eq!(GroupBy::Quarter.period(date), "2024-Q4");
+    /// ```
+    pub fn period(self, date: NaiveDate) -> String {
+        match self {
+            GroupBy::Month => date.format("%Y-%m").to_string(),
+            GroupBy::Week => {
+                let week = date.iso_week();
+                format!("{:04}-W{:02}", week.year(), week.week())
+            }
+            GroupBy::Quarter => format!("{:04}-Q{}", date.year(), date.month0() / 3 + 1),
+        }
+    }
+}
+
+/// An account name as part of a file name: runs of anything other than letters and digits become a dash
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::file_name_part;
+/// assert_eq!(file_name_part("Main Account"), "Main-Account");
+/// assert_eq!(file_name_part(" Spaces / Travel (2024) "), "Spaces-Travel-2024");
+/// ```
+pub fn file_name_part(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Language of the column names in the CSV output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// English
+    #[default]
+    En,
+    /// German
+    De,
+}
+
+/// Optional columns of the CSV output, written after the standard ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExtraColumn {
+    /// Time of the transaction, for sources that provide it (PayPal, Kraken, ...)
+    Time,
+    /// Label of the account, given in the manifest of the inputs
+    Account,
+    /// Transaction ID given by the source (PayPal, Stripe, Kraken), to trace rows back to it
+    Id,
+    /// ISIN of a security (securities mode)
+    Isin,
+    /// Number of shares or nominal value of a security (securities mode)
+    Quantity,
+    /// Fees paid for a securities transaction (securities mode)
+    Fee,
+    /// VAT rate in percent, extracted from the memo (VAT mode)
+    VatRate,
+    /// VAT included in the amount, calculated from the rate (VAT mode)
+    VatAmount,
+    /// Amount without VAT (EÜR profile)
+    NetAmount,
+    /// Amount with VAT, the same as the amount (EÜR profile)
+    GrossAmount,
+    /// Invoice or reference number extracted from the memo
+    Invoice,
+    /// Transaction type mapped to a small canonical set, see [`normalize_type`]
+    NormalizedType,
+    /// Free-form tags assigned by rules, separated by semicolons
+    Tags,
+    /// Category suggested by the classifier, see [`classify`]
+    SuggestedCategory,
+    /// Probability of the suggested category, between 0 and 1
+    Confidence,
+    /// Amount in euros of a DKB card payment in a foreign currency
+    AmountEur,
+    /// Amount in the foreign currency of a DKB card payment, with its currency
+    OriginalAmount,
+    /// Exchange rate of a DKB card payment in a foreign currency (foreign currency per euro)
+    Rate,
+}
+
+impl ExtraColumn {
+    /// All optional columns
+    pub const ALL: [ExtraColumn; 18] = [
+        ExtraColumn::Time,
+        ExtraColumn::Account,
+        ExtraColumn::Id,
+        ExtraColumn::Isin,
+        ExtraColumn::Quantity,
+        ExtraColumn::Fee,
+        ExtraColumn::VatRate,
+        ExtraColumn::VatAmount,
+        ExtraColumn::NetAmount,
+        ExtraColumn::GrossAmount,
+        ExtraColumn::Invoice,
+        ExtraColumn::NormalizedType,
+        ExtraColumn::Tags,
+        ExtraColumn::SuggestedCategory,
+        ExtraColumn::Confidence,
+        ExtraColumn::AmountEur,
+        ExtraColumn::OriginalAmount,
+        ExtraColumn::Rate,
+    ];
+
+    /// Columns added to the output with the conversions of DKB card payments in foreign currencies
+    pub const DKB_CONVERSIONS: [ExtraColumn; 3] = [
+        ExtraColumn::AmountEur,
+        ExtraColumn::OriginalAmount,
+        ExtraColumn::Rate,
+    ];
+
+    /// Columns added to the output in securities mode
+    pub const SECURITIES: [ExtraColumn; 3] =
+        [ExtraColumn::Isin, ExtraColumn::Quantity, ExtraColumn::Fee];
+
+    /// Columns added to the output in VAT mode
+    pub const VAT: [ExtraColumn; 2] = [ExtraColumn::VatRate, ExtraColumn::VatAmount];
+
+    /// Columns added to the output with the EÜR profile, in the order of simple EÜR spreadsheets
+    pub const EUER: [ExtraColumn; 4] = [
+        ExtraColumn::NetAmount,
+        ExtraColumn::VatRate,
+        ExtraColumn::VatAmount,
+        ExtraColumn::GrossAmount,
+    ];
+
+    /// The column name in the CSV header
+    pub fn label(&self, locale: Locale) -> &'static str {
+        match (self, locale) {
+            (ExtraColumn::Time, Locale::En) => "Time",
+            (ExtraColumn::Time, Locale::De) => "Uhrzeit",
+            (ExtraColumn::Account, Locale::En) => "Account",
+            (ExtraColumn::Account, Locale::De) => "Konto",
+            (ExtraColumn::Id, _) => "ID",
+            (ExtraColumn::Isin, _) => "ISIN",
+            (ExtraColumn::Quantity, Locale::En) => "Quantity",
+            (ExtraColumn::Quantity, Locale::De) => "Stück",
+            (ExtraColumn::Fee, Locale::En) => "Fee",
+            (ExtraColumn::Fee, Locale::De) => "Gebühr",
+            (ExtraColumn::VatRate, Locale::En) => "VAT Rate",
+            (ExtraColumn::VatRate, Locale::De) => "MwSt-Satz",
+            (ExtraColumn::VatAmount, Locale::En) => "VAT",
+            (ExtraColumn::VatAmount, Locale::De) => "MwSt",
+            (ExtraColumn::NetAmount, Locale::En) => "Net Amount",
+            (ExtraColumn::NetAmount, Locale::De) => "Netto",
+            (ExtraColumn::GrossAmount, Locale::En) => "Gross Amount",
+            (ExtraColumn::GrossAmount, Locale::De) => "Brutto",
+            (ExtraColumn::Invoice, Locale::En) => "Invoice",
+            (ExtraColumn::Invoice, Locale::De) => "Rechnung",
+            (ExtraColumn::NormalizedType, Locale::En) => "Normalized Type",
+            (ExtraColumn::NormalizedType, Locale::De) => "Kategorie",
+            (ExtraColumn::Tags, _) => "Tags",
+            (ExtraColumn::SuggestedCategory, Locale::En) => "Suggested Category",
+            (ExtraColumn::SuggestedCategory, Locale::De) => "Kategorievorschlag",
+            (ExtraColumn::Confidence, Locale::En) => "Confidence",
+            (ExtraColumn::Confidence, Locale::De) => "Konfidenz",
+            (ExtraColumn::AmountEur, Locale::En) => "Amount EUR",
+            (ExtraColumn::AmountEur, Locale::De) => "Betrag EUR",
+            (ExtraColumn::OriginalAmount, Locale::En) => "Original Amount",
+            (ExtraColumn::OriginalAmount, Locale::De) => "Originalbetrag",
+            (ExtraColumn::Rate, Locale::En) => "Rate",
+            (ExtraColumn::Rate, Locale::De) => "Kurs",
+        }
+    }
+
+    /// Find the column with this name in the CSV header, in any language
+    pub fn from_label(label: &str) -> Option<Self> {
+        ExtraColumn::ALL
+            .into_iter()
+            .find(|column| column.label(Locale::En) == label || column.label(Locale::De) == label)
+    }
+}
+
+/// A row in the CSV output
+#[derive(Clone)]
+pub struct CsvOutputRow {
+    /// The date of the transaction
+    pub date: NaiveDate,
+    /// The source of the transaction (PayPal, N26, DKB)
+    pub source: String,
+    /// The currency of the transaction, 3 letters (EUR, USD, ...)
+    pub currency: String,
+    /// The amount of the transaction
+    pub amount: String,
+    /// The type of the transaction, read from the original CSV
+    pub transaction_type: String,
+    /// The payee of the transaction
+    pub payee: String,
+    /// The memo or description of the transaction
+    pub memo: String,
+    /// Values of the optional columns, only written if the column is part of the output
+    pub extra: BTreeMap<ExtraColumn, String>,
+    /// Position in the original files: index of the input file and of the row in the file.
+    /// Not used to compare or order rows; see [`sort_in_original_order`]
+    pub sequence: Option<(usize, usize)>,
+}
+
+// The position is left out, so a transaction read again (e.g. from an existing output file) is equal
+impl PartialEq for CsvOutputRow {
+    fn eq(&self, other: &Self) -> bool {
+        (
+            self.date,
+            &self.source,
+            &self.currency,
+            &self.amount,
+            &self.transaction_type,
+            &self.payee,
+            &self.memo,
+            &self.extra,
+        ) == (
+            other.date,
+            &other.source,
+            &other.currency,
+            &other.amount,
+            &other.transaction_type,
+            &other.payee,
+            &other.memo,
+            &other.extra,
+        )
+    }
+}
+
+impl Eq for CsvOutputRow {}
+
+/// Sort transactions by date and time, and then in the order of the original files,
+/// instead of by amount and payee; rows without a position (e.g. from previous output files) come first.
+/// The sort is stable, so split rows keep their order
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::{sort_in_original_order, CsvOutputRow};
+/// use chrono::NaiveDate;
+/// let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+/// let mut builder = CsvOutputRow::builder(date);
+/// let mut coffee = builder.amount("-3,50").build();
+/// let mut rent = builder.amount("-900").build();
+/// coffee.sequence = Some((0, 2));
+/// rent.sequence = Some((0, 1));
+/// let mut rows = vec![&coffee, &rent];
+/// sort_in_original_order(&mut rows);
+/// assert_eq!(rows[0].amount, "-900");
+/// ```
+pub fn sort_in_original_order(transactions: &mut [&CsvOutputRow]) {
+    transactions.sort_by_key(|transaction| {
+        (
+            transaction.date,
+            transaction.extra.get(&ExtraColumn::Time).cloned(),
+            transaction.sequence,
+        )
+    });
+}
+
+impl PartialOrd for CsvOutputRow {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Consistent with `PartialEq`: rows are only equal if all their fields are, the position is left out
+impl Ord for CsvOutputRow {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Rows without a time come first on their day
+        let time = self.extra.get(&ExtraColumn::Time);
+        let other_time = other.extra.get(&ExtraColumn::Time);
+        self.date
+            .cmp(&other.date)
+            .then_with(|| time.cmp(&other_time))
+            .then_with(|| self.currency.cmp(&other.currency))
+            .then_with(|| self.amount.cmp(&other.amount))
+            .then_with(|| self.transaction_type.cmp(&other.transaction_type))
+            .then_with(|| self.payee.cmp(&other.payee))
+            .then_with(|| self.source.cmp(&other.source))
+            .then_with(|| self.memo.cmp(&other.memo))
+            .then_with(|| self.extra.cmp(&other.extra))
+    }
+}
+
+/// Transactions kept sorted as they are added
+///
+/// Identical transactions (all fields equal, e.g. two coffees of the same price on the same day)
+/// are all kept, in the order they were added; in dedupe mode, only the first one is kept.
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::TransactionSet;
+/// let mut coffees = TransactionSet::new(false);
+/// assert!(coffees.push("-3,50"));
+/// assert!(coffees.push("-3,50"));
+/// assert_eq!(coffees.len(), 2);
+/// let mut deduped = TransactionSet::new(true);
+/// assert!(deduped.push("-3,50"));
+/// assert!(!deduped.push("-3,50"));
+/// assert_eq!(deduped.len(), 1);
+/// ```
+///
+/// Transactions that only differ in the memo are not identical, e.g. the parts of a 50/50 split:
+///
+/// ```
+/// use bank_csv::split::SplitRule;
+/// use bank_csv::{CsvOutputRow, TransactionSet};
+/// use chrono::NaiveDate;
+/// let rule: SplitRule = toml::from_str(r#"
+///     payee = "hausverwaltung"
+///     parts = [{ fraction = 0.5, label = "housing" }, { fraction = 0.5, label = "office" }]
+/// "#).unwrap();
+/// let rent = CsvOutputRow::builder(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())
+///     .amount("-1000,00")
+///     .payee("Hausverwaltung GmbH")
+///     .memo("Miete Januar")
+///     .build();
+/// let mut deduped = TransactionSet::new(true);
+/// for part in rule.split(&rent) {
+///     assert!(deduped.push(part));
+/// }
+/// assert_eq!(deduped.len(), 2);
+/// assert_eq!(deduped[0].amount, "-500,00");
+/// assert_eq!(deduped[1].amount, "-500,00");
+/// assert!(!deduped.push(rule.split(&rent)[1].clone()));
+/// ```
+#[derive(Clone)]
+pub struct TransactionSet<T: Ord> {
+    rows: Vec<T>,
+    dedupe: bool,
+}
+
+impl<T: Ord> TransactionSet<T> {
+    /// Create an empty set; with `dedupe`, identical transactions are collapsed into one
+    pub fn new(dedupe: bool) -> Self {
+        Self {
+            rows: Vec::new(),
+            dedupe,
+        }
+    }
+
+    /// Add a transaction after the ones equal to it
+    ///
+    /// returns: false if the transaction was dropped as a duplicate in dedupe mode
+    pub fn push(&mut self, row: T) -> bool {
+        let index = self.rows.partition_point(|existing| *existing <= row);
+        if self.dedupe && index > 0 && self.rows[index - 1].cmp(&row) == Ordering::Equal {
+            return false;
+        }
+        self.rows.insert(index, row);
+        true
+    }
+}
+
+// Identical transactions are kept unless asked otherwise
+impl<T: Ord> Default for TransactionSet<T> {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl<T: Ord> Deref for TransactionSet<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.rows
+    }
+}
+
+impl fmt::Display for CsvOutputRow {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let direction = if self.amount.contains('-') {
             "paid to"
@@ -406,17 +1894,705 @@ impl fmt::Display for CsvOutputRow {
     }
 }
 
+/// Maximum width of the text columns in the preview table; longer texts are cut
+const PREVIEW_MAX_TEXT_WIDTH: usize = 30;
+
+/// Cut a text to a maximum number of characters, ending with "…" when it was cut
+fn truncate(text: &str, max_width: usize) -> String {
+    if text.chars().count() <= max_width {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_width - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Render transactions as an aligned table, with only the first and last `limit` rows
+///
+/// # Arguments
+///
+/// * `transactions`: the transactions to show, already sorted
+/// * `locale`: language of the column names
+/// * `limit`: number of rows to show at the beginning and at the end of the table
+///
+/// returns: String with one line per row, without a trailing newline
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::{preview_table, CsvOutputRow, Locale};
+/// use chrono::NaiveDate;
+/// colored::control::set_override(false);
+/// let transactions: Vec<CsvOutputRow> = (1..=5)
+///     .map(|day| CsvOutputRow::new(
+///         NaiveDate::from_ymd_opt(2024, 1, day).unwrap(),
+///         "N26".to_string(),
+///         "EUR".to_string(),
+///         format!("-{}.00", day),
+///         "Debit".to_string(),
+///         "Bakery".to_string(),
+///         String::new(),
+///     ))
+///     .collect();
+/// let references: Vec<&CsvOutputRow> = transactions.iter().collect();
+/// let table = preview_table(&references, Locale::En, 2);
+/// let lines: Vec<&str> = table.lines().collect();
+/// assert_eq!(lines[0], "Date        Source  Currency  Amount  Type   Payee   Memo");
+/// assert_eq!(lines[2], "2024-01-01  N26     EUR        -1,00  Debit  Bakery");
+/// assert_eq!(lines[4], "… 1 more transaction(s)");
+/// assert_eq!(lines.len(), 7);
+/// ```
+pub fn preview_table(transactions: &[&CsvOutputRow], locale: Locale, limit: usize) -> String {
+    let hidden = transactions.len().saturating_sub(2 * limit);
+    let shown: Vec<&CsvOutputRow> = if hidden == 0 {
+        transactions.to_vec()
+    } else {
+        transactions[..limit]
+            .iter()
+            .chain(&transactions[transactions.len() - limit..])
+            .copied()
+            .collect()
+    };
+    let header = CsvOutputRow::header(locale, &[]);
+    let rows: Vec<[String; NUM_OUTPUT_COLUMNS]> = shown
+        .iter()
+        .map(|transaction| {
+            [
+                transaction.date.to_string(),
+                transaction.source.clone(),
+                transaction.currency.clone(),
+                transaction.amount.clone(),
+                truncate(&transaction.transaction_type, PREVIEW_MAX_TEXT_WIDTH),
+                truncate(&transaction.payee, PREVIEW_MAX_TEXT_WIDTH),
+                truncate(&transaction.memo, PREVIEW_MAX_TEXT_WIDTH),
+            ]
+        })
+        .collect();
+    let mut widths = [0; NUM_OUTPUT_COLUMNS];
+    for (index, width) in widths.iter_mut().enumerate() {
+        *width = rows
+            .iter()
+            .map(|row| row[index].chars().count())
+            .chain(header.get(index).map(|name| name.chars().count()))
+            .max()
+            .unwrap_or_default();
+    }
+
+    let format_line = |cells: Vec<String>| cells.join("  ").trim_end().to_string();
+    let mut lines = vec![
+        format_line(
+            header
+                .iter()
+                .zip(widths)
+                .map(|(name, width)| format!("{:<width$}", name))
+                .collect(),
+        ),
+        format_line(widths.iter().map(|width| "-".repeat(*width)).collect()),
+    ];
+    for (row_index, row) in rows.iter().enumerate() {
+        if hidden > 0 && row_index == limit {
+            lines.push(format!("… {} more transaction(s)", hidden));
+        }
+        let cells = row
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(index, (cell, width))| {
+                // The amount is right-aligned and colored after padding, so colors don't affect the width
+                if index == 3 {
+                    let padded = format!("{:>width$}", cell);
+                    if cell.contains('-') {
+                        padded.red().to_string()
+                    } else {
+                        padded.green().to_string()
+                    }
+                } else {
+                    format!("{:<width$}", cell)
+                }
+            })
+            .collect();
+        lines.push(format_line(cells));
+    }
+    lines.join("\n")
+}
+
+/// Check if a CSV file was generated by this crate, by comparing its header with the output header
+/// in all supported languages
+///
+/// # Arguments
+///
+/// * `file_path`: path to the CSV file
+///
+/// returns: Result<bool, BankCsvError>
+pub fn is_output_file(file_path: &Path) -> Result<bool, BankCsvError> {
+    Ok(output_file_locale(file_path)?.is_some())
+}
+
+/// The language of the header of a CSV file generated by this crate, so it can be rewritten in the same language
+///
+/// returns: Result<Option<Locale>, BankCsvError>; `None` if the file was not generated by this crate
+pub fn output_file_locale(file_path: &Path) -> Result<Option<Locale>, BankCsvError> {
+    let mut reader = csv::Reader::from_path(file_path)?;
+    let header: Vec<&str> = reader.headers()?.iter().take(NUM_OUTPUT_COLUMNS).collect();
+    Ok([Locale::En, Locale::De].into_iter().find(|locale| {
+        CsvOutputRow::header(*locale, &[])
+            .iter()
+            .eq(header.iter().copied())
+    }))
+}
+
+/// Read the transactions from an output file previously generated by this crate;
+/// closing balance rows (see [`balance::CLOSING_BALANCE_TYPE`]) are skipped
+///
+/// # Arguments
+///
+/// * `file_path`: path to a `bank-csv-transactions-*.csv` file
+///
+/// returns: Result<Vec<CsvOutputRow>, BankCsvError>; an `UnknownFormat` error if a column is not one of the output
+pub fn read_output_file(file_path: &Path) -> Result<Vec<CsvOutputRow>, BankCsvError> {
+    let mut transactions = read_output_rows(file_path)?;
+    transactions
+        .retain(|transaction| transaction.transaction_type != balance::CLOSING_BALANCE_TYPE);
+    Ok(transactions)
+}
+
+/// Read all rows of an output file previously generated by this crate, closing balance rows included
+pub fn read_output_rows(file_path: &Path) -> Result<Vec<CsvOutputRow>, BankCsvError> {
+    let mut reader = csv::Reader::from_path(file_path)?;
+    let header = reader.headers()?.clone();
+    let extra_columns = header
+        .iter()
+        .skip(NUM_OUTPUT_COLUMNS)
+        .map(|label| {
+            ExtraColumn::from_label(label).ok_or_else(|| BankCsvError::UnknownFormat {
+                file_path: file_path.to_path_buf(),
+                reason: format!("unknown column {:?}", label),
+                columns: header.iter().map(String::from).collect(),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    reader
+        .records()
+        .map(|result| Ok(CsvOutputRow::from_record(&result?, &extra_columns)?))
+        .collect()
+}
+
+/// SHA-256 hash of the content of a file, as hexadecimal; used to detect the same file passed twice
+pub fn file_hash(file_path: &Path) -> Result<String, BankCsvError> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(file_path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// ISO 8601 date formats, tried after the formats of each source
+pub const FALLBACK_DATE_FORMATS: [&str; 3] = [
+    "%Y-%m-%d",
+    "%Y-%m-%dT%H:%M:%S%.f%:z",
+    "%Y-%m-%dT%H:%M:%S%.fZ",
+];
+
+/// Parse a date with the first matching format, then with the [`FALLBACK_DATE_FORMATS`];
+/// formats with a time are also accepted, but only the date is kept
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::parse_date_str;
+/// use chrono::NaiveDate;
+/// let date = NaiveDate::from_ymd_opt(2024, 1, 31);
+/// assert_eq!(parse_date_str("31.01.24", &["%d.%m.%y", "%d.%m.%Y"]), date);
+/// assert_eq!(parse_date_str("31.01.2024", &["%d.%m.%Y"]), date);
+/// assert_eq!(parse_date_str("2024-01-31 12:34", &["%Y-%m-%d %H:%M"]), date);
+/// assert_eq!(parse_date_str("2024-01-31T12:34:56Z", &[]), date);
+/// assert_eq!(parse_date_str("2024-01-31", &["%d.%m.%Y"]), date);
+/// assert_eq!(parse_date_str("31st of January", &["%d.%m.%Y"]), None);
+/// ```
+pub fn parse_date_str(text: &str, formats: &[&str]) -> Option<NaiveDate> {
+    let text = text.trim();
+    formats
+        .iter()
+        .chain(FALLBACK_DATE_FORMATS.iter())
+        .find_map(|format| {
+            NaiveDate::parse_from_str(text, format)
+                .ok()
+                .or_else(|| {
+                    NaiveDateTime::parse_from_str(text, format)
+                        .ok()
+                        .map(|datetime| datetime.date())
+                })
+                .or_else(|| {
+                    DateTime::parse_from_str(text, format)
+                        .ok()
+                        .map(|datetime| datetime.date_naive())
+                })
+        })
+}
+
+/// Time zone abbreviations used by PayPal, which are not IANA names
+const TIME_ZONE_ABBREVIATIONS: [(&str, &str); 12] = [
+    ("CET", "Europe/Berlin"),
+    ("CEST", "Europe/Berlin"),
+    ("BST", "Europe/London"),
+    ("PST", "America/Los_Angeles"),
+    ("PDT", "America/Los_Angeles"),
+    ("MST", "America/Denver"),
+    ("MDT", "America/Denver"),
+    ("CST", "America/Chicago"),
+    ("CDT", "America/Chicago"),
+    ("EST", "America/New_York"),
+    ("EDT", "America/New_York"),
+    ("MEZ", "Europe/Berlin"),
+];
+
+/// Parse a time zone, either an IANA name (`Europe/Berlin`, `UTC`) or a common abbreviation (`CEST`, `PDT`)
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::parse_time_zone;
+/// assert_eq!(parse_time_zone("Europe/Berlin"), parse_time_zone("CEST"));
+/// assert_eq!(parse_time_zone("UTC"), Some(chrono_tz::UTC));
+/// assert_eq!(parse_time_zone("Mars/Olympus"), None);
+/// ```
+pub fn parse_time_zone(name: &str) -> Option<Tz> {
+    let name = name.trim();
+    TIME_ZONE_ABBREVIATIONS
+        .iter()
+        .find(|(abbreviation, _)| abbreviation.eq_ignore_ascii_case(name))
+        .map_or(name, |(_, iana_name)| iana_name)
+        .parse()
+        .ok()
+}
+
+/// Convert a local date and time from one time zone to another; `None` if the time doesn't exist
+/// in the source time zone (skipped when the clocks go forward)
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::convert_time_zone;
+/// use chrono::{NaiveDate, NaiveTime};
+/// let date = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+/// let time = NaiveTime::from_hms_opt(1, 30, 0).unwrap();
+/// let converted = convert_time_zone(date, time, chrono_tz::Europe::Berlin, chrono_tz::UTC).unwrap();
+/// assert_eq!(converted.to_string(), "2024-06-30 23:30:00");
+/// ```
+pub fn convert_time_zone(
+    date: NaiveDate,
+    time: NaiveTime,
+    from: Tz,
+    to: Tz,
+) -> Option<NaiveDateTime> {
+    from.from_local_datetime(&date.and_time(time))
+        .earliest()
+        .map(|datetime| datetime.with_timezone(&to).naive_local())
+}
+
 /// Strip double quotes from the content of a field. Needed for the N26 CSV file.
+///
+/// A byte order mark and a carriage return left over from Windows downloads are also stripped.
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::strip_quotes;
+/// assert_eq!(strip_quotes("\"Payee\"".to_string()), "Payee");
+/// assert_eq!(strip_quotes("\u{feff}\"Date\"".to_string()), "Date");
+/// assert_eq!(strip_quotes("\"-12.50\"\r".to_string()), "-12.50");
+/// assert_eq!(strip_quotes("no quotes".to_string()), "no quotes");
+/// ```
 pub fn strip_quotes(s: String) -> String {
-    s.strip_prefix(CHAR_DOUBLE_QUOTE)
+    let trimmed = s
+        .strip_prefix(UTF8_BOM)
         .unwrap_or(s.as_str())
+        .trim_end_matches(CHAR_CARRIAGE_RETURN);
+    let without_prefix = trimmed.strip_prefix(CHAR_DOUBLE_QUOTE).unwrap_or(trimmed);
+    without_prefix
         .strip_suffix(CHAR_DOUBLE_QUOTE)
-        .unwrap_or(s.as_str())
+        .unwrap_or(without_prefix)
         .to_string()
 }
 
+/// How the text of the fields read from bank exports is cleaned up, the same way for all sources;
+/// missing values (nulls) are always empty text
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::TextNormalizer;
+/// use polars::prelude::AnyValue;
+/// let normalizer = TextNormalizer::default();
+/// assert_eq!(normalizer.text("\"Bakery \""), "Bakery ");
+/// assert_eq!(normalizer.value(&AnyValue::Null), "");
+/// assert_eq!(normalizer.amount("\"-1.234,56\""), "-1.234,56");
+///
+/// let normalizer = TextNormalizer { trim: true, ..normalizer }
+///     .with_decimal_separator(Some(','));
+/// assert_eq!(normalizer.text(" \"Bakery \""), "Bakery");
+/// assert_eq!(normalizer.value(&AnyValue::Null), "");
+/// assert_eq!(normalizer.amount("\"-1.234,56\""), "-1234,56");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextNormalizer {
+    /// Strip the double quotes around the text, see [`strip_quotes`]
+    pub strip_quotes: bool,
+    /// Trim whitespace around the text
+    pub trim: bool,
+    /// Decimal separator of the amounts, which are then normalized to a decimal comma
+    /// (see [`normalize_amount`]); amounts are kept as they are if `None`
+    pub decimal_separator: Option<char>,
+}
+
+impl Default for TextNormalizer {
+    fn default() -> Self {
+        Self {
+            strip_quotes: true,
+            trim: false,
+            decimal_separator: None,
+        }
+    }
+}
+
+impl TextNormalizer {
+    /// The same normalizer with another decimal separator for the amounts
+    pub fn with_decimal_separator(self, decimal_separator: Option<char>) -> Self {
+        Self {
+            decimal_separator,
+            ..self
+        }
+    }
+
+    /// Clean up a text
+    pub fn text(&self, text: &str) -> String {
+        // Whitespace can be outside the quotes, inside them, or both
+        let mut text = if self.trim { text.trim() } else { text }.to_string();
+        if self.strip_quotes {
+            text = strip_quotes(text);
+        }
+        if self.trim {
+            text = text.trim().to_string();
+        }
+        text
+    }
+
+    /// Clean up the text of a value of a data frame
+    pub fn value(&self, value: &AnyValue) -> String {
+        match value {
+            AnyValue::Null => String::new(),
+            _ => self.text(&value.to_string()),
+        }
+    }
+
+    /// Clean up an amount, normalizing its decimal separator if it's known
+    pub fn amount(&self, amount: &str) -> String {
+        let amount = self.text(amount);
+        match self.decimal_separator {
+            Some(separator) => normalize_amount(&amount, separator),
+            None => amount,
+        }
+    }
+}
+
+/// Remove the byte order mark and turn Windows line endings (CRLF) into Unix ones,
+/// as in files downloaded with some browsers on Windows
+///
+/// returns: None if the content has neither, so the original file can be read as it is
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::sources::SourceRegistry;
+/// use bank_csv::{remove_bom_and_crlf, CsvFormat};
+/// let windows = "\u{feff}\"Date\",\"Payee\",\"Account number\",\"Transaction type\",\"Payment reference\"\r\n";
+/// let cleaned = String::from_utf8(remove_bom_and_crlf(windows.as_bytes()).unwrap()).unwrap();
+/// assert_eq!(cleaned, "\"Date\",\"Payee\",\"Account number\",\"Transaction type\",\"Payment reference\"\n");
+/// let header: Vec<String> = cleaned.trim_end().split(',').map(|c| c.trim_matches('"').to_string()).collect();
+/// let header: Vec<&str> = header.iter().map(String::as_str).collect();
+/// assert_eq!(SourceRegistry::default().detect_format(&header), Ok(CsvFormat::N26));
+/// assert_eq!(remove_bom_and_crlf(b"Date,Payee\n"), None);
+/// ```
+pub fn remove_bom_and_crlf(content: &[u8]) -> Option<Vec<u8>> {
+    let without_bom = content.strip_prefix(UTF8_BOM.as_bytes()).unwrap_or(content);
+    let has_crlf = without_bom.windows(2).any(|pair| pair == b"\r\n");
+    if without_bom.len() == content.len() && !has_crlf {
+        return None;
+    }
+    let mut cleaned = Vec::with_capacity(without_bom.len());
+    for (index, byte) in without_bom.iter().enumerate() {
+        if *byte == b'\r' && without_bom.get(index + 1) == Some(&b'\n') {
+            continue;
+        }
+        cleaned.push(*byte);
+    }
+    Some(cleaned)
+}
+
+/// Currency symbols found inside amount fields, longest first so "R$" is stripped before "$"
+const CURRENCY_SYMBOLS: [&str; 8] = ["US$", "R$", "CHF", "EUR", "€", "$", "£", "¥"];
+
+/// Strip currency symbols and whitespace from an amount, e.g. `"€ 1.234,56"` or `"R$ -10,00"`
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::strip_currency_symbols;
+/// assert_eq!(strip_currency_symbols("-12,50 €"), "-12,50");
+/// assert_eq!(strip_currency_symbols("$1,234.56"), "1,234.56");
+/// assert_eq!(strip_currency_symbols("R$ -10,00"), "-10,00");
+/// assert_eq!(strip_currency_symbols("CHF\u{a0}99.90"), "99.90");
+/// assert_eq!(strip_currency_symbols("42,00"), "42,00");
+/// ```
+pub fn strip_currency_symbols(amount: &str) -> String {
+    let mut stripped = amount.to_string();
+    for symbol in CURRENCY_SYMBOLS {
+        stripped = stripped.replace(symbol, "");
+    }
+    stripped.retain(|c| !c.is_whitespace());
+    stripped
+}
+
+/// The decimal separator of an amount, if it can be told from the amount alone
+fn amount_decimal_separator(amount: &str) -> Option<char> {
+    let last_dot = amount.rfind('.');
+    let last_comma = amount.rfind(',');
+    match (last_dot, last_comma) {
+        // With both separators, the last one is the decimal separator
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(position), None) | (None, Some(position)) => {
+            let separator = amount.as_bytes()[position] as char;
+            if amount.matches(separator).count() > 1 {
+                // Repeated separators are thousands separators, e.g. "1.234.567"
+                Some(if separator == '.' { ',' } else { '.' })
+            } else if amount.len() - position - 1 != 3 {
+                Some(separator)
+            } else {
+                // "1.234" or "1,234" could be either
+                None
+            }
+        }
+        (None, None) => None,
+    }
+}
+
+/// Detect the decimal separator used by the amounts of a column, from the first unambiguous amount
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::detect_decimal_separator;
+/// assert_eq!(detect_decimal_separator(["1.234", "-1.234,56"]), Some(','));
+/// assert_eq!(detect_decimal_separator(["\"1,234\"", "12.5"]), Some('.'));
+/// assert_eq!(detect_decimal_separator(["€ 1.234.567"]), Some(','));
+/// assert_eq!(detect_decimal_separator(["1,234", "42"]), None);
+/// ```
+pub fn detect_decimal_separator<'a>(amounts: impl IntoIterator<Item = &'a str>) -> Option<char> {
+    amounts.into_iter().find_map(|amount| {
+        amount_decimal_separator(&strip_currency_symbols(&strip_quotes(amount.to_string())))
+    })
+}
+
+/// Normalize an amount to a decimal comma without thousands separators,
+/// given the decimal separator of its column (see [`detect_decimal_separator`])
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::normalize_amount;
+/// assert_eq!(normalize_amount("1,234.56", '.'), "1234,56");
+/// assert_eq!(normalize_amount("-1.234,56", ','), "-1234,56");
+/// assert_eq!(normalize_amount("12.5", '.'), "12,5");
+/// ```
+pub fn normalize_amount(amount: &str, decimal_separator: char) -> String {
+    let thousands_separator = if decimal_separator == '.' { ',' } else { '.' };
+    amount
+        .chars()
+        .filter(|c| *c != thousands_separator)
+        .map(|c| if c == decimal_separator { ',' } else { c })
+        .collect()
+}
+
+/// Describe a currency conversion with the amounts of both legs (with a decimal comma, see
+/// [`normalize_amount`]) and the exchange rate; `None` if an amount is not a number
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::conversion_memo;
+/// assert_eq!(
+///     conversion_memo(("EUR", "-10,00"), ("USD", "10,95")).unwrap(),
+///     "EUR -10,00 → USD 10,95 (rate 1,0950)"
+/// );
+/// assert_eq!(conversion_memo(("EUR", "-10,00"), ("USD", "?")), None);
+/// ```
+pub fn conversion_memo(from: (&str, &str), to: (&str, &str)) -> Option<String> {
+    let parse = |amount: &str| amount.replace(',', ".").parse::<f64>().ok();
+    let from_amount = parse(from.1)?.abs();
+    let to_amount = parse(to.1)?.abs();
+    if from_amount == 0.0 {
+        return None;
+    }
+    let rate = format!("{:.4}", to_amount / from_amount).replace('.', ",");
+    Some(format!(
+        "{} {} → {} {} (rate {})",
+        from.0, from.1, to.0, to.1, rate
+    ))
+}
+
+/// Builder of a [`CsvOutputRow`] with the fields as they are given, e.g. for library users;
+/// the builder is not consumed, so it can be reused as a template for similar rows
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::{CsvOutputRow, ExtraColumn};
+/// use chrono::NaiveDate;
+/// let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+/// let mut builder = CsvOutputRow::builder(date);
+/// builder.source("N26").currency("EUR").amount_value(-12.5).payee("\"Bakery\"");
+/// let row = builder.build();
+/// assert_eq!((row.amount.as_str(), row.payee.as_str()), ("-12,5", "\"Bakery\""));
+///
+/// let normalized = builder.amount("$1.5").extra(ExtraColumn::Id, "42").normalize(true).build();
+/// assert_eq!((normalized.amount.as_str(), normalized.payee.as_str()), ("1,5", "Bakery"));
+/// assert_eq!(normalized.extra[&ExtraColumn::Id], "42");
+/// ```
+#[derive(Clone, Debug)]
+pub struct CsvOutputRowBuilder {
+    date: NaiveDate,
+    source: String,
+    currency: String,
+    amount: String,
+    transaction_type: String,
+    payee: String,
+    memo: String,
+    extra: BTreeMap<ExtraColumn, String>,
+    normalize: bool,
+}
+
+impl CsvOutputRowBuilder {
+    /// Start a row on this date, with empty fields
+    pub fn new(date: NaiveDate) -> Self {
+        Self {
+            date,
+            source: String::new(),
+            currency: String::new(),
+            amount: String::new(),
+            transaction_type: String::new(),
+            payee: String::new(),
+            memo: String::new(),
+            extra: BTreeMap::new(),
+            normalize: false,
+        }
+    }
+
+    /// Set the date
+    pub fn date(&mut self, date: NaiveDate) -> &mut Self {
+        self.date = date;
+        self
+    }
+
+    /// Set the source (N26, PayPal, DKB, ...)
+    pub fn source(&mut self, source: impl Into<String>) -> &mut Self {
+        self.source = source.into();
+        self
+    }
+
+    /// Set the currency, 3 letters (EUR, USD, ...)
+    pub fn currency(&mut self, currency: impl Into<String>) -> &mut Self {
+        self.currency = currency.into();
+        self
+    }
+
+    /// Set the amount as text, written as it is unless normalized
+    pub fn amount(&mut self, amount: impl Into<String>) -> &mut Self {
+        self.amount = amount.into();
+        self
+    }
+
+    /// Set the amount from a number, written with a decimal comma like the output files
+    pub fn amount_value(&mut self, amount: f64) -> &mut Self {
+        self.amount = amount.to_string().replace(CHAR_DOT, CHAR_COMMA);
+        self
+    }
+
+    /// Set the transaction type, as given by the source
+    pub fn transaction_type(&mut self, transaction_type: impl Into<String>) -> &mut Self {
+        self.transaction_type = transaction_type.into();
+        self
+    }
+
+    /// Set the payee
+    pub fn payee(&mut self, payee: impl Into<String>) -> &mut Self {
+        self.payee = payee.into();
+        self
+    }
+
+    /// Set the memo
+    pub fn memo(&mut self, memo: impl Into<String>) -> &mut Self {
+        self.memo = memo.into();
+        self
+    }
+
+    /// Set the value of an optional column
+    pub fn extra(&mut self, column: ExtraColumn, value: impl Into<String>) -> &mut Self {
+        self.extra.insert(column, value.into());
+        self
+    }
+
+    /// Normalize the fields like the rows read from bank exports: strip quotes, strip currency symbols
+    /// and use a decimal comma in the amount, and use euros if the currency is empty.
+    /// Disabled by default
+    pub fn normalize(&mut self, enabled: bool) -> &mut Self {
+        self.normalize = enabled;
+        self
+    }
+
+    /// Create the row; the builder can still be used afterwards
+    pub fn build(&self) -> CsvOutputRow {
+        if !self.normalize {
+            return CsvOutputRow {
+                date: self.date,
+                source: self.source.clone(),
+                currency: self.currency.clone(),
+                amount: self.amount.clone(),
+                transaction_type: self.transaction_type.clone(),
+                payee: self.payee.clone(),
+                memo: self.memo.clone(),
+                extra: self.extra.clone(),
+                sequence: None,
+            };
+        }
+        // Assume euros if the currency is empty (thanks DKB and N26)
+        let stripped = strip_quotes(self.currency.clone());
+        let final_currency = if stripped.is_empty() {
+            "EUR"
+        } else {
+            stripped.as_str()
+        };
+
+        CsvOutputRow {
+            date: self.date,
+            source: self.source.clone(),
+            currency: final_currency.to_string(),
+            // "Numbers" on my macOS only understands commas as decimal separators;
+            // I can make it configurable if someone ever uses this crate
+            amount: strip_currency_symbols(&strip_quotes(self.amount.clone()))
+                .replace(CHAR_DOT, CHAR_COMMA),
+            transaction_type: strip_quotes(self.transaction_type.clone()),
+            payee: strip_quotes(self.payee.clone()),
+            memo: strip_quotes(self.memo.clone()),
+            extra: self.extra.clone(),
+            sequence: None,
+        }
+    }
+}
+
 impl CsvOutputRow {
-    /// Create a new CsvOutputRow
+    /// Create a new CsvOutputRow, normalizing the fields (see [`CsvOutputRowBuilder::normalize`])
     pub fn new(
         date: NaiveDate,
         source: String,
@@ -426,42 +2602,96 @@ impl CsvOutputRow {
         payee: String,
         memo: String,
     ) -> Self {
-        // Assume euros if the currency is empty or "null" (thanks DKB and N26)
-        let stripped = strip_quotes(currency);
-        let final_currency = if stripped.is_empty() || stripped == "null" {
-            "EUR"
-        } else {
-            stripped.as_str()
-        };
-
-        Self {
+        CsvOutputRowBuilder {
             date,
             source,
-            currency: final_currency.to_string(),
-            // "Numbers" on my macOS only understands commas as decimal separators;
-            // I can make it configurable if someone ever uses this crate
-            amount: strip_quotes(amount).replace(CHAR_DOT, CHAR_COMMA),
-            transaction_type: strip_quotes(transaction_type),
-            payee: strip_quotes(payee),
-            memo: strip_quotes(memo),
+            currency,
+            amount,
+            transaction_type,
+            payee,
+            memo,
+            extra: BTreeMap::new(),
+            normalize: true,
         }
+        .build()
     }
 
-    /// Create a CSV header
-    pub fn header() -> StringRecord {
-        let mut record = StringRecord::new();
-        record.push_field("Date");
-        record.push_field("Source");
-        record.push_field("Currency");
-        record.push_field("Amount");
-        record.push_field("Type");
-        record.push_field("Payee");
-        record.push_field("Memo");
+    /// Start building a row with fields that are kept as they are given, see [`CsvOutputRowBuilder`]
+    pub fn builder(date: NaiveDate) -> CsvOutputRowBuilder {
+        CsvOutputRowBuilder::new(date)
+    }
+
+    /// Create a CSV header with column names in the given language, followed by the extra columns
+    pub fn header(locale: Locale, extra_columns: &[ExtraColumn]) -> StringRecord {
+        let names = match locale {
+            Locale::En => [
+                "Date", "Source", "Currency", "Amount", "Type", "Payee", "Memo",
+            ],
+            Locale::De => [
+                "Datum",
+                "Quelle",
+                "Währung",
+                "Betrag",
+                "Typ",
+                "Empfänger",
+                "Verwendungszweck",
+            ],
+        };
+        let mut record = StringRecord::from(names.to_vec());
+        for column in extra_columns {
+            record.push_field(column.label(locale));
+        }
         record
     }
 
-    /// Convert a CsvOutputRow to a CSV record
-    pub fn to_record(&self) -> StringRecord {
+    /// Parse a CSV record previously written by [`CsvOutputRow::to_record`]
+    ///
+    /// The values are taken as they are, without the normalization done by [`CsvOutputRow::new`].
+    /// The extra columns are expected after the standard ones, in the given order.
+    pub fn from_record(record: &StringRecord, extra_columns: &[ExtraColumn]) -> io::Result<Self> {
+        let field = |index: usize| record.get(index).unwrap_or_default().to_string();
+        let date = NaiveDate::parse_from_str(&field(0), "%Y-%m-%d").map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid date {:?}: {}", field(0), err),
+            )
+        })?;
+        Ok(Self {
+            date,
+            source: field(1),
+            currency: field(2),
+            amount: field(3),
+            transaction_type: field(4),
+            payee: field(5),
+            memo: field(6),
+            extra: extra_columns
+                .iter()
+                .enumerate()
+                .map(|(index, column)| (*column, field(NUM_OUTPUT_COLUMNS + index)))
+                .filter(|(_, value)| !value.is_empty())
+                .collect(),
+            sequence: None,
+        })
+    }
+
+    /// Hash of the standard fields except the date and the amount, to find the same transaction
+    /// with slightly different dates (e.g. booking date vs. value date in different exports)
+    /// or amounts (e.g. rounding in currency conversions)
+    pub fn hash_without_date_and_amount(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (
+            &self.source,
+            &self.currency,
+            &self.transaction_type,
+            &self.payee,
+            &self.memo,
+        )
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Convert a CsvOutputRow to a CSV record, with the values of the extra columns at the end
+    pub fn to_record(&self, extra_columns: &[ExtraColumn]) -> StringRecord {
         let mut record = StringRecord::new();
         record.push_field(&self.date.format("%Y-%m-%d").to_string());
         record.push_field(&self.source);
@@ -470,6 +2700,9 @@ impl CsvOutputRow {
         record.push_field(&self.transaction_type);
         record.push_field(&self.payee);
         record.push_field(&self.memo);
+        for column in extra_columns {
+            record.push_field(self.extra.get(column).map_or("", String::as_str));
+        }
         record
     }
 }
@@ -0,0 +1,47 @@
+//! Advisory lock on the output directory, so concurrent runs don't write the same monthly files
+use fs4::FileExt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// File in the output directory that is locked while a run writes to it
+pub const LOCK_FILE_NAME: &str = ".bank-csv.lock";
+
+/// A lock on an output directory, released when dropped (or when the process ends)
+#[derive(Debug)]
+pub struct OutputLock {
+    file: File,
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        // The lock is also released when the file is closed; unlocking explicitly just makes it sooner
+        let _ = self.file.unlock();
+    }
+}
+
+/// Lock an output directory for this run, without waiting
+///
+/// returns: Result<OutputLock, Error>; an error if another run holds the lock
+pub fn lock_output_dir(dir: &Path) -> io::Result<OutputLock> {
+    let path = dir.join(LOCK_FILE_NAME);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)?;
+    file.try_lock_exclusive().map_err(|err| {
+        if err.kind() == fs4::lock_contended_error().kind() {
+            io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!(
+                    "{}: another bank-csv run is writing to this directory; try again when it's done",
+                    dir.display()
+                ),
+            )
+        } else {
+            io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+        }
+    })?;
+    Ok(OutputLock { file })
+}
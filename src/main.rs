@@ -1,62 +1,620 @@
 //! Detect CSV files from a couple of German banks (N26, DKB) and PayPal,
 //! filter out transactions in a specific currency and generate a CSV file with these transactions
+use bank_csv::aliases::AliasStore;
+use bank_csv::balance::{monthly_closing_balances, CLOSING_BALANCE_TYPE};
+use bank_csv::classify::Classifier;
+use bank_csv::cloud::{download, is_cloud_url, upload};
+use bank_csv::config::Config;
+use bank_csv::crypto::{decrypt_file, encrypt, is_encrypted, AGE_EXTENSION};
+use bank_csv::error::BankCsvError;
+use bank_csv::formats::{load_formats, FormatDescriptor};
+use bank_csv::history::{HistoryEntry, HistoryInput, HistoryOutput};
+use bank_csv::index::{index_csv, IndexEntry, Totals, INDEX_FILE_NAME};
+use bank_csv::lock::lock_output_dir;
+use bank_csv::manifest::{load_manifest, ManifestInput};
+use bank_csv::merge::{
+    detect_source, merge_files_with, read_file, FileContent, MergeHandler, MergeOptions, SkipReason,
+};
+use bank_csv::package::{overview_html, write_package, OVERVIEW_FILE_NAME, SUMMARY_FILE_NAME};
+use bank_csv::payees::PayeeFilter;
+use bank_csv::report::{check_months, year_to_date};
+use bank_csv::rules::RuleSet;
+use bank_csv::schema::{schema_version, sort_columns, write_schema_version, SCHEMA_VERSION};
+use bank_csv::script::RowScript;
+use bank_csv::sources::SourceRegistry;
+use bank_csv::state::State;
+use bank_csv::tags::add_tags;
+use bank_csv::unzip::{extract_csv_files, is_zip_file, needs_password};
 use bank_csv::{
-    detect_separator, dkb_edit_file, dkb_extract_amount, filter_data_frame, strip_quotes,
-    CsvOutputRow, Source, NUM_SELECT_COLUMNS,
+    file_hash, file_name_part, output_file_locale, parse_time_zone, preview_table,
+    read_output_file, read_output_rows, sort_in_original_order, AmountTolerance, BalanceImpact,
+    CsvFormat, CsvOutputRow, DateBasis, ExtraColumn, GroupBy, Locale, TransactionSet,
+    DEFAULT_INVOICE_PATTERN,
 };
-use chrono::{Datelike, NaiveDate};
-use clap::{Parser, Subcommand};
+use chrono::{DateTime, Datelike, NaiveDate};
+use clap::{Args, Parser, Subcommand};
+use colored::{Color, Colorize};
 use csv::Writer;
-use polars::export::arrow::temporal_conversions::EPOCH_DAYS_FROM_CE;
-use polars::frame::row::Row;
-use polars::prelude::*;
-use sorted_vec::SortedSet;
-use std::collections::HashMap;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use tempfile::NamedTempFile;
+use std::process::ExitCode;
+use tempfile::TempDir;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Path to the configuration file. Default: ~/.config/bank-csv/config.toml
+    #[arg(long, global = true, env = "BANK_CSV_CONFIG", value_hint = clap::ValueHint::FilePath)]
+    config: Option<PathBuf>,
+    /// Profile of the configuration file to use, e.g. one per person (see `[profiles.NAME]`)
+    #[arg(long, global = true, env = "BANK_CSV_PROFILE")]
+    profile: Option<String>,
+    /// Disable colored output (colors are also disabled automatically when not writing to a terminal)
+    #[arg(long, global = true)]
+    no_color: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Merge one or more bank CSV files and split them into multiple files, one for each month
+    /// Merge one or more bank CSV files and split them into multiple files, one for each month (or week)
+    #[command(arg_required_else_help = true)]
+    Merge(Box<MergeArgs>),
+    /// Consolidate monthly files generated by `merge` into yearly files or a single file
+    Consolidate(ConsolidateArgs),
+    /// Find transactions that appear in more than one monthly file, and optionally remove them
+    Duplicates(DuplicatesArgs),
+    /// Show the number of transactions per month and warn about missing or suspiciously quiet months
+    Report(ReportArgs),
+    /// Show the source, format, separator and encoding detected for bank CSV files, without merging them
+    #[command(arg_required_else_help = true)]
+    Detect(DetectArgs),
+    /// Rewrite the monthly files of an older version with the current layout
+    Upgrade(UpgradeArgs),
+    /// Debug the payee, tag and split rules of the configuration
+    #[command(subcommand, alias = "import-rules")]
+    Rules(RulesCommand),
+    /// Remember the preferred names of payees, applied automatically in every merge
+    #[command(subcommand)]
+    Alias(AliasCommand),
+}
+
+#[derive(Subcommand)]
+enum AliasCommand {
+    /// Add or replace the preferred name of a payee
+    #[command(arg_required_else_help = true)]
+    Add(AliasAddArgs),
+    /// Remove the preferred name of a payee
+    #[command(arg_required_else_help = true)]
+    Remove(AliasRemoveArgs),
+    /// List the payees with a preferred name
+    List,
+}
+
+#[derive(Args)]
+struct AliasAddArgs {
+    /// The payee as it is written in the bank exports or the output files (case-insensitive)
+    payee: String,
+    /// The preferred name written instead
+    name: String,
+}
+
+#[derive(Args)]
+struct AliasRemoveArgs {
+    /// The payee as it was added (case-insensitive)
+    payee: String,
+}
+
+#[derive(Subcommand)]
+enum RulesCommand {
+    /// Show which rules match each transaction of a bank CSV file, without merging it
     #[command(arg_required_else_help = true)]
-    Merge {
-        /// Path(s) to the CSV file(s) to be parsed
-        csv_file_paths: Vec<PathBuf>,
-        /// Currency to filter (case-insensitive)
-        #[arg(short, long, default_value = "EUR")]
-        currency: String,
-        /// Output directory to generate the CSV files. Default: download directory
-        #[arg(short, long, value_hint = clap::ValueHint::DirPath)]
-        output_dir: Option<PathBuf>,
-    },
-}
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let cli = Cli::parse();
+    Test(RulesTestArgs),
+}
+
+#[derive(Args)]
+struct RulesTestArgs {
+    /// Path to the CSV file
+    csv_file_path: PathBuf,
+    /// Currency to filter (case-insensitive). Default: EUR
+    #[arg(short, long, env = "BANK_CSV_CURRENCY")]
+    currency: Option<String>,
+}
+
+#[derive(Args)]
+struct DetectArgs {
+    /// Path(s) to the CSV file(s)
+    csv_file_paths: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    /// Directory with the monthly files. Default: output directory from the config, or download directory
+    #[arg(value_hint = clap::ValueHint::DirPath)]
+    dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct UpgradeArgs {
+    /// Directory with the monthly files. Default: output directory from the config, or download directory
+    #[arg(value_hint = clap::ValueHint::DirPath)]
+    dir: Option<PathBuf>,
+    /// Language of the column names of the rewritten files. Default: the language of each file
+    #[arg(short, long, value_enum)]
+    locale: Option<Locale>,
+}
+
+#[derive(Args)]
+struct DuplicatesArgs {
+    /// Directory with the monthly files. Default: output directory from the config, or download directory
+    #[arg(value_hint = clap::ValueHint::DirPath)]
+    dir: Option<PathBuf>,
+    /// Remove the duplicates, keeping the transaction in the earliest month
+    #[arg(short, long)]
+    remove: bool,
+    /// Language of the column names when rewriting files. Default: en
+    #[arg(short, long, value_enum)]
+    locale: Option<Locale>,
+    /// Maximum difference between amounts of the same transaction,
+    /// either absolute (e.g. 0.05) or a percentage (e.g. 1%). Default: exact amounts
+    #[arg(short, long)]
+    tolerance: Option<AmountTolerance>,
+}
+
+/// Transactions with the same fields at most this number of days apart are considered duplicates
+const DUPLICATE_MAX_DAYS: i64 = 5;
+
+/// Period covered by each consolidated file
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum Period {
+    /// One file per currency and year
+    Year,
+    /// One file per currency with all transactions
+    All,
+}
+
+#[derive(Args)]
+struct ConsolidateArgs {
+    /// Directory with the monthly files. Default: output directory from the config, or download directory
+    #[arg(value_hint = clap::ValueHint::DirPath)]
+    input_dir: Option<PathBuf>,
+    /// Period covered by each consolidated file
+    #[arg(short, long, value_enum, default_value_t = Period::Year)]
+    period: Period,
+    /// Output directory to generate the consolidated files. Default: input directory
+    #[arg(short, long, value_hint = clap::ValueHint::DirPath)]
+    output_dir: Option<PathBuf>,
+    /// Overwrite existing consolidated files without asking
+    #[arg(short, long)]
+    force: bool,
+    /// Language of the column names in the output files. Default: en
+    #[arg(short, long, value_enum)]
+    locale: Option<Locale>,
+    /// Keep only one of identical transactions (all columns equal).
+    /// Default: identical transactions are all kept
+    #[arg(long)]
+    dedupe: bool,
+}
+
+#[derive(Args)]
+struct MergeArgs {
+    /// Path(s) to the CSV file(s) to be parsed
+    csv_file_paths: Vec<PathBuf>,
+    /// TOML manifest listing input files, with an optional format, account label and date range per file
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    manifest: Option<PathBuf>,
+    /// Currency to filter (case-insensitive). Default: EUR
+    #[arg(short, long, env = "BANK_CSV_CURRENCY")]
+    currency: Option<String>,
+    /// Currency to filter in one file instead of --currency, as FILE=CURRENCY (e.g. paypal.csv=USD);
+    /// the file is matched by its path or name. Its transactions go to files of that currency
+    #[arg(long, value_name = "FILE=CURRENCY")]
+    file_currency: Vec<String>,
+    /// Output directory to generate the CSV files. Default: download directory
+    #[arg(short, long, env = "BANK_CSV_OUTPUT_DIR", value_hint = clap::ValueHint::DirPath)]
+    output_dir: Option<PathBuf>,
+    /// Overwrite existing monthly files without asking
+    #[arg(short, long, conflicts_with = "append")]
+    force: bool,
+    /// Add the new transactions to existing monthly files, skipping the ones already there
+    #[arg(short, long)]
+    append: bool,
+    /// Language of the column names in the output files. Default: en
+    #[arg(short, long, value_enum)]
+    locale: Option<Locale>,
+    /// Date used to assign transactions to months, for sources with both a booking and a value date
+    /// (DKB, N26). Default: booking
+    #[arg(long, value_enum)]
+    date_basis: Option<DateBasis>,
+    /// Period of the output files: one file per month (`2024-01`), per ISO week (`2024-W05`)
+    /// or per quarter (`2024-Q1`). Default: month
+    #[arg(long, value_enum)]
+    group_by: Option<GroupBy>,
+    /// Add a closing balance row at the end of each monthly file, for statements with a balance (DKB, in euros).
+    /// Balances of previous months are computed back from the balance of the statement
+    #[arg(long)]
+    emit_balance_rows: bool,
+    /// Write one file per account and period, for N26 exports of several accounts and accounts of the manifest,
+    /// e.g. `bank-csv-transactions-EUR-Main-Account-2024-01.csv`
+    #[arg(long)]
+    split_by_account: bool,
+    /// Add an ID column with the transaction ID given by the source (empty for banks without IDs)
+    #[arg(long)]
+    with_id: bool,
+    /// Add a Time column with the time of the transaction, for sources that provide it
+    /// (PayPal, Kraken, ...), and order the transactions of a day by it
+    #[arg(long)]
+    with_time: bool,
+    /// Convert dates and times to this time zone, e.g. `Europe/Berlin` (with --with-time).
+    /// Default: the time zone of each source
+    #[arg(long, value_name = "TZ", requires = "with_time")]
+    time_zone: Option<String>,
+    /// Securities mode: read depot exports (DKB, Trade Republic, comdirect) and add ISIN, quantity and fee columns to the output
+    #[arg(long)]
+    securities: bool,
+    /// VAT mode: add VAT rate and amount columns, extracted from memos like "inkl. 19% MwSt"
+    #[arg(long)]
+    vat: bool,
+    /// EÜR profile for German freelancer bookkeeping: VAT mode with Netto and Brutto columns,
+    /// one file per quarter and German column names (unless --group-by or --locale are given)
+    #[arg(long)]
+    euer: bool,
+    /// Add an Invoice column with invoice numbers found in the memos (e.g. RE-2024-001)
+    #[arg(long)]
+    invoice: bool,
+    /// Clean up payees like "AMZN Mktp DE*2K4T 800-279-6620" with built-in and configured rules
+    #[arg(long)]
+    clean_payees: bool,
+    /// Add a Tags column with the tags of the matching `tag_rules` of the configuration
+    #[arg(long)]
+    tags: bool,
+    /// Review the tags of each transaction in the terminal and add more by hand (implies --tags)
+    #[arg(long)]
+    review_tags: bool,
+    /// Add Suggested Category and Confidence columns, learned from the Normalized Type column
+    /// of the monthly files already in the output directory (including categories corrected by hand)
+    #[arg(long)]
+    suggest_categories: bool,
+    /// Add a Normalized Type column mapping the types of each bank to a small canonical set
+    /// (income, card_payment, direct_debit, transfer, ...)
+    #[arg(long)]
+    normalize_types: bool,
+    /// Keep transactions with a zero amount, like card verifications and declined payments.
+    /// Default: they are dropped and counted
+    #[arg(long)]
+    keep_zero_amounts: bool,
+    /// Keep moves between the N26 main account and Spaces (or Monzo pots), as transfers.
+    /// Default: they are dropped and counted, so they don't show up as expenses or income
+    #[arg(long)]
+    keep_internal_transfers: bool,
+    /// File with a list of payees, one per line (exact names or glob patterns like `Techniker*`).
+    /// Only their transactions are kept, e.g. to extract the tax-relevant ones
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    payees_file: Option<PathBuf>,
+    /// Drop the transactions of the payees in --payees-file, instead of keeping only them
+    #[arg(long, requires = "payees_file")]
+    exclude_payees: bool,
+    /// Keep PayPal currency conversions as one transaction per conversion, with the other currency
+    /// and the exchange rate in the memo. Default: conversions are dropped
+    #[arg(long)]
+    paypal_conversions: bool,
+    /// Add Amount EUR, Original Amount and Rate columns to DKB card payments in foreign currencies,
+    /// so both amounts are kept whatever the currency of the output
+    #[arg(long)]
+    dkb_conversions: bool,
+    /// PayPal rows to read by their balance impact, e.g. `debit,credit` to include refunds
+    /// and incoming payments. Default: debit
+    #[arg(long, value_enum, value_delimiter = ',')]
+    paypal_balance_impact: Vec<BalanceImpact>,
+    /// Keep only one of identical transactions (all columns equal),
+    /// e.g. when input files overlap. Default: identical transactions are all kept
+    #[arg(long)]
+    dedupe: bool,
+    /// Read all rows of the files, e.g. to archive them: every currency (one output file each), type
+    /// (also pending rows and the legs of currency conversions), payee and date.
+    /// Nothing is filtered or dropped, but the rows are normalized as usual
+    #[arg(long, conflicts_with_all = ["payees_file", "paypal_balance_impact", "exclude_before_account_opening"])]
+    no_filter: bool,
+    /// Drop rows dated before this date (default: 1990-01-01) or more than --max-days-ahead in the future,
+    /// like the 1970-01-01 dates of some bank bugs; they are counted instead of creating nonsense months.
+    /// The date goes after an equal sign, e.g. `--exclude-before-account-opening=2015-03-01`
+    #[arg(
+        long,
+        value_name = "DATE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "1990-01-01"
+    )]
+    exclude_before_account_opening: Option<NaiveDate>,
+    /// Days after today that are still plausible dates, with --exclude-before-account-opening
+    #[arg(long, value_name = "DAYS", default_value_t = 7)]
+    max_days_ahead: u32,
+    /// Keep transactions of the same date (and time) in the order of the input files and their rows,
+    /// e.g. a fee after its payment. Default: sorted by currency, amount, type and payee
+    #[arg(long)]
+    original_order: bool,
+    /// Show the first and last N transactions (at least 1) of each month as a table, instead of every transaction.
+    /// The number goes after an equal sign, e.g. `--preview=10`, so it is not taken for an input file
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "5",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    preview: Option<usize>,
+    /// Encrypt the monthly files to this age recipient (public key), writing `.csv.age` files.
+    /// Can be repeated
+    #[arg(long, value_name = "RECIPIENT", conflicts_with = "append")]
+    encrypt_to: Vec<String>,
+    /// age identity file with the private key, to decrypt `.age` input files
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    identity: Option<PathBuf>,
+    /// Password of encrypted ZIP input files. Default: ask interactively when running in a terminal
+    #[arg(long, env = "BANK_CSV_ZIP_PASSWORD", hide_env_values = true)]
+    zip_password: Option<String>,
+    /// Mask IBANs, card numbers and mandate references in the memos, keeping their last 4 characters
+    #[arg(long)]
+    mask_pii: bool,
+    /// Replace payees with stable pseudonyms, e.g. to share realistic data for debugging or demos
+    #[arg(long)]
+    pseudonymize: bool,
+    /// Secret key for the pseudonyms; the same key always gives the same pseudonym for a payee
+    #[arg(long, env = "BANK_CSV_PSEUDONYM_KEY", hide_env_values = true)]
+    pseudonym_key: Option<String>,
+    /// Format to use when it can't be detected from the header, instead of asking interactively
+    #[arg(long, value_enum)]
+    assume: Option<CsvFormat>,
+    /// Directory with TOML descriptors of other bank formats, tried when a header doesn't match
+    /// a built-in format. Default: ~/.config/bank-csv/formats
+    #[arg(long, value_hint = clap::ValueHint::DirPath)]
+    formats_dir: Option<PathBuf>,
+    /// Rhai script with a `transform(row)` function, applied to each transaction after all rules;
+    /// needs the `scripting` feature
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    script: Option<PathBuf>,
+    /// Move the input files to this directory after processing them, renamed like `2024-09-dkb.csv`
+    #[arg(long, value_hint = clap::ValueHint::DirPath)]
+    archive_dir: Option<PathBuf>,
+    /// Copy the input files to the archive directory instead of moving them
+    #[arg(long, requires = "archive_dir")]
+    archive_copy: bool,
+    /// State file to record the latest exported date per source. Default: no state file,
+    /// or ~/.config/bank-csv/state.toml with --since-last-run
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    state_file: Option<PathBuf>,
+    /// Skip transactions older than the latest date exported by the previous run, per source
+    #[arg(long)]
+    since_last_run: bool,
+    /// Also write an index.csv to the output directory, with the period, row count and totals
+    /// of each file written by this run
+    #[arg(long)]
+    index: bool,
+    /// Also write the dropped transactions (zero amounts, internal transfers, other currencies, ...)
+    /// to a bank-csv-skipped-<CURRENCY>.csv file in the output directory, with a Reason column
+    #[arg(long)]
+    write_skipped: bool,
+    /// Also bundle the files of this run in a ZIP archive, e.g. for a tax advisor: the monthly files,
+    /// a summary.json with the inputs, outputs and settings, the index and the skipped transactions
+    #[arg(long, value_name = "ZIP", value_hint = clap::ValueHint::FilePath)]
+    package: Option<PathBuf>,
+    /// Add an overview.html to the package, with the totals of each file and the skipped transactions
+    #[arg(long, requires = "package")]
+    package_overview: bool,
+    /// Append a line with the inputs, outputs and settings of this run to a JSON Lines history file
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    history_file: Option<PathBuf>,
+}
+
+/// Exit codes of the commands, so scripts and cron jobs can tell failures apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    /// Everything was read and written
+    Success = 0,
+    /// An error without a more specific code
+    Failure = 1,
+    /// The outputs were written, but some input files or existing output files were skipped
+    Partial = 2,
+    /// Some input files have an unknown format; the outputs of the other files were written
+    UnknownFormat = 3,
+    /// The output files could not be written
+    OutputError = 4,
+    /// Invalid command line arguments
+    Usage = 5,
+}
+
+impl From<Status> for ExitCode {
+    fn from(status: Status) -> Self {
+        ExitCode::from(status as u8)
+    }
+}
+
+/// An error writing the outputs, with its own exit code; unknown formats ([`BankCsvError::UnknownFormat`])
+/// have one too, and other errors exit with [`Status::Failure`]
+#[derive(Debug)]
+enum CommandError {
+    /// The outputs could not be written
+    Output(Box<dyn Error>),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Output(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for CommandError {}
+
+/// Mark an error as an error writing the outputs
+fn output_error(err: impl Into<Box<dyn Error>>) -> CommandError {
+    CommandError::Output(err.into())
+}
+
+fn main() -> ExitCode {
+    // Clap exits with 2 on invalid arguments, which is the code of a partial merge here
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) => {
+            let _ = err.print();
+            return match err.use_stderr() {
+                true => Status::Usage.into(),
+                false => Status::Success.into(),
+            };
+        }
+    };
+    match run(cli) {
+        Ok(status) => status.into(),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            if let Some(CommandError::Output(_)) = err.downcast_ref::<CommandError>() {
+                return Status::OutputError.into();
+            }
+            match err.downcast_ref::<BankCsvError>() {
+                Some(BankCsvError::UnknownFormat { .. }) => Status::UnknownFormat.into(),
+                _ => Status::Failure.into(),
+            }
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<Status, Box<dyn Error>> {
+    if cli.no_color {
+        colored::control::set_override(false);
+    }
+    let mut config = Config::load_or_default(cli.config.as_deref())?;
+    if let Some(profile) = &cli.profile {
+        config = config.with_profile(profile)?;
+    }
     match cli.command {
-        Commands::Merge {
-            csv_file_paths,
-            currency,
-            output_dir,
-        } => merge_command(csv_file_paths, currency, output_dir),
+        Commands::Merge(args) => merge_command(*args, &config),
+        Commands::Consolidate(args) => consolidate_command(args, &config).map(|()| Status::Success),
+        Commands::Duplicates(args) => duplicates_command(args, &config).map(|()| Status::Success),
+        Commands::Report(args) => report_command(args, &config).map(|()| Status::Success),
+        Commands::Upgrade(args) => upgrade_command(args, &config).map(|()| Status::Success),
+        Commands::Detect(args) => detect_command(args).map(|()| Status::Success),
+        Commands::Rules(RulesCommand::Test(args)) => {
+            rules_test_command(args, &config).map(|()| Status::Success)
+        }
+        Commands::Alias(command) => alias_command(command, &config).map(|()| Status::Success),
     }
 }
 
-fn merge_command(
-    csv_file_paths: Vec<PathBuf>,
-    currency: String,
-    original_output_dir: Option<PathBuf>,
-) -> Result<(), Box<dyn Error>> {
-    let output_dir: PathBuf = match original_output_dir {
-        None => dirs::download_dir().unwrap(),
+/// A transaction in one line like its `Display`, with the amount in red or green and the source colored,
+/// so transactions from different banks stand out in the terminal
+fn colored_transaction(transaction: &CsvOutputRow) -> String {
+    // Each source always gets the same color; red and green are left for the amounts
+    let palette = [
+        Color::Cyan,
+        Color::Blue,
+        Color::Magenta,
+        Color::Yellow,
+        Color::BrightCyan,
+        Color::BrightBlue,
+        Color::BrightMagenta,
+        Color::BrightYellow,
+    ];
+    let hash = transaction.source.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte.into())
+    });
+    let source = transaction.source.color(palette[hash % palette.len()]);
+    let (direction, amount) = if transaction.amount.contains('-') {
+        ("paid to", transaction.amount.red())
+    } else {
+        ("from", transaction.amount.green())
+    };
+    format!(
+        "{} [{}] {} {} {} {} ({})",
+        transaction.date,
+        source,
+        transaction.currency,
+        amount,
+        direction,
+        transaction.payee,
+        transaction.transaction_type,
+    )
+}
+
+/// Ask the user a yes/no question on the terminal; anything other than "y" or "yes" means no
+fn confirm(question: &str) -> io::Result<bool> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Show each transaction with the tags of the rules and ask the user for more, separated by semicolons;
+/// an empty answer keeps the tags, and the end of the input (Ctrl-D) stops the review
+fn review_tags_in_terminal(transactions: &mut [CsvOutputRow]) -> io::Result<()> {
+    eprintln!("Add tags separated by semicolons (empty to keep them, Ctrl-D to stop reviewing)");
+    for transaction in transactions {
+        let tags = transaction
+            .extra
+            .get(&ExtraColumn::Tags)
+            .cloned()
+            .unwrap_or_default();
+        eprint!(
+            "{} {} [{}] ",
+            colored_transaction(transaction),
+            transaction.memo,
+            tags
+        );
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            eprintln!();
+            break;
+        }
+        transaction
+            .extra
+            .insert(ExtraColumn::Tags, add_tags(&tags, &answer));
+    }
+    Ok(())
+}
+
+/// Ask the user to pick one of the candidate formats; all formats are offered if there are no candidates
+fn choose_format(file_path: &Path, candidates: &[CsvFormat]) -> io::Result<Option<CsvFormat>> {
+    let options: &[CsvFormat] = if candidates.is_empty() {
+        &CsvFormat::ALL
+    } else {
+        candidates
+    };
+    eprintln!(
+        "{}",
+        format!(
+            "Could not detect the format of {} confidently. Which one is it?",
+            file_path.display()
+        )
+        .yellow()
+    );
+    for (index, format) in options.iter().enumerate() {
+        eprintln!("  {}) {}", index + 1, format);
+    }
+    eprint!("Number (empty to skip the file): ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| options.get(number.wrapping_sub(1)))
+        .copied())
+}
+
+/// Expand the output directory (default: download directory) and make sure it exists
+fn resolve_output_dir(output_dir: Option<PathBuf>) -> Result<PathBuf, Box<dyn Error>> {
+    let output_dir: PathBuf = match output_dir {
+        None => dirs::download_dir().ok_or("No download directory, use --output-dir")?,
         Some(output_dir) => {
             PathBuf::from(shellexpand::tilde(&output_dir.to_string_lossy()).to_string())
         }
@@ -75,149 +633,1626 @@ fn merge_command(
         )
         .into());
     }
+    Ok(output_dir)
+}
 
-    let mut currency_transactions: SortedSet<CsvOutputRow> = SortedSet::new();
-    let upper_currency = currency.to_uppercase();
-    for original_path in csv_file_paths {
-        let expanded_path =
-            PathBuf::from(shellexpand::tilde(&original_path.to_string_lossy()).to_string());
-        if !expanded_path.exists() {
+/// How output files are written, shared by the commands that generate them
+struct OutputOptions<'a> {
+    /// Overwrite existing files without asking
+    force: bool,
+    /// Add new transactions to existing files
+    append: bool,
+    /// Language of the column names
+    locale: Locale,
+    /// Optional columns written after the standard ones
+    extra_columns: &'a [ExtraColumn],
+    /// Print a table with the first and last N transactions instead of every transaction
+    preview: Option<usize>,
+    /// Encrypt the files to these age recipients; plain text if empty
+    encrypt_to: &'a [String],
+    /// Keep transactions of the same date in the order they were read
+    original_order: bool,
+    /// Keep only one of identical transactions
+    dedupe: bool,
+}
+
+/// Write transactions to an output file, protecting an existing file unless forced or appending;
+/// closing balance rows are written after the transactions
+///
+/// Returns the totals of the written file (including existing transactions when appending),
+/// or `None` if an existing file was skipped.
+fn write_output_file(
+    path: &Path,
+    transactions: &[&CsvOutputRow],
+    balance_rows: &[&CsvOutputRow],
+    options: &OutputOptions,
+) -> Result<Option<Totals>, Box<dyn Error>> {
+    let mut existing_transactions: Vec<CsvOutputRow> = Vec::new();
+    if path.exists() {
+        if options.append {
+            existing_transactions = read_output_file(path)?;
+        } else if !options.force {
+            let overwrite = io::stdin().is_terminal()
+                && confirm(&format!(
+                    "Output file {} already exists. Overwrite it?",
+                    path.display()
+                ))?;
+            if !overwrite {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Skipping existing output file {} (use --force to overwrite or --append to add new transactions)",
+                        path.display()
+                    )
+                    .yellow()
+                );
+                return Ok(None);
+            }
+        }
+    }
+    // When appending, a new transaction written like one already in the file is not added again;
+    // rows are compared as written, since new ones may have values of columns that are not in the file.
+    // Identical transactions are matched one to one, so a second coffee of the same price is still added
+    let written_fields = |trn: &CsvOutputRow| -> Vec<String> {
+        trn.to_record(options.extra_columns)
+            .iter()
+            .map(String::from)
+            .collect()
+    };
+    let mut existing_counts: BTreeMap<Vec<String>, usize> = BTreeMap::new();
+    let mut all_transactions: TransactionSet<&CsvOutputRow> = TransactionSet::new(options.dedupe);
+    for trn in existing_transactions.iter() {
+        *existing_counts.entry(written_fields(trn)).or_default() += 1;
+        all_transactions.push(trn);
+    }
+    for trn in transactions.iter().copied() {
+        match existing_counts.get_mut(&written_fields(trn)) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => {
+                all_transactions.push(trn);
+            }
+        }
+    }
+    let mut all_transactions: Vec<&CsvOutputRow> = all_transactions.iter().copied().collect();
+    if options.original_order {
+        sort_in_original_order(&mut all_transactions);
+    }
+
+    eprintln!(
+        "\nWriting output file {}",
+        path.display().to_string().bold()
+    );
+    let mut writer = Writer::from_writer(Vec::new());
+    writer.write_record(&CsvOutputRow::header(options.locale, options.extra_columns))?;
+    for trn in all_transactions.iter().chain(balance_rows) {
+        if options.preview.is_none() {
+            println!("{}", colored_transaction(trn));
+        }
+        writer.write_record(&trn.to_record(options.extra_columns))?;
+    }
+    if let Some(limit) = options.preview {
+        println!(
+            "{}",
+            preview_table(&all_transactions, options.locale, limit)
+        );
+    }
+    let content = writer.into_inner()?;
+    if options.encrypt_to.is_empty() {
+        std::fs::write(path, content)?;
+    } else {
+        std::fs::write(path, encrypt(&content, options.encrypt_to)?)?;
+    }
+    Ok(Some(Totals::of(all_transactions.iter().copied())))
+}
+
+/// The hooks of the `merge` command for each input file: it logs the file, records it in the history,
+/// and collects the rows that were dropped and the closing balances of its statement
+struct MergeRun<'a> {
+    /// The path of each file as given (not the decrypted one), a flag telling if it's temporary,
+    /// and its settings from the manifest
+    files: Vec<(PathBuf, bool, &'a ManifestInput)>,
+    /// The currency of the merge, in uppercase
+    upper_currency: &'a str,
+    /// Read all rows, without the date ranges of the manifest
+    no_filter: bool,
+    /// Compute the closing balances of the statements that have one
+    emit_balance_rows: bool,
+    /// Keep the dropped rows, not only their counts
+    collect_skipped: bool,
+    /// The entry of this run in the history file
+    history: &'a mut HistoryEntry,
+    /// Input files to archive after the output is written: index, path, source and latest transaction date
+    processed_files: &'a mut Vec<(usize, PathBuf, String, Option<NaiveDate>)>,
+    /// Rows dropped in all input files, per reason
+    skipped_counts: BTreeMap<SkipReason, usize>,
+    /// Rows dropped in all input files, with the reason; only with `collect_skipped`
+    skipped_rows: Vec<(SkipReason, CsvOutputRow)>,
+    /// Closing balance of each statement with a known balance, per month
+    balance_rows: Vec<CsvOutputRow>,
+    /// Files that were not read (missing, encrypted, unreadable, ...), for the exit code
+    skipped_files: usize,
+    /// Files with an unknown format, for the exit code
+    unknown_formats: usize,
+}
+
+impl MergeRun<'_> {
+    /// The currency to filter in a file, from the manifest or the one of the merge
+    fn file_currency(&self, index: usize) -> String {
+        self.files[index]
+            .2
+            .currency
+            .as_ref()
+            .map_or(self.upper_currency.to_string(), |currency| {
+                currency.to_uppercase()
+            })
+    }
+}
+
+impl MergeHandler for MergeRun<'_> {
+    fn options<'a>(
+        &mut self,
+        index: usize,
+        _file_path: &Path,
+        options: &'a MergeOptions,
+    ) -> Cow<'a, MergeOptions> {
+        let (path, _, settings) = &self.files[index];
+        let file_currency = self.file_currency(index);
+        if self.no_filter {
+            eprintln!("Parsing CSV file {} without filters", path.display());
+        } else {
             eprintln!(
-                "CSV file {} does not exist",
-                expanded_path.as_path().display()
+                "Parsing CSV file {} filtered by currency {}",
+                path.display(),
+                file_currency
             );
-            continue;
         }
-        eprintln!(
-            "Parsing CSV file {} filtered by currency {}",
-            expanded_path.as_path().display(),
-            upper_currency
-        );
+        // A format given in the manifest is used instead of detecting it
+        if settings.format.is_some() || settings.currency.is_some() {
+            Cow::Owned(MergeOptions {
+                format: settings.format,
+                currency: file_currency,
+                ..options.clone()
+            })
+        } else {
+            Cow::Borrowed(options)
+        }
+    }
 
-        let df_csv = match detect_separator(expanded_path.as_path()) {
-            Ok((separator, source)) => {
-                let temp_file = NamedTempFile::new()?;
-                let modified_path: &Path = match source {
-                    Some(Source::DKB) => {
-                        dkb_edit_file(expanded_path.as_path(), &temp_file)?;
-                        temp_file.path()
-                    }
-                    _ => expanded_path.as_path(),
-                };
-                CsvReader::from_path(modified_path)?
-                    .has_header(true)
-                    .with_try_parse_dates(true)
-                    .with_separator(separator)
-                    .truncate_ragged_lines(true)
-                    .finish()?
-            }
-            Err(err) => {
-                eprintln!("{}", err);
-                continue;
-            }
+    fn choose_format(
+        &mut self,
+        index: usize,
+        _file_path: &Path,
+        candidates: &[CsvFormat],
+    ) -> io::Result<Option<CsvFormat>> {
+        if io::stdin().is_terminal() {
+            choose_format(&self.files[index].0, candidates)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn read(&mut self, index: usize, _file_path: &Path, sha256: &str, content: &mut FileContent) {
+        let file_currency = self.file_currency(index);
+        let (path, extracted, settings) = &self.files[index];
+        let mut history_input = HistoryInput {
+            path: path.clone(),
+            sha256: sha256.to_string(),
+            format: None,
+            rows: 0,
+            skipped: None,
         };
-        let (source, df_filtered) = filter_data_frame(&df_csv, upper_currency.clone());
-
-        const DEFAULT_COLUMN_VALUE: AnyValue = AnyValue::String("");
-        let mut row = Row::new(vec![DEFAULT_COLUMN_VALUE; NUM_SELECT_COLUMNS]);
-        for row_index in 0..df_filtered.height() {
-            // https://stackoverflow.com/questions/72440403/iterate-over-rows-polars-rust
-            df_filtered.get_row_amortized(row_index, &mut row)?;
-
-            let mut currency = row.0[1].to_string();
-            let mut amount = row.0[2].to_string();
-            let transaction_type = strip_quotes(row.0[3].to_string());
-            let memo = row.0[5].to_string();
-
-            // Post-processing of rows according to the source
-            // TODO: on OOP this would be an abstract method overridden in base classes, but how to do this in Rust?
-            if source == Source::DKB {
-                if upper_currency == "EUR" {
-                    currency = "EUR".to_string();
-                } else {
-                    currency = upper_currency.clone();
-                    match dkb_extract_amount(&currency, &memo) {
-                        None => {
-                            continue;
-                        }
-                        Some(extracted_amount) => {
-                            // Turn the amount into a negative number
-                            amount = if amount.contains('-') {
-                                format!("-{}", extracted_amount)
-                            } else {
-                                extracted_amount
+        let (transactions, file_skipped_rows) = match content {
+            FileContent::Transactions {
+                format,
+                transactions,
+                last_date,
+                warning,
+                metadata,
+                skipped_rows,
+            } => {
+                if let Some(metadata) = metadata
+                    .as_ref()
+                    .filter(|metadata| **metadata != Default::default())
+                {
+                    eprintln!("Statement: {}", metadata);
+                    // Only DKB statements have a balance, always in euros
+                    let balance = metadata
+                        .balance
+                        .as_ref()
+                        .and_then(|balance| balance.replace(',', ".").parse::<f64>().ok());
+                    if let (true, "EUR", Some(balance), Some(balance_date)) = (
+                        self.emit_balance_rows,
+                        file_currency.as_str(),
+                        balance,
+                        metadata.balance_date,
+                    ) {
+                        // Rows dropped by filters still moved money; only other currencies are left out
+                        let statement_rows: Vec<CsvOutputRow> = transactions
+                            .iter()
+                            .chain(
+                                skipped_rows
+                                    .iter()
+                                    .filter(|(reason, _)| *reason != SkipReason::Currency)
+                                    .map(|(_, transaction)| transaction),
+                            )
+                            .cloned()
+                            .collect();
+                        for (date, amount) in
+                            monthly_closing_balances(balance, balance_date, &statement_rows)
+                        {
+                            let mut row = CsvOutputRow::builder(date)
+                                .source(format.source().to_string())
+                                .currency(file_currency.clone())
+                                .amount(format!("{:.2}", amount).replace('.', ","))
+                                .transaction_type(CLOSING_BALANCE_TYPE)
+                                .payee(metadata.account.clone().unwrap_or_default())
+                                .build();
+                            if let Some(account) = &settings.account {
+                                row.extra.insert(ExtraColumn::Account, account.clone());
                             }
+                            self.balance_rows.push(row);
                         }
                     }
                 }
-            } else if source == Source::N26 && transaction_type == "Presentment" {
-                // The new file format doesn't seem to have negative amounts anymore,
-                // but different transaction types instead, e.g. A refund is "Presentment Refund"
-                // Turn the amount into a negative number
-                amount = format!("-{}", amount);
-            }
-
-            let naive_date = match row.0[0].try_extract::<i32>() {
-                Ok(gregorian_days) => {
-                    NaiveDate::from_num_days_from_ce_opt(gregorian_days + EPOCH_DAYS_FROM_CE)
-                        .unwrap()
+                if let Some(warning) = warning {
+                    eprintln!("{}", format!("{}: {}", path.display(), warning).yellow());
                 }
-                // Some CSVs hve the date in the German format
-                Err(_) => {
-                    let date_str = row.0[0].get_str().unwrap();
-                    if date_str.len() == 8 {
-                        // The new DKB file format has dates with 2-digit years... ¯\_(ツ)_/¯
-                        NaiveDate::parse_from_str(date_str, "%d.%m.%y")?
-                    } else {
-                        NaiveDate::parse_from_str(date_str, "%d.%m.%Y")?
-                    }
+                history_input.format = Some(format.to_string());
+                if !extracted {
+                    self.processed_files.push((
+                        index,
+                        path.clone(),
+                        format.source().to_string(),
+                        *last_date,
+                    ));
+                }
+                (transactions, std::mem::take(skipped_rows))
+            }
+            FileContent::Custom {
+                name,
+                source,
+                transactions,
+                last_date,
+                skipped_rows,
+            } => {
+                history_input.format = Some(name.clone());
+                if !extracted {
+                    self.processed_files
+                        .push((index, path.clone(), source.clone(), *last_date));
                 }
+                (transactions, std::mem::take(skipped_rows))
+            }
+            FileContent::Output(transactions) => (transactions, Vec::new()),
+            FileContent::Skipped(_) | FileContent::UnknownFormat(_) => return,
+        };
+        let (in_range, out_of_range): (Vec<_>, Vec<_>) = std::mem::take(transactions)
+            .into_iter()
+            .partition(|transaction| self.no_filter || settings.includes(transaction.date));
+        for (reason, transaction) in file_skipped_rows.into_iter().chain(
+            out_of_range
+                .into_iter()
+                .map(|transaction| (SkipReason::DateRange, transaction)),
+        ) {
+            *self.skipped_counts.entry(reason).or_default() += 1;
+            if self.collect_skipped {
+                self.skipped_rows.push((reason, transaction));
+            }
+        }
+        history_input.rows = in_range.len();
+        *transactions = in_range;
+        // Account names of multi-account exports are more specific than the label of the file
+        if let Some(account) = &settings.account {
+            for transaction in transactions.iter_mut() {
+                transaction
+                    .extra
+                    .entry(ExtraColumn::Account)
+                    .or_insert_with(|| account.clone());
+            }
+        }
+        self.history.inputs.push(history_input);
+    }
+
+    fn ignored(
+        &mut self,
+        index: usize,
+        _file_path: &Path,
+        sha256: Option<&str>,
+        err: &BankCsvError,
+    ) {
+        let path = &self.files[index].0;
+        // The path of a decrypted file is a temporary one, so the error is shown with the original path
+        eprintln!(
+            "{}",
+            format!("{}: {}", path.display(), err.reason()).yellow()
+        );
+        match err {
+            BankCsvError::Duplicate { .. } => {}
+            BankCsvError::UnknownFormat { .. } => self.unknown_formats += 1,
+            _ => self.skipped_files += 1,
+        }
+        // Missing files are not in the history
+        if let Some(sha256) = sha256 {
+            self.history.inputs.push(HistoryInput {
+                path: path.clone(),
+                sha256: sha256.to_string(),
+                format: None,
+                rows: 0,
+                skipped: Some(err.reason()),
+            });
+        }
+    }
+}
+
+fn merge_command(args: MergeArgs, config: &Config) -> Result<Status, Box<dyn Error>> {
+    let MergeArgs {
+        csv_file_paths,
+        manifest,
+        currency,
+        file_currency,
+        output_dir,
+        force,
+        append,
+        locale,
+        date_basis,
+        group_by,
+        split_by_account,
+        emit_balance_rows,
+        with_id,
+        with_time,
+        time_zone,
+        securities,
+        vat,
+        euer,
+        invoice,
+        normalize_types,
+        keep_zero_amounts,
+        keep_internal_transfers,
+        payees_file,
+        exclude_payees,
+        tags,
+        review_tags,
+        suggest_categories,
+        clean_payees,
+        paypal_conversions,
+        dkb_conversions,
+        paypal_balance_impact,
+        original_order,
+        dedupe,
+        no_filter,
+        exclude_before_account_opening,
+        max_days_ahead,
+        preview,
+        encrypt_to,
+        identity,
+        zip_password,
+        mask_pii: mask_personal_data,
+        pseudonymize: use_pseudonyms,
+        pseudonym_key,
+        assume,
+        formats_dir,
+        script,
+        state_file,
+        since_last_run,
+        index,
+        write_skipped,
+        package,
+        package_overview,
+        history_file,
+        archive_dir,
+        archive_copy,
+    } = args;
+    let tags = tags || review_tags;
+    let archive_dir = archive_dir
+        .map(|dir| resolve_output_dir(Some(dir)))
+        .transpose()?;
+    let package =
+        package.map(|path| PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).to_string()));
+    // The package always has the skipped transactions, even without --write-skipped
+    let collect_skipped = write_skipped || package.is_some();
+    // Monthly files of this run, to bundle in the package
+    let mut package_files: Vec<PathBuf> = Vec::new();
+    // Input files to archive after the output is written: index, path, source and latest transaction date
+    let mut processed_files: Vec<(usize, PathBuf, String, Option<NaiveDate>)> = Vec::new();
+    // Indexes of the input files with transactions in an output file that was skipped; they are not archived
+    let mut unwritten_inputs: HashSet<usize> = HashSet::new();
+    let state_file = state_file
+        .or_else(|| config.state_file.clone())
+        .or_else(|| since_last_run.then(State::default_path).flatten())
+        .map(|path| PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).to_string()));
+    let history_file = history_file
+        .or_else(|| config.history_file.clone())
+        .map(|path| PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).to_string()));
+    let mut sources = SourceRegistry::default();
+    if let Some(dir) = formats_dir
+        .or_else(|| config.formats_dir.clone())
+        .or_else(FormatDescriptor::default_dir)
+    {
+        for descriptor in load_formats(Path::new(
+            &shellexpand::tilde(&dir.to_string_lossy()).to_string(),
+        ))? {
+            sources.register(descriptor);
+        }
+    }
+    let script = script
+        .or_else(|| config.script.clone())
+        .map(|path| {
+            RowScript::load(Path::new(
+                &shellexpand::tilde(&path.to_string_lossy()).to_string(),
+            ))
+        })
+        .transpose()?;
+    let aliases = match aliases_path(config) {
+        Some(path) => AliasStore::load(&path)?,
+        None => AliasStore::default(),
+    };
+    let mut history = HistoryEntry::new("merge");
+    let mut state = match &state_file {
+        Some(path) => State::load(path)?,
+        None => State::default(),
+    };
+    // Filter with the dates of the previous run, before they are updated by this one
+    let previous_state = since_last_run.then(|| State {
+        last_dates: state.last_dates.clone(),
+    });
+    // Inputs of the manifest come after the ones of the command line
+    let mut inputs: Vec<ManifestInput> = csv_file_paths
+        .into_iter()
+        .map(ManifestInput::from_path)
+        .collect();
+    if let Some(manifest_path) = manifest {
+        inputs.extend(load_manifest(&manifest_path)?);
+    }
+    for file_currency in &file_currency {
+        let (file, currency) = file_currency
+            .split_once('=')
+            .filter(|(file, currency)| !file.is_empty() && !currency.is_empty())
+            .ok_or_else(|| format!("--file-currency {:?} is not FILE=CURRENCY", file_currency))?;
+        let mut found = false;
+        for input in inputs.iter_mut().filter(|input| {
+            input.path == Path::new(file)
+                || input.path.file_name() == Some(std::ffi::OsStr::new(file))
+        }) {
+            input.currency = Some(currency.to_string());
+            found = true;
+        }
+        if !found {
+            return Err(format!("--file-currency: {} is not one of the input files", file).into());
+        }
+    }
+    let mut extra_columns: Vec<ExtraColumn> = Vec::new();
+    if with_time {
+        extra_columns.push(ExtraColumn::Time);
+    }
+    if inputs.iter().any(|input| input.account.is_some()) {
+        extra_columns.push(ExtraColumn::Account);
+    }
+    let time_zone = match time_zone.or_else(|| config.time_zone.clone()) {
+        Some(name) if with_time => {
+            Some(parse_time_zone(&name).ok_or_else(|| format!("unknown time zone {:?}", name))?)
+        }
+        _ => None,
+    };
+    if with_id {
+        extra_columns.push(ExtraColumn::Id);
+    }
+    if securities {
+        extra_columns.extend(ExtraColumn::SECURITIES);
+    }
+    let vat = vat || euer;
+    if euer {
+        extra_columns.extend(ExtraColumn::EUER);
+    } else if vat {
+        extra_columns.extend(ExtraColumn::VAT);
+    }
+    let invoice_patterns: Vec<Regex> = if !invoice {
+        Vec::new()
+    } else if config.invoice_patterns.is_empty() {
+        vec![Regex::new(DEFAULT_INVOICE_PATTERN)?]
+    } else {
+        config
+            .invoice_patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<_, _>>()?
+    };
+    if invoice {
+        extra_columns.push(ExtraColumn::Invoice);
+    }
+    // Configured rules are tried before the built-in ones
+    let payee_rules: Vec<(Regex, String)> = if clean_payees {
+        RuleSet::from_config(config)?.payee_rules
+    } else {
+        Vec::new()
+    };
+    if normalize_types {
+        extra_columns.push(ExtraColumn::NormalizedType);
+    }
+    if tags {
+        extra_columns.push(ExtraColumn::Tags);
+    }
+    if dkb_conversions {
+        extra_columns.extend(ExtraColumn::DKB_CONVERSIONS);
+    }
+    let euer_locale = euer.then_some(Locale::De);
+    let locale = locale.or(euer_locale).or(config.locale).unwrap_or_default();
+    let date_basis = date_basis.or(config.date_basis).unwrap_or_default();
+    let euer_group_by = euer.then_some(GroupBy::Quarter);
+    let group_by = group_by
+        .or(euer_group_by)
+        .or(config.group_by)
+        .unwrap_or_default();
+    let paypal_balance_impact = if paypal_balance_impact.is_empty() {
+        config
+            .paypal_balance_impact
+            .clone()
+            .unwrap_or_else(|| vec![BalanceImpact::Debit])
+    } else {
+        paypal_balance_impact
+    };
+    // Only keep the key when pseudonyms are requested, so it's used as a flag below
+    let pseudonym_key = if use_pseudonyms {
+        Some(
+            pseudonym_key
+                .or_else(|| config.pseudonym_key.clone())
+                .ok_or("--pseudonymize needs a key: use --pseudonym-key or pseudonym_key in the configuration")?,
+        )
+    } else {
+        None
+    };
+    // Command line flags and environment variables take precedence over the config file
+    let currency = currency
+        .or_else(|| config.currency.clone())
+        .unwrap_or_else(|| "EUR".to_string());
+    let output_dir = output_dir.or_else(|| config.output_dir.clone());
+    // Monthly files for cloud storage are written to a temporary directory, then uploaded
+    let upload_dir = output_dir
+        .as_ref()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .filter(|dir| is_cloud_url(dir));
+    if upload_dir.is_some() && append {
+        return Err("--append is not supported when writing to cloud storage".into());
+    }
+    let output_temp_dir = TempDir::new()?;
+    let output_dir = match upload_dir {
+        Some(_) => output_temp_dir.path().to_path_buf(),
+        None => resolve_output_dir(output_dir)?,
+    };
+    // Held until the end of the merge, so another run doesn't write the same monthly files
+    let _lock = match upload_dir {
+        Some(_) => None,
+        None => Some(lock_output_dir(&output_dir).map_err(output_error)?),
+    };
+    // Monthly files of an older schema are only rewritten by `upgrade`, not mixed with the current one
+    let outdated_files = upload_dir.is_none()
+        && schema_version(&output_dir)? < SCHEMA_VERSION
+        && monthly_files(&output_dir).is_ok();
+    if outdated_files && append {
+        return Err(format!(
+            "The monthly files in {} were written by an older version; run `bank-csv upgrade` before appending to them",
+            output_dir.display()
+        )
+        .into());
+    }
+    let classifier = if suggest_categories {
+        extra_columns.extend([ExtraColumn::SuggestedCategory, ExtraColumn::Confidence]);
+        // A new output directory has no monthly files yet, so there is nothing to learn from
+        let mut previous_transactions = Vec::new();
+        for path in monthly_files(&output_dir).unwrap_or_default() {
+            previous_transactions.extend(read_output_file(&path)?);
+        }
+        let classifier = Classifier::train(&previous_transactions);
+        if classifier.is_empty() {
+            eprintln!(
+                "{}",
+                format!(
+                    "No categorized transactions in {} to learn from, no categories will be suggested",
+                    output_dir.display()
+                )
+                .yellow()
+            );
+        }
+        Some(classifier)
+    } else {
+        None
+    };
+
+    let upper_currency = currency.to_uppercase();
+    let merge_options = MergeOptions {
+        currency,
+        date_basis,
+        assume,
+        format: None,
+        securities,
+        vat,
+        net_amounts: euer,
+        invoice_patterns,
+        payee_rules,
+        memo_templates: config.memo_templates.clone(),
+        source_filters: config.sources.clone(),
+        mask_pii: mask_personal_data,
+        pseudonym_key,
+        split_rules: config.split_rules.clone(),
+        type_mappings: normalize_types.then(|| config.type_mappings.clone()),
+        tag_rules: tags.then(|| config.tag_rules.clone()),
+        paypal_conversions,
+        dkb_conversions,
+        paypal_balance_impact: paypal_balance_impact.clone(),
+        with_time,
+        time_zone,
+        keep_zero_amounts,
+        keep_internal_transfers,
+        payee_filter: payees_file
+            .map(|path| PayeeFilter::load(&path, exclude_payees))
+            .transpose()?,
+        read_other_currencies: collect_skipped,
+        dedupe,
+        plausible_dates: exclude_before_account_opening.map(|earliest| {
+            let today = chrono::Local::now().date_naive();
+            (earliest, today + chrono::Days::new(max_days_ahead.into()))
+        }),
+        sources,
+        script,
+        classifier,
+        no_filter,
+        aliases,
+    };
+
+    // CSV files inside ZIP archives and files in cloud storage are extracted or downloaded
+    // to a temporary directory, deleted at the end.
+    // Input paths are paired with a flag telling if they are temporary (they are not archived)
+    let extract_dir = TempDir::new()?;
+    // Input paths are also paired with the index of their settings in `inputs`
+    let mut input_paths: Vec<(PathBuf, bool, usize)> = Vec::new();
+    for (index, input) in inputs.iter().enumerate() {
+        let original_path = &input.path;
+        // One directory per input, in case they contain files with the same name
+        let target_dir = extract_dir.path().join(index.to_string());
+        let location = original_path.to_string_lossy().to_string();
+        let (expanded_path, downloaded) = if is_cloud_url(&location) {
+            std::fs::create_dir(&target_dir)?;
+            (download(&location, &target_dir)?, true)
+        } else {
+            (
+                PathBuf::from(shellexpand::tilde(&location).to_string()),
+                false,
+            )
+        };
+        if !is_zip_file(&expanded_path) || !expanded_path.exists() {
+            input_paths.push((expanded_path, downloaded, index));
+            continue;
+        }
+        let password = match &zip_password {
+            Some(password) => Some(password.clone()),
+            None if needs_password(&expanded_path)? && io::stdin().is_terminal() => Some(
+                rpassword::prompt_password(format!("Password of {}: ", expanded_path.display()))?,
+            ),
+            None => None,
+        };
+        if !downloaded {
+            std::fs::create_dir(&target_dir)?;
+        }
+        for extracted_path in extract_csv_files(&expanded_path, &target_dir, password.as_deref())? {
+            input_paths.push((extracted_path, true, index));
+        }
+    }
+
+    // Encrypted files are decrypted to temporary files, deleted once the files are merged
+    let mut decrypted_files = Vec::new();
+    // The paths that are read, and the path, temporary flag and settings of each one for the messages
+    let mut read_paths: Vec<PathBuf> = Vec::new();
+    let mut files: Vec<(PathBuf, bool, &ManifestInput)> = Vec::new();
+    // Files that were not read decide the exit code
+    let mut skipped_files = 0;
+    for (expanded_path, extracted, input_index) in input_paths {
+        if expanded_path.exists() && is_encrypted(&expanded_path) {
+            let Some(identity_file) = identity.as_deref() else {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "{}: encrypted file, use --identity to decrypt it",
+                        expanded_path.display()
+                    )
+                    .yellow()
+                );
+                history.inputs.push(HistoryInput {
+                    // The file is only recorded, so an unreadable one gets an empty hash
+                    sha256: file_hash(&expanded_path).unwrap_or_default(),
+                    path: expanded_path,
+                    format: None,
+                    rows: 0,
+                    skipped: Some("encrypted file without --identity".to_string()),
+                });
+                skipped_files += 1;
+                continue;
             };
-            let transaction = CsvOutputRow::new(
-                naive_date,
-                source.to_string(),
-                currency,
-                amount,
-                transaction_type,
-                row.0[4].to_string(),
-                memo,
+            let decrypted_file = decrypt_file(&expanded_path, identity_file)?;
+            read_paths.push(decrypted_file.path().to_path_buf());
+            decrypted_files.push(decrypted_file);
+        } else {
+            read_paths.push(expanded_path.clone());
+        }
+        files.push((expanded_path, extracted, &inputs[input_index]));
+    }
+    let mut run = MergeRun {
+        files,
+        upper_currency: &upper_currency,
+        no_filter,
+        emit_balance_rows,
+        collect_skipped,
+        history: &mut history,
+        processed_files: &mut processed_files,
+        skipped_counts: BTreeMap::new(),
+        skipped_rows: Vec::new(),
+        balance_rows: Vec::new(),
+        skipped_files,
+        unknown_formats: 0,
+    };
+    let mut merged = merge_files_with(&read_paths, &merge_options, &mut run)?;
+    drop(decrypted_files);
+    if review_tags {
+        if io::stdin().is_terminal() {
+            review_tags_in_terminal(&mut merged.transactions)?;
+        } else {
+            eprintln!(
+                "{}",
+                "--review-tags needs a terminal; only the tags of the rules were added".yellow()
             );
-            currency_transactions.push(transaction);
         }
     }
+    let MergeRun {
+        skipped_counts,
+        mut skipped_rows,
+        balance_rows,
+        skipped_files,
+        unknown_formats,
+        ..
+    } = run;
+
+    // Multi-account exports have an account name per row, even without accounts in the manifest
+    if !extra_columns.contains(&ExtraColumn::Account)
+        && merged
+            .transactions
+            .iter()
+            .any(|transaction| transaction.extra.contains_key(&ExtraColumn::Account))
+    {
+        extra_columns.push(ExtraColumn::Account);
+    }
+    sort_columns(&mut extra_columns);
 
-    // Group transactions by year and month
-    let mut transaction_map: HashMap<(i32, u32), SortedSet<&CsvOutputRow>> = HashMap::new();
-    for transaction in currency_transactions.iter() {
-        let date = transaction.date;
-        let year = date.year();
-        let month = date.month();
-        let key = (year, month);
-        let transactions_for_key = transaction_map.entry(key).or_default();
+    // Group transactions by currency (more than one with --file-currency), account
+    // (only with --split-by-account) and period (year and month, week or quarter)
+    let file_key = |transaction: &CsvOutputRow| {
+        (
+            transaction.currency.to_uppercase(),
+            transaction
+                .extra
+                .get(&ExtraColumn::Account)
+                .filter(|_| split_by_account)
+                .map(|account| file_name_part(account)),
+            group_by.period(transaction.date),
+        )
+    };
+    type FileKey = (String, Option<String>, String);
+    let mut transaction_map: HashMap<FileKey, TransactionSet<&CsvOutputRow>> = HashMap::new();
+    let mut balance_map: HashMap<FileKey, Vec<&CsvOutputRow>> = HashMap::new();
+    for row in &balance_rows {
+        balance_map.entry(file_key(row)).or_default().push(row);
+    }
+    let mut already_exported = 0;
+    for transaction in &merged.transactions {
+        if previous_state.as_ref().is_some_and(|previous| {
+            previous.is_already_exported(
+                &transaction.source,
+                transaction
+                    .extra
+                    .get(&ExtraColumn::Account)
+                    .map(String::as_str),
+                transaction.date,
+            )
+        }) {
+            already_exported += 1;
+            continue;
+        }
+        let transactions_for_key = transaction_map.entry(file_key(transaction)).or_default();
         transactions_for_key.push(transaction);
     }
 
-    // Sort by year and month
+    // Periods are zero-padded, so they sort chronologically within each account
     let mut sorted_keys = transaction_map.keys().collect::<Vec<_>>();
     sorted_keys.sort();
 
-    // Write one CSV per year/month
-    for &(year, month) in &sorted_keys {
-        let transactions = transaction_map.get(&(*year, *month)).unwrap();
-        let year_month_filename = format!(
-            "bank-csv-transactions-{}-{:04}-{:02}.csv",
-            upper_currency, year, month
-        );
+    // Write one CSV per period
+    let output_options = OutputOptions {
+        force,
+        append,
+        locale,
+        extra_columns: &extra_columns,
+        preview,
+        encrypt_to: &encrypt_to,
+        original_order,
+        dedupe,
+    };
+    let mut index_entries: Vec<IndexEntry> = Vec::new();
+    // Existing output files that were not overwritten also decide the exit code
+    let mut skipped_outputs = 0;
+    for key in &sorted_keys {
+        let (currency, account, period) = key;
+        let transactions = transaction_map.get(*key).unwrap();
+        let file_period = match account {
+            Some(account) => format!("{}-{}", account, period),
+            None => period.clone(),
+        };
+        let mut period_filename = format!("bank-csv-transactions-{}-{}.csv", currency, file_period);
+        if !encrypt_to.is_empty() {
+            period_filename = format!("{}.{}", period_filename, AGE_EXTENSION);
+        }
         let mut new_path = output_dir.clone();
-        new_path.push(year_month_filename);
-        eprintln!("\nWriting output file {}", new_path.as_path().display());
-        let mut writer = Writer::from_path(new_path)?;
-        writer.write_record(&CsvOutputRow::header())?;
-        for trn in transactions.iter() {
-            println!("{}", trn);
-            writer.write_record(&trn.to_record())?;
-        }
-        writer.flush()?;
+        new_path.push(period_filename);
+        let transactions: Vec<&CsvOutputRow> = transactions.iter().copied().collect();
+        let file_balance_rows = balance_map.get(*key).map_or(&[][..], Vec::as_slice);
+        let totals = write_output_file(
+            new_path.as_path(),
+            &transactions,
+            file_balance_rows,
+            &output_options,
+        )
+        .map_err(output_error)?;
+        let mut written = totals.is_some();
+        if let (true, Some(dir)) = (written, &upload_dir) {
+            if upload(&new_path, dir, force).map_err(output_error)? {
+                eprintln!("Uploaded to {}", dir.bold());
+            } else {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Skipping existing file in {} (use --force to overwrite it)",
+                        dir
+                    )
+                    .yellow()
+                );
+                // A file kept in cloud storage is skipped like an existing local file
+                written = false;
+            }
+        }
+        if !written {
+            skipped_outputs += 1;
+        }
+        // Dates of skipped files are not recorded, so the next --since-last-run still exports them
+        for transaction in &transactions {
+            if written {
+                state.record(
+                    &transaction.source,
+                    transaction
+                        .extra
+                        .get(&ExtraColumn::Account)
+                        .map(String::as_str),
+                    transaction.date,
+                );
+            } else if let Some((file_index, _)) = transaction.sequence {
+                unwritten_inputs.insert(file_index);
+            }
+        }
+        // Existing files that were not overwritten still have the transactions of their period
+        if package.is_some() && new_path.exists() {
+            package_files.push(new_path.clone());
+        }
+        if let Some(totals) = totals.filter(|_| written) {
+            index_entries.push(IndexEntry::new(
+                new_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                period.to_string(),
+                currency.clone(),
+                totals,
+            ));
+        }
+        history.outputs.push(HistoryOutput {
+            path: match &upload_dir {
+                Some(dir) => format!(
+                    "{}/{}",
+                    dir.trim_end_matches('/'),
+                    new_path.file_name().unwrap_or_default().to_string_lossy()
+                ),
+                None => new_path.display().to_string(),
+            },
+            rows: transactions.len(),
+            written,
+        });
+    }
+
+    // Files regenerated on every run, also bundled in the package: name and content
+    let mut run_files: Vec<(String, Vec<u8>)> = Vec::new();
+    if package.is_some() && !index_entries.is_empty() {
+        run_files.push((INDEX_FILE_NAME.to_string(), index_csv(&index_entries)?));
+    }
+    // The index also lists the monthly files of earlier runs, which were not written this time
+    let mut all_index_entries = index_entries.clone();
+    if index {
+        all_index_entries.extend(earlier_index_entries(&output_dir, &index_entries)?);
+        all_index_entries.sort_by(|left, right| left.file.cmp(&right.file));
+    }
+    if index && !all_index_entries.is_empty() {
+        let index_path = write_run_file(
+            &output_dir,
+            INDEX_FILE_NAME,
+            &index_csv(&all_index_entries)?,
+            &encrypt_to,
+            upload_dir.as_deref(),
+        )
+        .map_err(output_error)?;
+        eprintln!(
+            "\nWriting index file {}",
+            index_path.display().to_string().bold()
+        );
+    }
+    if collect_skipped && !skipped_rows.is_empty() {
+        let mut writer = Writer::from_writer(Vec::new());
+        let mut header = CsvOutputRow::header(locale, &extra_columns);
+        header.push_field(match locale {
+            Locale::En => "Reason",
+            Locale::De => "Grund",
+        });
+        writer.write_record(&header)?;
+        skipped_rows.sort_by(|(_, left), (_, right)| left.cmp(right));
+        for (reason, transaction) in &skipped_rows {
+            let mut record = transaction.to_record(&extra_columns);
+            record.push_field(&reason.to_string());
+            writer.write_record(&record)?;
+        }
+        let skipped_file = (
+            format!("bank-csv-skipped-{}.csv", upper_currency),
+            writer.into_inner()?,
+        );
+        if write_skipped {
+            let skipped_path = write_run_file(
+                &output_dir,
+                &skipped_file.0,
+                &skipped_file.1,
+                &encrypt_to,
+                upload_dir.as_deref(),
+            )
+            .map_err(output_error)?;
+            eprintln!(
+                "\nWriting skipped transactions to {}",
+                skipped_path.display().to_string().bold()
+            );
+        }
+        run_files.push(skipped_file);
+    }
+    for (reason, count) in &skipped_counts {
+        let hint = match reason {
+            SkipReason::ZeroAmount => " (use --keep-zero-amounts to keep them)",
+            SkipReason::InternalTransfer => " (use --keep-internal-transfers to keep them)",
+            SkipReason::ImplausibleDate => " (see --exclude-before-account-opening)",
+            SkipReason::Payee
+            | SkipReason::Currency
+            | SkipReason::DateRange
+            | SkipReason::Script => "",
+        };
+        eprintln!("\nSkipped {} transaction(s): {}{}", count, reason, hint);
+    }
+    if already_exported > 0 {
+        eprintln!(
+            "\nSkipped {} transaction(s) already exported by the previous run",
+            already_exported
+        );
+    }
+    if upload_dir.is_none() && !outdated_files {
+        write_schema_version(&output_dir).map_err(output_error)?;
+    }
+    if let Some(path) = state_file {
+        state.save(&path)?;
+    }
+    history.settings = BTreeMap::from([
+        ("currency".to_string(), upper_currency.clone()),
+        ("locale".to_string(), format!("{:?}", locale)),
+        ("date_basis".to_string(), format!("{:?}", date_basis)),
+        ("group_by".to_string(), format!("{:?}", group_by)),
+        (
+            "paypal_balance_impact".to_string(),
+            format!("{:?}", paypal_balance_impact),
+        ),
+        (
+            "extra_columns".to_string(),
+            extra_columns
+                .iter()
+                .map(|column| column.label(Locale::En))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        ("since_last_run".to_string(), since_last_run.to_string()),
+        ("force".to_string(), force.to_string()),
+        ("append".to_string(), append.to_string()),
+    ]);
+    if let Some(path) = history_file {
+        history.append_to(&path)?;
+    }
+    if let Some(package_path) = package {
+        run_files.push((
+            SUMMARY_FILE_NAME.to_string(),
+            serde_json::to_vec_pretty(&history)?,
+        ));
+        if package_overview {
+            let skipped: Vec<(String, usize)> = skipped_counts
+                .iter()
+                .map(|(reason, count)| (reason.to_string(), *count))
+                .collect();
+            run_files.push((
+                OVERVIEW_FILE_NAME.to_string(),
+                overview_html(&index_entries, &skipped).into_bytes(),
+            ));
+        }
+        // Like in the output directory, the files are encrypted with --encrypt-to
+        let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+        for path in &package_files {
+            files.push((
+                path.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                std::fs::read(path)?,
+            ));
+        }
+        for (name, content) in run_files {
+            files.push(if encrypt_to.is_empty() {
+                (name, content)
+            } else {
+                (
+                    format!("{}.{}", name, AGE_EXTENSION),
+                    encrypt(&content, &encrypt_to)?,
+                )
+            });
+        }
+        write_package(&package_path, &files).map_err(output_error)?;
+        eprintln!(
+            "\nWriting package {} with {} file(s)",
+            package_path.display().to_string().bold(),
+            files.len()
+        );
+    }
+    if let Some(archive_dir) = archive_dir {
+        for (file_index, path, source, last_date) in processed_files {
+            if unwritten_inputs.contains(&file_index) {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Not archiving {}: some of its output files were skipped",
+                        path.display()
+                    )
+                    .yellow()
+                );
+                continue;
+            }
+            let archived_path =
+                archive_file(&path, &archive_dir, &source, last_date, archive_copy)?;
+            eprintln!("Archived {} as {}", path.display(), archived_path.display());
+        }
+    }
+    Ok(if unknown_formats > 0 {
+        Status::UnknownFormat
+    } else if skipped_files > 0 || skipped_outputs > 0 {
+        Status::Partial
+    } else {
+        Status::Success
+    })
+}
+
+/// Write a file that is regenerated on every run (e.g. the index) to the output directory,
+/// encrypted like the monthly files, and upload it if the output is in cloud storage
+///
+/// The file always replaces the previous one. Returns its local path.
+fn write_run_file(
+    output_dir: &Path,
+    file_name: &str,
+    content: &[u8],
+    encrypt_to: &[String],
+    upload_dir: Option<&str>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    // These files have transaction data too, so they are as private as the monthly files
+    let path = if encrypt_to.is_empty() {
+        let path = output_dir.join(file_name);
+        std::fs::write(&path, content)?;
+        path
+    } else {
+        let path = output_dir.join(format!("{}.{}", file_name, AGE_EXTENSION));
+        std::fs::write(&path, encrypt(content, encrypt_to)?)?;
+        path
+    };
+    if let Some(dir) = upload_dir {
+        upload(&path, dir, true)?;
+    }
+    Ok(path)
+}
+
+/// Index lines of the monthly files in the output directory that are not in `written`, e.g. files of earlier runs
+/// or existing files that were not overwritten; encrypted files can't be read and are left out
+fn earlier_index_entries(
+    output_dir: &Path,
+    written: &[IndexEntry],
+) -> Result<Vec<IndexEntry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    // A new output directory has no monthly files yet
+    for path in monthly_files(output_dir).unwrap_or_default() {
+        let file_name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if written.iter().any(|entry| entry.file == file_name) {
+            continue;
+        }
+        // Names were checked by `monthly_files`, e.g. `bank-csv-transactions-EUR-2024-01.csv`
+        let stem = file_name
+            .trim_start_matches("bank-csv-transactions-")
+            .trim_end_matches(".csv");
+        let currency = stem.split('-').next().unwrap_or_default().to_string();
+        let period = match stem.rsplitn(3, '-').collect::<Vec<_>>()[..] {
+            [period, year, _] => format!("{}-{}", year, period),
+            _ => continue,
+        };
+        let totals = Totals::of(&read_output_file(&path)?);
+        entries.push(IndexEntry::new(file_name, period, currency, totals));
+    }
+    Ok(entries)
+}
+
+/// Move or copy an input file to the archive directory, with a name based on its month and source
+/// (e.g. `2024-09-dkb.csv`); a numeric suffix is added if the name is already taken.
+///
+/// The month is the one of the latest transaction, or of the last modification if there are none.
+/// The extension of the input is kept, so encrypted files are still recognized (e.g. `2024-09-dkb.csv.age`).
+fn archive_file(
+    path: &Path,
+    archive_dir: &Path,
+    source: &str,
+    last_date: Option<NaiveDate>,
+    copy: bool,
+) -> io::Result<PathBuf> {
+    let date = match last_date {
+        Some(date) => date,
+        None => DateTime::<chrono::Local>::from(std::fs::metadata(path)?.modified()?).date_naive(),
+    };
+    let stem = format!("{}-{}", date.format("%Y-%m"), source.to_lowercase());
+    let extension = archived_extension(path);
+    let mut archived_path = archive_dir.join(format!("{}.{}", stem, extension));
+    let mut suffix = 1;
+    while archived_path.exists() {
+        suffix += 1;
+        archived_path = archive_dir.join(format!("{}-{}.{}", stem, suffix, extension));
+    }
+    // Renaming fails across file systems, so fall back to copying and removing
+    if copy || std::fs::rename(path, &archived_path).is_err() {
+        std::fs::copy(path, &archived_path)?;
+        if !copy {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(archived_path)
+}
+
+/// The extension of an archived file: the one of the input, with the one before it for encrypted files
+/// (e.g. `csv.age`), or `csv` if it has none
+fn archived_extension(path: &Path) -> String {
+    let extension = |path: &Path| {
+        path.extension()
+            .map(|extension| extension.to_string_lossy().into_owned())
+    };
+    match extension(path) {
+        Some(last) if last == AGE_EXTENSION => match extension(&path.with_extension("")) {
+            Some(inner) => format!("{}.{}", inner, AGE_EXTENSION),
+            None => format!("csv.{}", AGE_EXTENSION),
+        },
+        Some(last) => last,
+        None => "csv".to_string(),
+    }
+}
+
+/// Check if a file name looks like a monthly file generated by `merge`, e.g. `bank-csv-transactions-EUR-2024-01.csv`,
+/// or a weekly or quarterly one, e.g. `bank-csv-transactions-EUR-2024-W05.csv` or `bank-csv-transactions-EUR-2024-Q1.csv`
+fn is_monthly_file_name(file_name: &str) -> bool {
+    let Some(stem) = file_name
+        .strip_prefix("bank-csv-transactions-")
+        .and_then(|rest| rest.strip_suffix(".csv"))
+    else {
+        return false;
+    };
+    let is_number = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+    match stem.rsplitn(3, '-').collect::<Vec<_>>()[..] {
+        [period, year, currency] => {
+            !currency.is_empty()
+                && year.len() == 4
+                && is_number(year)
+                && match (period.strip_prefix('W'), period.strip_prefix('Q')) {
+                    (Some(week), _) => week.len() == 2 && is_number(week),
+                    (_, Some(quarter)) => matches!(quarter, "1" | "2" | "3" | "4"),
+                    _ => period.len() == 2 && is_number(period),
+                }
+        }
+        _ => false,
+    }
+}
+
+/// The monthly files generated by `merge` in a directory, sorted by name; it's an error if there are none
+fn monthly_files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut monthly_paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(is_monthly_file_name)
+        })
+        .collect();
+    monthly_paths.sort();
+    if monthly_paths.is_empty() {
+        return Err(format!("No monthly files found in {}", dir.display()).into());
+    }
+    Ok(monthly_paths)
+}
+
+fn upgrade_command(args: UpgradeArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let dir = resolve_output_dir(args.dir.or_else(|| config.output_dir.clone()))?;
+    let _lock = lock_output_dir(&dir).map_err(output_error)?;
+    let version = schema_version(&dir)?;
+    if version >= SCHEMA_VERSION {
+        eprintln!(
+            "The monthly files in {} already have the current layout (version {})",
+            dir.display(),
+            version
+        );
+        return Ok(());
+    }
+    let files: Vec<(PathBuf, Vec<CsvOutputRow>)> = monthly_files(&dir)?
+        .into_iter()
+        .map(|path| read_output_rows(&path).map(|rows| (path, rows)))
+        .collect::<Result<_, BankCsvError>>()?;
+    // All files get the optional columns that have values in any of them
+    let extra_columns: Vec<ExtraColumn> = ExtraColumn::ALL
+        .into_iter()
+        .filter(|column| {
+            files
+                .iter()
+                .flat_map(|(_, rows)| rows)
+                .any(|row| row.extra.contains_key(column))
+        })
+        .collect();
+    for (path, rows) in &files {
+        // Each file keeps its language, unless another one is given
+        let locale = args
+            .locale
+            .or(output_file_locale(path)?)
+            .or(config.locale)
+            .unwrap_or_default();
+        let output_options = OutputOptions {
+            force: true,
+            append: false,
+            locale,
+            extra_columns: &extra_columns,
+            preview: None,
+            encrypt_to: &[],
+            original_order: false,
+            dedupe: false,
+        };
+        let (balance_rows, transactions): (Vec<&CsvOutputRow>, Vec<&CsvOutputRow>) = rows
+            .iter()
+            .partition(|row| row.transaction_type == CLOSING_BALANCE_TYPE);
+        write_output_file(path, &transactions, &balance_rows, &output_options)
+            .map_err(output_error)?;
+    }
+    write_schema_version(&dir).map_err(output_error)?;
+    eprintln!(
+        "Upgraded {} monthly file(s) from version {} to {}",
+        files.len(),
+        version,
+        SCHEMA_VERSION
+    );
+    Ok(())
+}
+
+fn duplicates_command(args: DuplicatesArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let dir = resolve_output_dir(args.dir.or_else(|| config.output_dir.clone()))?;
+    // Only removing duplicates writes to the monthly files
+    let _lock = match args.remove {
+        true => Some(lock_output_dir(&dir).map_err(output_error)?),
+        false => None,
+    };
+    let tolerance = args
+        .tolerance
+        .or(config.amount_tolerance)
+        .unwrap_or_default();
+    // Closing balance rows are read too, so they are written back when duplicates are removed
+    let files: Vec<(PathBuf, Vec<CsvOutputRow>)> = monthly_files(&dir)?
+        .into_iter()
+        .map(|path| read_output_rows(&path).map(|rows| (path, rows)))
+        .collect::<Result<_, BankCsvError>>()?;
+
+    // Files are sorted by month, so the first occurrence is the one in the earliest month
+    let mut first_occurrences: HashMap<u64, Vec<(usize, usize)>> = HashMap::new();
+    let mut duplicates: Vec<Vec<usize>> = vec![Vec::new(); files.len()];
+    for (file_index, (path, rows)) in files.iter().enumerate() {
+        for (row_index, row) in rows.iter().enumerate() {
+            if row.transaction_type == CLOSING_BALANCE_TYPE {
+                continue;
+            }
+            let occurrences = first_occurrences
+                .entry(row.hash_without_date_and_amount())
+                .or_default();
+            let original = occurrences.iter().find(|(other_file, other_row)| {
+                let other = &files[*other_file].1[*other_row];
+                *other_file != file_index
+                    && (other.date - row.date).num_days().abs() <= DUPLICATE_MAX_DAYS
+                    && tolerance.matches(&other.amount, &row.amount)
+            });
+            match original {
+                Some((other_file, _)) => {
+                    println!(
+                        "{}\n  in {}\n  and {}",
+                        colored_transaction(row),
+                        files[*other_file].0.display(),
+                        path.display()
+                    );
+                    duplicates[file_index].push(row_index);
+                }
+                None => occurrences.push((file_index, row_index)),
+            }
+        }
+    }
+
+    let total: usize = duplicates.iter().map(Vec::len).sum();
+    eprintln!("\nFound {} duplicate transaction(s)", total);
+    if !args.remove || total == 0 {
+        return Ok(());
+    }
+    for ((path, rows), duplicate_rows) in files.iter().zip(duplicates) {
+        if duplicate_rows.is_empty() {
+            continue;
+        }
+        let (balance_rows, remaining): (Vec<&CsvOutputRow>, Vec<&CsvOutputRow>) = rows
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !duplicate_rows.contains(index))
+            .map(|(_, row)| row)
+            .partition(|row| row.transaction_type == CLOSING_BALANCE_TYPE);
+        // The header stays in the language of the file, unless another one is given
+        let locale = args
+            .locale
+            .or(output_file_locale(path)?)
+            .or(config.locale)
+            .unwrap_or_default();
+        let extra_columns: Vec<ExtraColumn> = ExtraColumn::ALL
+            .into_iter()
+            .filter(|column| rows.iter().any(|row| row.extra.contains_key(column)))
+            .collect();
+        let output_options = OutputOptions {
+            force: true,
+            append: false,
+            locale,
+            extra_columns: &extra_columns,
+            preview: None,
+            encrypt_to: &[],
+            original_order: false,
+            dedupe: false,
+        };
+        write_output_file(path, &remaining, &balance_rows, &output_options)
+            .map_err(output_error)?;
+    }
+    Ok(())
+}
+
+fn report_command(args: ReportArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let dir = resolve_output_dir(args.dir.or_else(|| config.output_dir.clone()))?;
+
+    // Count and sum transactions per currency and month
+    let mut totals: BTreeMap<String, BTreeMap<(i32, u32), Totals>> = BTreeMap::new();
+    for path in monthly_files(&dir)? {
+        for transaction in read_output_file(&path)? {
+            let month = (transaction.date.year(), transaction.date.month());
+            totals
+                .entry(transaction.currency.clone())
+                .or_default()
+                .entry(month)
+                .or_default()
+                .add(&transaction);
+        }
+    }
+
+    let amount = |value: f64| format!("{:.2}", value).replace('.', ",");
+    for (currency, month_totals) in &totals {
+        println!("{}", currency.bold());
+        let running_totals = year_to_date(month_totals);
+        for ((year, month), month_total) in month_totals {
+            let running = running_totals[&(*year, *month)];
+            println!(
+                "  {:04}-{:02}: {} transaction(s), in {} / out {}, year to date: in {} / out {}",
+                year,
+                month,
+                month_total.rows,
+                amount(month_total.income),
+                amount(month_total.expenses),
+                amount(running.income),
+                amount(running.expenses)
+            );
+        }
+        let month_counts = month_totals
+            .iter()
+            .map(|(month, month_total)| (*month, month_total.rows))
+            .collect();
+        for warning in check_months(&month_counts) {
+            eprintln!("{}", format!("  {}", warning).yellow());
+        }
+    }
+    Ok(())
+}
+
+fn detect_command(args: DetectArgs) -> Result<(), Box<dyn Error>> {
+    let sources = SourceRegistry::default();
+    for path in args.csv_file_paths {
+        match detect_source(&path, &sources) {
+            Ok(info) => println!("{}: {}", path.display(), info),
+            Err(err) => {
+                // Some errors already start with the file
+                let message = err.to_string();
+                let prefix = format!("{}: ", path.display());
+                let message = message.strip_prefix(&prefix).unwrap_or(&message);
+                eprintln!("{}", format!("{}: {}", path.display(), message).yellow())
+            }
+        }
+    }
+    Ok(())
+}
+
+fn rules_test_command(args: RulesTestArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let rules = RuleSet::from_config(config)?;
+    let options = MergeOptions {
+        currency: args
+            .currency
+            .or_else(|| config.currency.clone())
+            .unwrap_or_else(|| "EUR".to_string()),
+        memo_templates: config.memo_templates.clone(),
+        source_filters: config.sources.clone(),
+        ..Default::default()
+    };
+    let transactions = match read_file(&args.csv_file_path, &options, &mut |_| Ok(None))? {
+        FileContent::Transactions { transactions, .. }
+        | FileContent::Custom { transactions, .. }
+        | FileContent::Output(transactions) => transactions,
+        FileContent::Skipped(reason) => {
+            return Err(format!("{}: {}", args.csv_file_path.display(), reason).into())
+        }
+        FileContent::UnknownFormat(err) => return Err(err.into()),
+    };
+    let mut unmatched = 0;
+    for transaction in &transactions {
+        let matches = rules.matches(transaction);
+        let description = if matches.is_empty() {
+            unmatched += 1;
+            "no match".yellow().to_string()
+        } else {
+            matches
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; ")
+        };
+        println!(
+            "{} {:>10} {}: {}",
+            transaction.date,
+            transaction.amount,
+            transaction.payee.bold(),
+            description
+        );
+    }
+    eprintln!(
+        "\n{} of {} transaction(s) matched no rule",
+        unmatched,
+        transactions.len()
+    );
+    Ok(())
+}
+
+fn consolidate_command(args: ConsolidateArgs, config: &Config) -> Result<(), Box<dyn Error>> {
+    let input_dir = resolve_output_dir(args.input_dir.or_else(|| config.output_dir.clone()))?;
+    let output_dir = match args.output_dir {
+        Some(output_dir) => resolve_output_dir(Some(output_dir))?,
+        None => input_dir.clone(),
+    };
+    let _lock = lock_output_dir(&output_dir).map_err(output_error)?;
+    let locale = args.locale.or(config.locale).unwrap_or_default();
+
+    let monthly_paths = monthly_files(&input_dir)?;
+
+    // Group transactions by currency and period
+    let mut groups: HashMap<(String, Option<i32>), TransactionSet<CsvOutputRow>> = HashMap::new();
+    for path in monthly_paths {
+        eprintln!("Reading monthly file {}", path.display());
+        for transaction in read_output_file(&path)? {
+            let period = match args.period {
+                Period::Year => Some(transaction.date.year()),
+                Period::All => None,
+            };
+            groups
+                .entry((transaction.currency.clone(), period))
+                .or_insert_with(|| TransactionSet::new(args.dedupe))
+                .push(transaction);
+        }
+    }
+
+    let mut sorted_keys = groups.keys().collect::<Vec<_>>();
+    sorted_keys.sort();
+    for key in sorted_keys {
+        let (currency, year) = key;
+        let transactions: Vec<&CsvOutputRow> = groups[key].iter().collect();
+        // Keep the optional columns that have values in any of the transactions
+        let extra_columns: Vec<ExtraColumn> = ExtraColumn::ALL
+            .into_iter()
+            .filter(|column| {
+                transactions
+                    .iter()
+                    .any(|trn| trn.extra.contains_key(column))
+            })
+            .collect();
+        let file_name = match year {
+            Some(year) => format!("bank-csv-transactions-{}-{:04}.csv", currency, year),
+            None => format!("bank-csv-transactions-{}-all.csv", currency),
+        };
+        let output_options = OutputOptions {
+            force: args.force,
+            append: false,
+            locale,
+            extra_columns: &extra_columns,
+            preview: None,
+            encrypt_to: &[],
+            original_order: false,
+            dedupe: false,
+        };
+        write_output_file(
+            &output_dir.join(file_name),
+            &transactions,
+            &[],
+            &output_options,
+        )
+        .map_err(output_error)?;
+    }
+    Ok(())
+}
+
+/// The aliases file of the configuration, or the default one
+fn aliases_path(config: &Config) -> Option<PathBuf> {
+    config
+        .aliases_file
+        .as_ref()
+        .map(|path| PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).to_string()))
+        .or_else(AliasStore::default_path)
+}
+
+fn alias_command(command: AliasCommand, config: &Config) -> Result<(), Box<dyn Error>> {
+    let path = aliases_path(config).ok_or("the home directory of the aliases file is unknown")?;
+    let mut store = AliasStore::load(&path)?;
+    match command {
+        AliasCommand::Add(args) => {
+            match store.add(&args.payee, &args.name) {
+                Some(previous) => println!(
+                    "{} → {} (was {})",
+                    args.payee.trim(),
+                    args.name.trim(),
+                    previous
+                ),
+                None => println!("{} → {}", args.payee.trim(), args.name.trim()),
+            }
+            store.save(&path).map_err(output_error)?;
+        }
+        AliasCommand::Remove(args) => {
+            if store.remove(&args.payee).is_none() {
+                return Err(format!("{:?} has no alias in {}", args.payee, path.display()).into());
+            }
+            store.save(&path).map_err(output_error)?;
+        }
+        AliasCommand::List => {
+            for (payee, name) in &store.aliases {
+                println!("{} → {}", payee, name);
+            }
+        }
     }
     Ok(())
 }
@@ -0,0 +1,121 @@
+//! Manifest file listing the inputs of a merge, with settings per file
+use crate::cloud::is_cloud_url;
+use crate::CsvFormat;
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An input file and its settings
+#[derive(Clone, Debug, Default)]
+pub struct ManifestInput {
+    /// Path of the file (or cloud storage URL); relative paths are relative to the manifest
+    pub path: PathBuf,
+    /// Format of the file, instead of detecting it from the header
+    pub format: Option<CsvFormat>,
+    /// Label of the account, written to the Account column
+    pub account: Option<String>,
+    /// Currency to filter in this file, instead of the one of the merge
+    pub currency: Option<String>,
+    /// Only transactions on or after this date are read
+    pub from: Option<NaiveDate>,
+    /// Only transactions on or before this date are read
+    pub to: Option<NaiveDate>,
+}
+
+impl ManifestInput {
+    /// An input without settings, e.g. a path given on the command line
+    pub fn from_path(path: PathBuf) -> Self {
+        Self {
+            path,
+            ..Self::default()
+        }
+    }
+
+    /// Check if a date is in the date range of the input
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::manifest::ManifestInput;
+    /// use chrono::NaiveDate;
+    /// let input = ManifestInput {
+    ///     from: NaiveDate::from_ymd_opt(2024, 1, 1),
+    ///     ..ManifestInput::from_path("dkb.csv".into())
+    /// };
+    /// assert!(input.includes(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()));
+    /// assert!(!input.includes(NaiveDate::from_ymd_opt(2023, 12, 31).unwrap()));
+    /// ```
+    pub fn includes(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+/// An input as it is written in the manifest
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawInput {
+    path: PathBuf,
+    format: Option<String>,
+    account: Option<String>,
+    currency: Option<String>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    inputs: Vec<RawInput>,
+}
+
+/// Load the inputs of a TOML manifest, with one `[[inputs]]` table per file
+///
+/// # Arguments
+///
+/// * `file_path`: path to the manifest
+///
+/// returns: Result<Vec<ManifestInput>, Error>; an error if the manifest can't be parsed
+/// or has an unknown format
+pub fn load_manifest(file_path: &Path) -> io::Result<Vec<ManifestInput>> {
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", file_path.display(), message),
+        )
+    };
+    let content = fs::read_to_string(file_path)?;
+    let manifest: RawManifest = toml::from_str(&content).map_err(|err| invalid(err.to_string()))?;
+    let base_dir = file_path.parent().unwrap_or(Path::new(""));
+    manifest
+        .inputs
+        .into_iter()
+        .map(|input| {
+            let format = input
+                .format
+                .map(|name| CsvFormat::from_str(&name, true))
+                .transpose()
+                .map_err(invalid)?;
+            let path_text = input.path.to_string_lossy();
+            // Cloud URLs and paths starting with ~ are kept as they are
+            let path = if input.path.is_relative()
+                && !is_cloud_url(&path_text)
+                && !path_text.starts_with('~')
+            {
+                base_dir.join(&input.path)
+            } else {
+                input.path
+            };
+            Ok(ManifestInput {
+                path,
+                format,
+                account: input.account,
+                currency: input.currency,
+                from: input.from,
+                to: input.to,
+            })
+        })
+        .collect()
+}
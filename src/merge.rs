@@ -0,0 +1,1775 @@
+//! Read bank CSV files into normalized transactions; this is the core of the `merge` command
+use crate::aliases::AliasStore;
+use crate::banks::paypal::PAYPAL_CURRENCY_CONVERSION;
+use crate::classify::Classifier;
+use crate::config::SourceFilter;
+use crate::error::BankCsvError;
+use crate::payees::PayeeFilter;
+use crate::script::RowScript;
+use crate::sources::{SourceRegistry, SourceRow, SourceSpec};
+use crate::split::SplitRule;
+use crate::tags::{tags_for, TagRule};
+use crate::{
+    clean_payee, conversion_memo, convert_time_zone, detect_decimal_separator, detect_encoding,
+    detect_separator, extract_invoice_number, extract_vat, file_hash, filter_data_frame,
+    is_output_file, mask_pii, net_amount, normalize_type, parse_date_str, parse_time_zone,
+    pseudonymize, read_output_file, remove_bom_and_crlf, render_template, source_key,
+    strip_currency_symbols, template_placeholders, BalanceImpact, CsvFormat, CsvOutputRow,
+    DateBasis, ExtraColumn, Locale, Source, StatementMetadata, TextNormalizer, TransactionSet,
+    NUM_SELECT_COLUMNS,
+};
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use chrono_tz::Tz;
+use encoding_rs::{Encoding, UTF_8};
+use polars::export::arrow::temporal_conversions::EPOCH_DAYS_FROM_CE;
+use polars::frame::row::Row;
+use polars::prelude::*;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// Options to read and normalize the transactions of bank CSV files
+#[derive(Clone, Debug)]
+pub struct MergeOptions {
+    /// Currency to filter (EUR, USD, ...), in any case
+    pub currency: String,
+    /// Which date to use when a source has both a booking and a value date
+    pub date_basis: DateBasis,
+    /// Format to use when it can't be detected from the header
+    pub assume: Option<CsvFormat>,
+    /// Format of the file, instead of detecting it from the header
+    pub format: Option<CsvFormat>,
+    /// Read DKB Depot files and fill the ISIN, quantity and fee columns
+    pub securities: bool,
+    /// Extract the VAT rate and amount from the memos
+    pub vat: bool,
+    /// Also fill the net and gross amounts of every transaction (EÜR profile), with `vat`
+    pub net_amounts: bool,
+    /// Patterns to extract invoice numbers from the memos; no extraction if empty
+    pub invoice_patterns: Vec<Regex>,
+    /// Templates for the memo column per source, by [`crate::source_key`] (e.g. `paypal` or `ing_nl`)
+    pub memo_templates: HashMap<String, String>,
+    /// Types and payees to drop per source, by [`crate::source_key`] (e.g. `paypal` or `ing_nl`)
+    pub source_filters: HashMap<String, SourceFilter>,
+    /// Rules to rewrite payees (regular expression and replacement); no cleanup if empty
+    pub payee_rules: Vec<(Regex, String)>,
+    /// Mask IBANs, card numbers and mandate references in the memos
+    pub mask_pii: bool,
+    /// Replace payees with pseudonyms derived from this key
+    pub pseudonym_key: Option<String>,
+    /// Rules to split transactions into several rows; the first matching rule is applied
+    pub split_rules: Vec<SplitRule>,
+    /// Fill the normalized type column, with these mappings besides the built-in ones
+    pub type_mappings: Option<BTreeMap<String, String>>,
+    /// Fill the tags column with the tags of these rules
+    pub tag_rules: Option<Vec<TagRule>>,
+    /// Pair the legs of PayPal currency conversions into one transaction instead of dropping them
+    pub paypal_conversions: bool,
+    /// Add the euro amount, original amount and exchange rate of card payments in foreign currencies,
+    /// for sources that have them (e.g. DKB, see [`SourceSpec::conversion`])
+    pub dkb_conversions: bool,
+    /// PayPal rows to read by their balance impact; only debits if empty
+    pub paypal_balance_impact: Vec<BalanceImpact>,
+    /// Fill the time column, for sources that provide the time of the transactions
+    pub with_time: bool,
+    /// Convert dates and times to this time zone; sources without a time zone are kept as they are
+    pub time_zone: Option<Tz>,
+    /// Keep transactions with a zero amount (card verifications, declined payments); dropped by default
+    pub keep_zero_amounts: bool,
+    /// Keep N26 moves between the main account and Spaces (and Monzo pot transfers); dropped by default
+    pub keep_internal_transfers: bool,
+    /// Keep only the transactions of these payees, or drop them; compared after cleaning the payees
+    pub payee_filter: Option<PayeeFilter>,
+    /// Also read the transactions in other currencies, returned as skipped rows
+    pub read_other_currencies: bool,
+    /// Keep only one of identical transactions, e.g. when input files overlap; all kept by default
+    pub dedupe: bool,
+    /// Earliest and latest plausible dates (inclusive); rows outside of them are dropped. No limits by default
+    pub plausible_dates: Option<(NaiveDate, NaiveDate)>,
+    /// Sources the format of the files is detected with: the built-in formats by default (see [`crate::banks`]),
+    /// followed by the format descriptors (see [`crate::formats`]) and the ones of programs using this crate
+    pub sources: SourceRegistry,
+    /// Script applied to each transaction after all other rules, see [`crate::script`]
+    pub script: Option<RowScript>,
+    /// Classifier to suggest a category for each transaction, with its confidence
+    pub classifier: Option<Classifier>,
+    /// Read the rows of all currencies, types, payees and dates: nothing is filtered, only normalized
+    pub no_filter: bool,
+    /// Preferred names of payees; they replace the payee of the export or the one cleaned by the payee rules
+    pub aliases: AliasStore,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            currency: "EUR".to_string(),
+            date_basis: DateBasis::default(),
+            assume: None,
+            format: None,
+            securities: false,
+            vat: false,
+            net_amounts: false,
+            invoice_patterns: Vec::new(),
+            memo_templates: HashMap::new(),
+            source_filters: HashMap::new(),
+            payee_rules: Vec::new(),
+            mask_pii: false,
+            pseudonym_key: None,
+            split_rules: Vec::new(),
+            type_mappings: None,
+            tag_rules: None,
+            paypal_conversions: false,
+            dkb_conversions: false,
+            paypal_balance_impact: vec![BalanceImpact::Debit],
+            with_time: false,
+            time_zone: None,
+            keep_zero_amounts: false,
+            keep_internal_transfers: false,
+            payee_filter: None,
+            read_other_currencies: false,
+            dedupe: false,
+            plausible_dates: None,
+            sources: SourceRegistry::default(),
+            script: None,
+            classifier: None,
+            no_filter: false,
+            aliases: AliasStore::default(),
+        }
+    }
+}
+
+impl MergeOptions {
+    /// The same options with the filters turned off, except the currency (read one at a time)
+    fn without_filters(&self) -> Self {
+        Self {
+            source_filters: HashMap::new(),
+            paypal_balance_impact: vec![
+                BalanceImpact::Debit,
+                BalanceImpact::Credit,
+                BalanceImpact::Memo,
+            ],
+            keep_zero_amounts: true,
+            keep_internal_transfers: true,
+            payee_filter: None,
+            read_other_currencies: false,
+            plausible_dates: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// What was read from one input file
+pub enum FileContent {
+    /// Transactions of a bank export, filtered by currency and normalized
+    Transactions {
+        /// Format of the file
+        format: CsvFormat,
+        /// Transactions in the requested currency
+        transactions: Vec<CsvOutputRow>,
+        /// Latest transaction date in the file, in any currency that was read
+        last_date: Option<NaiveDate>,
+        /// Why there are no transactions, if the file has rows but none in the requested currency,
+        /// or how many PayPal currency conversion legs were left out
+        warning: Option<String>,
+        /// Account data found above the header of the file
+        metadata: Option<StatementMetadata>,
+        /// Rows that were dropped, and why; other currencies only with `read_other_currencies`
+        skipped_rows: Vec<(SkipReason, CsvOutputRow)>,
+    },
+    /// Transactions of a bank export read with a registered source, e.g. a format descriptor,
+    /// see [`crate::sources`]
+    Custom {
+        /// Name of the format
+        name: String,
+        /// Source of the transactions
+        source: String,
+        /// Transactions in the requested currency
+        transactions: Vec<CsvOutputRow>,
+        /// Latest transaction date in the file
+        last_date: Option<NaiveDate>,
+        /// Rows that were dropped, and why; other currencies only with `read_other_currencies`
+        skipped_rows: Vec<(SkipReason, CsvOutputRow)>,
+    },
+    /// A file generated by a previous run; already normalized, only filtered by currency
+    Output(Vec<CsvOutputRow>),
+    /// The file was not read, for the given reason (without the file path)
+    Skipped(String),
+    /// The format of the file is not known, see [`BankCsvError::UnknownFormat`]
+    UnknownFormat(BankCsvError),
+}
+
+/// Why a row of a bank export was dropped
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SkipReason {
+    /// Informational rows without money moved, like card verifications and declined payments
+    ZeroAmount,
+    /// Money moved between accounts of the same person, like N26 Spaces
+    InternalTransfer,
+    /// The payee is not in the list of payees to keep, or it's in the list to drop
+    Payee,
+    /// The row is in another currency than the requested one
+    Currency,
+    /// The date is outside the date range given for the file in the manifest
+    DateRange,
+    /// The date is too old or too far in the future to be real, e.g. 1970-01-01 from a bank bug
+    ImplausibleDate,
+    /// The script dropped the row
+    Script,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::ZeroAmount => write!(f, "zero amount"),
+            SkipReason::InternalTransfer => write!(f, "internal transfer"),
+            SkipReason::Payee => write!(f, "payee filter"),
+            SkipReason::Currency => write!(f, "other currency"),
+            SkipReason::DateRange => write!(f, "date range"),
+            SkipReason::ImplausibleDate => write!(f, "implausible date"),
+            SkipReason::Script => write!(f, "script"),
+        }
+    }
+}
+
+/// What was detected about a bank CSV file before reading its transactions
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceInfo {
+    /// Separator of the columns
+    pub separator: u8,
+    /// Encoding of the original file (DKB files are Latin, the others UTF-8)
+    pub encoding: &'static Encoding,
+    /// Bank or service of the file, if known from its format or from the lines above the header
+    pub source: Option<Source>,
+    /// Layout of the header, if exactly one format matches it
+    pub format: Option<CsvFormat>,
+    /// Formats whose columns are all present in the header, when the format is not detected
+    pub candidates: Vec<CsvFormat>,
+}
+
+impl fmt::Display for SourceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.format, self.source) {
+            (Some(format), _) => write!(f, "{}", format)?,
+            (None, Some(source)) => write!(f, "unknown {} format", source)?,
+            (None, None) => write!(f, "unknown format")?,
+        }
+        if self.format.is_none() && !self.candidates.is_empty() {
+            write!(f, " (candidates: {:?})", self.candidates)?;
+        }
+        let separator = match self.separator {
+            b'\t' => "tab".to_string(),
+            other => format!("'{}'", other as char),
+        };
+        write!(
+            f,
+            ", separator {}, encoding {}",
+            separator,
+            self.encoding.name()
+        )
+    }
+}
+
+/// Detect the separator, encoding, source and format of a bank CSV file,
+/// the same way they are detected when merging it
+///
+/// # Arguments
+///
+/// * `file_path`: path to the CSV file
+/// * `sources`: the sources to detect the format with, see [`MergeOptions::sources`]
+///
+/// returns: Result<SourceInfo, BankCsvError>; an error if the file can't be read or has no separator
+pub fn detect_source(
+    file_path: &Path,
+    sources: &SourceRegistry,
+) -> Result<SourceInfo, BankCsvError> {
+    let (separator, detected_source) = detect_separator(file_path)?;
+    let (info, _, _) = read_detected(file_path, separator, detected_source, sources)?;
+    Ok(info)
+}
+
+/// Read a CSV file into a data frame and detect its format with the registered sources,
+/// once the separator is known
+fn read_detected(
+    file_path: &Path,
+    separator: u8,
+    detected_source: Option<Source>,
+    sources: &SourceRegistry,
+) -> Result<(SourceInfo, DataFrame, Option<StatementMetadata>), BankCsvError> {
+    let (df, metadata, encoding) =
+        read_data_frame(file_path, separator, detected_source, None, sources)?;
+    let column_names = df.get_column_names();
+    let (format, candidates) = match sources.detect(&column_names) {
+        Some(spec) => (spec.format(), Vec::new()),
+        None => (None, sources.candidates(&column_names)),
+    };
+    let info = SourceInfo {
+        separator,
+        encoding,
+        source: format.map(|format| format.source()).or(detected_source),
+        format,
+        candidates,
+    };
+    Ok((info, df, metadata))
+}
+
+/// Read a CSV file into a data frame, skipping the lines some sources add above the header;
+/// the account data in these lines and the encoding of the file are returned as well
+///
+/// The encoding is the given one (from a format descriptor), the one of the source,
+/// or the detected one, in this order. Localized headers are translated by the registered sources.
+fn read_data_frame(
+    file_path: &Path,
+    separator: u8,
+    detected_source: Option<Source>,
+    encoding: Option<&'static Encoding>,
+    sources: &SourceRegistry,
+) -> Result<(DataFrame, Option<StatementMetadata>, &'static Encoding), BankCsvError> {
+    let temp_file = NamedTempFile::new()?;
+    let mut metadata = None;
+    let content = std::fs::read(file_path)?;
+    let detected_spec = detected_source.and_then(|source| source.spec());
+    let encoding = encoding
+        .or_else(|| detected_spec.and_then(|spec| spec.encoding()))
+        .unwrap_or_else(|| detect_encoding(&content));
+    let modified_path: &Path = match detected_spec {
+        Some(spec) if spec.edits_file() => {
+            metadata = spec.edit_file(file_path, &temp_file, encoding)?;
+            temp_file.path()
+        }
+        // polars only reads UTF-8
+        _ if encoding != UTF_8 => {
+            let (decoded, _, had_errors) = encoding.decode(&content);
+            if had_errors {
+                return Err(encoding_error(file_path, encoding));
+            }
+            let cleaned = remove_bom_and_crlf(decoded.as_bytes());
+            std::fs::write(
+                temp_file.path(),
+                cleaned.as_deref().unwrap_or(decoded.as_bytes()),
+            )?;
+            temp_file.path()
+        }
+        _ if std::str::from_utf8(&content).is_err() => {
+            return Err(encoding_error(file_path, encoding));
+        }
+        _ => match remove_bom_and_crlf(&content) {
+            Some(cleaned) => {
+                std::fs::write(temp_file.path(), cleaned)?;
+                temp_file.path()
+            }
+            None => file_path,
+        },
+    };
+    let mut df = CsvReader::from_path(modified_path)?
+        .has_header(true)
+        .with_try_parse_dates(true)
+        .with_separator(separator)
+        .truncate_ragged_lines(true)
+        .finish()?;
+    if let Some(translated_names) = sources.translate_header(&df.get_column_names()) {
+        df.set_column_names(&translated_names)?;
+    }
+    Ok((df, metadata, encoding))
+}
+
+/// A file whose content is not valid in its encoding
+fn encoding_error(file_path: &Path, encoding: &'static Encoding) -> BankCsvError {
+    BankCsvError::Encoding {
+        file_path: file_path.to_path_buf(),
+        encoding: encoding.name(),
+    }
+}
+
+/// The first line of a file containing a text, starting at 1
+fn find_line(file_path: &Path, text: &str) -> Option<usize> {
+    let content = std::fs::read(file_path).ok()?;
+    String::from_utf8_lossy(&content)
+        .lines()
+        .position(|line| line.contains(text))
+        .map(|index| index + 1)
+}
+
+/// Parse the date column of a row, with the date formats of the source
+fn parse_date(
+    value: &AnyValue,
+    format: CsvFormat,
+    file_path: &Path,
+) -> Result<NaiveDate, BankCsvError> {
+    if let Some(date) = polars_date(value) {
+        return Ok(date);
+    }
+    // Dates that polars didn't recognize, e.g. in the German format
+    let text = TextNormalizer::default().value(value);
+    let date_formats = format.spec().date_formats();
+    parse_date_str(&text, &date_formats).ok_or_else(|| BankCsvError::DateParse {
+        file_path: file_path.to_path_buf(),
+        line: find_line(file_path, &text),
+        value: text,
+        format: format.to_string(),
+        date_formats: date_formats.iter().map(|f| f.to_string()).collect(),
+    })
+}
+
+/// The date of a value that polars already parsed as a date or a timestamp
+fn polars_date(value: &AnyValue) -> Option<NaiveDate> {
+    if let AnyValue::Datetime(timestamp, time_unit, _) = value {
+        // Timestamps (e.g. Kraken) are parsed as datetimes; only the date is kept
+        let seconds = match time_unit {
+            TimeUnit::Nanoseconds => timestamp / 1_000_000_000,
+            TimeUnit::Microseconds => timestamp / 1_000_000,
+            TimeUnit::Milliseconds => timestamp / 1_000,
+        };
+        return DateTime::from_timestamp(seconds, 0).map(|datetime| datetime.date_naive());
+    }
+    // Only actual dates: numbers like "20240628" (ING NL) are parsed with the formats of the source
+    if let AnyValue::Date(gregorian_days) = value {
+        return NaiveDate::from_num_days_from_ce_opt(gregorian_days + EPOCH_DAYS_FROM_CE);
+    }
+    None
+}
+
+/// The time of a row and its time zone: from the date column if it's a timestamp (in UTC),
+/// or from the time and time zone columns of the format, if any
+fn parse_time(date_value: &AnyValue, time_values: &[AnyValue]) -> Option<(NaiveTime, Option<Tz>)> {
+    if let AnyValue::Datetime(timestamp, time_unit, _) = date_value {
+        let nanoseconds = match time_unit {
+            TimeUnit::Nanoseconds => *timestamp,
+            TimeUnit::Microseconds => timestamp * 1_000,
+            TimeUnit::Milliseconds => timestamp * 1_000_000,
+        };
+        return Some((
+            DateTime::from_timestamp_nanos(nanoseconds).time(),
+            Some(chrono_tz::UTC),
+        ));
+    }
+    let [time, zone] = time_values else {
+        return None;
+    };
+    let normalizer = TextNormalizer::default();
+    let time = NaiveTime::parse_from_str(&normalizer.value(time), "%H:%M:%S").ok()?;
+    Some((time, parse_time_zone(&normalizer.value(zone))))
+}
+
+/// Read one bank CSV file (or a file generated by a previous run), filtered by currency and normalized
+///
+/// # Arguments
+///
+/// * `file_path`: path to the CSV file
+/// * `options`: currency, optional columns and transformations
+/// * `choose_format`: called with the candidate formats when the format can't be detected
+///   and `options.assume` is not set; returning `None` skips the file
+///
+/// returns: Result<FileContent, BankCsvError>
+pub fn read_file(
+    file_path: &Path,
+    options: &MergeOptions,
+    choose_format: &mut dyn FnMut(&[CsvFormat]) -> io::Result<Option<CsvFormat>>,
+) -> Result<FileContent, BankCsvError> {
+    let upper_currency = options.currency.to_uppercase();
+    let filterless_options;
+    let options = if options.no_filter {
+        filterless_options = options.without_filters();
+        &filterless_options
+    } else {
+        options
+    };
+
+    // Files generated by a previous run are already normalized; only the currency is filtered
+    if is_output_file(file_path).unwrap_or(false) {
+        return Ok(FileContent::Output(
+            read_output_file(file_path)?
+                .into_iter()
+                .filter(|transaction| options.no_filter || transaction.currency == upper_currency)
+                .filter(|transaction| {
+                    options
+                        .payee_filter
+                        .as_ref()
+                        .is_none_or(|filter| filter.keeps(&transaction.payee))
+                })
+                .collect(),
+        ));
+    }
+
+    // Registered sources with lines above the header are recognized before the separator is detected
+    if options.format.is_none() {
+        if let Some(content) = read_after_preamble(file_path, options)? {
+            return Ok(content);
+        }
+    }
+
+    let (separator, detected_source) = match detect_separator(file_path) {
+        Ok(detected) => detected,
+        Err(err @ BankCsvError::UnknownFormat { .. }) => {
+            return Ok(FileContent::UnknownFormat(err));
+        }
+        Err(err) => return Err(err),
+    };
+    let (info, df_csv, metadata) =
+        read_detected(file_path, separator, detected_source, &options.sources)?;
+    if options.format.is_none() && info.format.is_none() {
+        // The header matches no built-in format, but it can match another registered source
+        if let Some(spec) = options.sources.detect(&df_csv.get_column_names()) {
+            // The header was read with the detected encoding; the rows need the declared one
+            if let Some(encoding) = spec
+                .encoding()
+                .filter(|encoding| *encoding != info.encoding)
+            {
+                let (df_declared, _, _) = read_data_frame(
+                    file_path,
+                    separator,
+                    detected_source,
+                    Some(encoding),
+                    &options.sources,
+                )?;
+                return read_registered_file(file_path, &df_declared, spec, options);
+            }
+            return read_registered_file(file_path, &df_csv, spec, options);
+        }
+    }
+
+    let detected_format = match (options.format, info.format) {
+        (Some(format), _) | (None, Some(format)) => Ok(format),
+        (None, None) => Err(info.candidates),
+    };
+    let format = match detected_format {
+        Ok(format) => format,
+        Err(candidates) => {
+            let chosen = match options.assume {
+                Some(format) => Some(format),
+                None => choose_format(&candidates)?,
+            };
+            match chosen {
+                Some(format) => format,
+                None => {
+                    return Ok(FileContent::UnknownFormat(BankCsvError::UnknownFormat {
+                        file_path: file_path.to_path_buf(),
+                        reason: format!(
+                            "unknown CSV format, skipping it (candidates: {:?})",
+                            candidates
+                        ),
+                        columns: df_csv
+                            .get_column_names()
+                            .iter()
+                            .map(|name| name.to_string())
+                            .collect(),
+                    }))
+                }
+            }
+        }
+    };
+    let spec = format.spec();
+    if spec.is_securities() && !options.securities {
+        return Ok(FileContent::Skipped(
+            "securities file, skipping it (use --securities to read it)".to_string(),
+        ));
+    }
+    if options.no_filter {
+        return read_all_currencies(file_path, &df_csv, format, metadata, options);
+    }
+    read_bank_file(file_path, &df_csv, format, metadata, options)
+}
+
+/// Read the rows of a bank export in the requested currency, once its format is known
+fn read_bank_file(
+    file_path: &Path,
+    df_csv: &DataFrame,
+    format: CsvFormat,
+    metadata: Option<StatementMetadata>,
+    options: &MergeOptions,
+) -> Result<FileContent, BankCsvError> {
+    let upper_currency = options.currency.to_uppercase();
+    let spec = format.spec();
+    let source = format.source();
+    // Columns of the original file used by the memo template of this source, if any
+    let memo_template = options.memo_templates.get(&source_key(&source.to_string()));
+    let column_names = df_csv.get_column_names();
+    let template_columns: Vec<String> = memo_template
+        .map(|template| {
+            template_placeholders(template)
+                .into_iter()
+                .filter(|placeholder| column_names.contains(placeholder))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    // The time and time zone columns of sources that have them are selected after the template ones
+    let time_columns: Vec<String> = spec
+        .time_columns()
+        .filter(|_| options.with_time)
+        .map(|(time, zone)| vec![time.to_string(), zone.to_string()])
+        .unwrap_or_default();
+    // N26 exports of several accounts into one file have different account names;
+    // the column is selected last, so each row is labeled with its account
+    let normalizer = TextNormalizer::default();
+    let account_columns: Vec<String> = match spec.account_column() {
+        Some(column) if column_names.contains(&column) => {
+            let accounts: HashSet<String> = df_csv
+                .column(column)?
+                .iter()
+                .map(|value| normalizer.value(&value))
+                .filter(|account| !account.is_empty())
+                .collect();
+            if accounts.len() > 1 {
+                vec![column.to_string()]
+            } else {
+                Vec::new()
+            }
+        }
+        _ => Vec::new(),
+    };
+    // The categories of the source are used as normalized types, instead of mapping the types
+    let category_columns: Vec<String> = spec
+        .category_column()
+        .filter(|column| options.type_mappings.is_some() && column_names.contains(column))
+        .map(|column| vec![column.to_string()])
+        .unwrap_or_default();
+    // Fees that are not included in the amount are written as separate expenses
+    let fee_columns: Vec<String> = spec
+        .fee_column()
+        .map(|column| vec![column.to_string()])
+        .unwrap_or_default();
+    let additional_columns = [
+        template_columns.clone(),
+        time_columns.clone(),
+        account_columns.clone(),
+        category_columns.clone(),
+        fee_columns.clone(),
+    ]
+    .concat();
+    let df_filtered = filter_data_frame(file_path, df_csv, format, options, &additional_columns)?;
+    let template_columns_start = df_filtered.width() - additional_columns.len();
+    let time_columns_start = template_columns_start + template_columns.len();
+    let account_column_index = time_columns_start + time_columns.len();
+    let category_column_index = account_column_index + account_columns.len();
+    let fee_column_index = category_column_index + category_columns.len();
+    // Sources differ in their decimal convention, and some even between languages
+    let raw_amounts: Vec<String> = df_filtered.get_columns()[2]
+        .iter()
+        .map(|value| TextNormalizer::default().value(&value))
+        .collect();
+    let decimal_separator = detect_decimal_separator(raw_amounts.iter().map(String::as_str));
+    let mut last_date: Option<NaiveDate> = None;
+    let mut transactions = Vec::new();
+    let mut skipped_rows = Vec::new();
+
+    let normalizer = TextNormalizer::default().with_decimal_separator(decimal_separator);
+    const DEFAULT_COLUMN_VALUE: AnyValue = AnyValue::String("");
+    let mut row = Row::new(vec![DEFAULT_COLUMN_VALUE; df_filtered.width()]);
+    for row_index in 0..df_filtered.height() {
+        // https://stackoverflow.com/questions/72440403/iterate-over-rows-polars-rust
+        df_filtered.get_row_amortized(row_index, &mut row)?;
+
+        let mut source_row = SourceRow {
+            currency: normalizer.value(&row.0[1]),
+            amount: normalizer.value(&row.0[2]),
+            transaction_type: normalizer.value(&row.0[3]),
+            payee: normalizer.value(&row.0[4]),
+            memo: normalizer.value(&row.0[5]),
+            extra: row.0[NUM_SELECT_COLUMNS..template_columns_start]
+                .iter()
+                .map(|value| normalizer.value(value))
+                .collect(),
+        };
+
+        // Both amounts of card payments in foreign currencies, before one of them is discarded
+        let mut conversion_columns = Vec::new();
+        if options.dkb_conversions {
+            if let Some((original_amount, original_currency, rate)) =
+                spec.conversion(&source_row.memo)
+            {
+                let sign = if source_row.amount.contains('-') {
+                    "-"
+                } else {
+                    ""
+                };
+                conversion_columns = vec![
+                    (
+                        ExtraColumn::AmountEur,
+                        normalizer.amount(&source_row.amount),
+                    ),
+                    (
+                        ExtraColumn::OriginalAmount,
+                        format!("{}{} {}", sign, original_amount, original_currency),
+                    ),
+                    (ExtraColumn::Rate, rate),
+                ];
+            }
+        }
+
+        if !spec.post_process(&mut source_row, &upper_currency)? {
+            continue;
+        }
+        // Rows with an empty currency are only selected for the default currency of the source
+        if source_row.currency.is_empty() {
+            source_row.currency = upper_currency.clone();
+        }
+        let amount = normalizer.amount(&source_row.amount);
+
+        let mut naive_date = parse_date(&row.0[0], format, file_path)?;
+        let mut time = None;
+        if options.with_time {
+            let time_values = &row.0[time_columns_start..time_columns_start + time_columns.len()];
+            if let Some((naive_time, zone)) = parse_time(&row.0[0], time_values) {
+                let converted = zone
+                    .zip(options.time_zone)
+                    .and_then(|(from, to)| convert_time_zone(naive_date, naive_time, from, to));
+                time = Some(match converted {
+                    Some(datetime) => {
+                        naive_date = datetime.date();
+                        datetime.time()
+                    }
+                    None => naive_time,
+                });
+            }
+        }
+        let mut transaction = CsvOutputRow::new(
+            naive_date,
+            source.to_string(),
+            source_row.currency,
+            amount,
+            source_row.transaction_type,
+            source_row.payee,
+            source_row.memo,
+        );
+        // Filtered rows keep the order of the file; callers merging several files set the file index
+        transaction.sequence = Some((0, row_index));
+        transaction.extra.extend(conversion_columns);
+        if !account_columns.is_empty() {
+            let account = normalizer.value(&row.0[account_column_index]);
+            if !account.is_empty() {
+                transaction.extra.insert(ExtraColumn::Account, account);
+            }
+        }
+        if let (false, Some(type_mappings)) = (category_columns.is_empty(), &options.type_mappings)
+        {
+            let category = normalizer.value(&row.0[category_column_index]);
+            if !category.is_empty() {
+                transaction.extra.insert(
+                    ExtraColumn::NormalizedType,
+                    normalize_category(&category, type_mappings),
+                );
+            }
+        }
+        if let Some(time) = time {
+            transaction
+                .extra
+                .insert(ExtraColumn::Time, time.format("%H:%M:%S").to_string());
+        }
+        if spec.memo_is_id() {
+            transaction
+                .extra
+                .insert(ExtraColumn::Id, transaction.memo.clone());
+        }
+        // Fees have the decimal convention of the amounts, maybe with a currency symbol
+        let fee_row = if fee_columns.is_empty() || spec.is_securities() {
+            None
+        } else {
+            let fee = normalizer.amount(&normalizer.value(&row.0[fee_column_index]));
+            fee_transaction(&transaction, &fee, options)
+        };
+        let skip_reason = skip_reason(&transaction, options)
+            .or_else(|| internal_transfer(spec, &mut transaction, options));
+        if let Some(reason) = skip_reason {
+            // The fee is skipped with its transaction
+            skipped_rows.extend(fee_row.map(|fee| (reason, fee)));
+            skipped_rows.push((reason, transaction));
+            continue;
+        }
+        if spec.is_securities() {
+            if spec.memo_is_isin() {
+                transaction
+                    .extra
+                    .insert(ExtraColumn::Isin, transaction.memo.clone());
+            }
+            transaction.extra.insert(
+                ExtraColumn::Quantity,
+                normalizer.value(&row.0[NUM_SELECT_COLUMNS]),
+            );
+            transaction.extra.insert(
+                ExtraColumn::Fee,
+                normalizer.value(&row.0[NUM_SELECT_COLUMNS + 1]),
+            );
+        }
+        let template_values = memo_template.map(|template| {
+            let values = template_columns
+                .iter()
+                .enumerate()
+                .map(|(index, column)| {
+                    (
+                        column.as_str(),
+                        normalizer.value(&row.0[template_columns_start + index]),
+                    )
+                })
+                .collect();
+            (template.as_str(), values)
+        });
+        let finished = finish_with_fee(
+            transaction,
+            fee_row,
+            options,
+            template_values,
+            &mut skipped_rows,
+        )?;
+        last_date = last_date.max(finished.iter().map(|row| row.date).max());
+        transactions.extend(finished);
+    }
+    let mut unpaired_legs = 0;
+    // Without filters, the legs of the conversions are read as rows
+    if options.paypal_conversions && !options.no_filter && source == Source::PayPal {
+        let (conversions, unpaired) =
+            paypal_conversions(df_csv, format, &upper_currency, file_path)?;
+        unpaired_legs = unpaired;
+        for transaction in conversions {
+            // Conversions go through the same rules, aliases, masking and script as the other rows
+            let finished = finish_transaction(transaction, options, None, &mut skipped_rows)?;
+            last_date = last_date.max(finished.iter().map(|row| row.date).max());
+            transactions.extend(finished);
+        }
+    }
+    // A typo in the currency would silently produce nothing
+    let warning = if transactions.is_empty() && skipped_rows.is_empty() && df_csv.height() > 0 {
+        let mut present: Vec<String> = count_currencies(df_csv, format)?.into_keys().collect();
+        present.sort();
+        Some(format!(
+            "0 of {} rows matched currency {}; currencies present: {}",
+            df_csv.height(),
+            upper_currency,
+            present.join(", ")
+        ))
+    } else if unpaired_legs > 0 {
+        Some(format!(
+            "{} PayPal currency conversion legs without a counterpart were left out",
+            unpaired_legs
+        ))
+    } else {
+        None
+    };
+    if options.read_other_currencies {
+        let mut other_currencies: Vec<String> = count_currencies(df_csv, format)?
+            .into_keys()
+            .filter(|currency| *currency != upper_currency)
+            .collect();
+        other_currencies.sort();
+        for currency in other_currencies {
+            let other_options = MergeOptions {
+                currency,
+                assume: Some(format),
+                read_other_currencies: false,
+                ..options.clone()
+            };
+            // The format is already known, so there's nothing to choose
+            let mut known_format = |_: &[CsvFormat]| Ok(Some(format));
+            if let FileContent::Transactions {
+                transactions: other_transactions,
+                skipped_rows: other_skipped_rows,
+                ..
+            } = read_file(file_path, &other_options, &mut known_format)?
+            {
+                skipped_rows.extend(
+                    other_transactions
+                        .into_iter()
+                        .map(|transaction| (SkipReason::Currency, transaction)),
+                );
+                skipped_rows.extend(other_skipped_rows);
+            }
+        }
+    }
+    Ok(FileContent::Transactions {
+        format,
+        transactions,
+        last_date,
+        warning,
+        metadata,
+        skipped_rows,
+    })
+}
+
+/// A category given by the source as a normalized type: mapped by the custom type mappings,
+/// or in lowercase with underscores, like the canonical types (e.g. `Grocery` becomes `grocery`)
+fn normalize_category(category: &str, type_mappings: &BTreeMap<String, String>) -> String {
+    type_mappings
+        .iter()
+        .find(|(original, _)| original.eq_ignore_ascii_case(category))
+        .map(|(_, canonical)| canonical.clone())
+        .unwrap_or_else(|| category.to_lowercase().replace(' ', "_"))
+}
+
+/// Read the rows of each currency of a bank export, for `no_filter`
+///
+/// Each currency is read like with `--currency`, so every row is read once, with the amount in its own currency.
+/// Only the currency is filtered (see [`SourceSpec::currency_filter`]), not the other rows the format drops.
+fn read_all_currencies(
+    file_path: &Path,
+    df_csv: &DataFrame,
+    format: CsvFormat,
+    metadata: Option<StatementMetadata>,
+    options: &MergeOptions,
+) -> Result<FileContent, BankCsvError> {
+    let mut currencies: Vec<String> = count_currencies(df_csv, format)?.into_keys().collect();
+    currencies.sort();
+    let mut all_transactions = Vec::new();
+    let mut all_skipped_rows = Vec::new();
+    let mut last_date = None;
+    for currency in currencies {
+        let currency_options = MergeOptions {
+            currency,
+            ..options.clone()
+        };
+        if let FileContent::Transactions {
+            transactions,
+            last_date: currency_last_date,
+            skipped_rows,
+            ..
+        } = read_bank_file(file_path, df_csv, format, None, &currency_options)?
+        {
+            all_transactions.extend(transactions);
+            all_skipped_rows.extend(skipped_rows);
+            last_date = last_date.max(currency_last_date);
+        }
+    }
+    Ok(FileContent::Transactions {
+        format,
+        transactions: all_transactions,
+        last_date,
+        warning: None,
+        metadata,
+        skipped_rows: all_skipped_rows,
+    })
+}
+
+/// Drop internal transfers of a source (see [`SourceSpec::is_internal_transfer`]),
+/// unless they are kept; they are normalized as transfers either way
+fn internal_transfer(
+    spec: &dyn SourceSpec,
+    transaction: &mut CsvOutputRow,
+    options: &MergeOptions,
+) -> Option<SkipReason> {
+    if !spec.is_internal_transfer(&transaction.transaction_type, &transaction.memo) {
+        return None;
+    }
+    if options.type_mappings.is_some() {
+        transaction
+            .extra
+            .insert(ExtraColumn::NormalizedType, "transfer".to_string());
+    }
+    (!options.keep_internal_transfers).then_some(SkipReason::InternalTransfer)
+}
+
+/// Why a row is dropped before it is normalized, whatever its source
+fn skip_reason(transaction: &CsvOutputRow, options: &MergeOptions) -> Option<SkipReason> {
+    if options
+        .plausible_dates
+        .is_some_and(|(earliest, latest)| transaction.date < earliest || transaction.date > latest)
+    {
+        Some(SkipReason::ImplausibleDate)
+    } else if !options.keep_zero_amounts
+        && transaction
+            .amount
+            .replace(',', ".")
+            .parse::<f64>()
+            .is_ok_and(|value| value == 0.0)
+    {
+        Some(SkipReason::ZeroAmount)
+    } else {
+        None
+    }
+}
+
+/// Apply the options that don't depend on the source to a transaction:
+/// VAT, invoice numbers, the memo template, type mappings, payee rules and filter, tags,
+/// suggested categories, masking, pseudonyms, split rules and the script
+///
+/// # Arguments
+///
+/// * `transaction`: the transaction, already read from its file
+/// * `options`: the options of the merge
+/// * `memo_template`: the memo template of the source, with the values of the columns of the
+///   original file that it uses
+/// * `skipped_rows`: where the row is added if the payee filter or the script drops it
+///
+/// returns: Result<Vec<CsvOutputRow>, Error> with one row or the rows it was split into;
+/// empty if it was dropped. An error if the script fails
+fn finish_transaction(
+    mut transaction: CsvOutputRow,
+    options: &MergeOptions,
+    memo_template: Option<(&str, HashMap<&str, String>)>,
+    skipped_rows: &mut Vec<(SkipReason, CsvOutputRow)>,
+) -> io::Result<Vec<CsvOutputRow>> {
+    if options.vat {
+        if let Some((rate, vat_amount)) = extract_vat(&transaction.memo, &transaction.amount) {
+            transaction.extra.insert(ExtraColumn::VatRate, rate);
+            transaction.extra.insert(ExtraColumn::VatAmount, vat_amount);
+        }
+        if options.net_amounts {
+            let vat_amount = transaction
+                .extra
+                .get(&ExtraColumn::VatAmount)
+                .map(String::as_str)
+                .unwrap_or_default();
+            let net = net_amount(&transaction.amount, vat_amount);
+            transaction.extra.insert(ExtraColumn::NetAmount, net);
+            transaction
+                .extra
+                .insert(ExtraColumn::GrossAmount, transaction.amount.clone());
+        }
+    }
+    if let Some(invoice_number) =
+        extract_invoice_number(&transaction.memo, &options.invoice_patterns)
+    {
+        transaction
+            .extra
+            .insert(ExtraColumn::Invoice, invoice_number);
+    }
+    if let Some((template, column_values)) = memo_template {
+        let mut values: HashMap<&str, String> = HashMap::from([
+            ("date", transaction.date.to_string()),
+            ("source", transaction.source.clone()),
+            ("currency", transaction.currency.clone()),
+            ("amount", transaction.amount.clone()),
+            ("type", transaction.transaction_type.clone()),
+            ("payee", transaction.payee.clone()),
+            ("memo", transaction.memo.clone()),
+        ]);
+        values.extend(column_values);
+        transaction.memo = render_template(template, &values);
+    }
+    if let Some(type_mappings) = &options.type_mappings {
+        // Internal transfers were already flagged, whatever their type
+        transaction
+            .extra
+            .entry(ExtraColumn::NormalizedType)
+            .or_insert_with(|| {
+                normalize_type(
+                    &transaction.transaction_type,
+                    &transaction.source,
+                    type_mappings,
+                )
+            });
+    }
+    if !options.payee_rules.is_empty() && options.aliases.get(&transaction.payee).is_none() {
+        transaction.payee = clean_payee(&transaction.payee, &options.payee_rules);
+    }
+    if let Some(name) = options.aliases.get(&transaction.payee) {
+        transaction.payee = name.to_string();
+    }
+    if let Some(filter) = &options.payee_filter {
+        if !filter.keeps(&transaction.payee) {
+            skipped_rows.push((SkipReason::Payee, transaction));
+            return Ok(Vec::new());
+        }
+    }
+    if let Some(tag_rules) = &options.tag_rules {
+        let tags = tags_for(&transaction, tag_rules);
+        transaction.extra.insert(ExtraColumn::Tags, tags);
+    }
+    if let Some((category, confidence)) = options
+        .classifier
+        .as_ref()
+        .and_then(|classifier| classifier.suggest(&transaction))
+    {
+        transaction
+            .extra
+            .insert(ExtraColumn::SuggestedCategory, category);
+        transaction.extra.insert(
+            ExtraColumn::Confidence,
+            format!("{:.2}", confidence).replace('.', ","),
+        );
+    }
+    if options.mask_pii {
+        transaction.memo = mask_pii(&transaction.memo);
+    }
+    if let Some(key) = &options.pseudonym_key {
+        transaction.payee = pseudonymize(&transaction.payee, key);
+    }
+    let rows = match options
+        .split_rules
+        .iter()
+        .find(|rule| rule.condition.matches(&transaction))
+    {
+        Some(rule) => rule.split(&transaction),
+        None => vec![transaction],
+    };
+    let Some(script) = &options.script else {
+        return Ok(rows);
+    };
+    let mut finished = Vec::new();
+    for row in rows {
+        match script.apply(row.clone())? {
+            Some(transformed) => finished.push(transformed),
+            None => skipped_rows.push((SkipReason::Script, row)),
+        }
+    }
+    Ok(finished)
+}
+
+/// A separate expense for the fee of a transaction, see [`SourceSpec::fee_column`];
+/// a negative fee (e.g. refunded by Stripe with its payment) is an income. `None` if the fee is empty or zero
+///
+/// # Arguments
+///
+/// * `transaction`: the transaction the fee was charged for
+/// * `fee`: the fee, with the decimal separator of the amounts already normalized
+/// * `options`: the options of the merge
+fn fee_transaction(
+    transaction: &CsvOutputRow,
+    fee: &str,
+    options: &MergeOptions,
+) -> Option<CsvOutputRow> {
+    let fee = strip_currency_symbols(fee);
+    if !fee
+        .replace(',', ".")
+        .parse::<f64>()
+        .is_ok_and(|value| value != 0.0)
+    {
+        return None;
+    }
+    // Sources write the fees they charge as positive numbers
+    let amount = match fee.strip_prefix('-') {
+        Some(refunded) => refunded.to_string(),
+        None => format!("-{}", fee),
+    };
+    let mut fee_transaction = CsvOutputRow::new(
+        transaction.date,
+        transaction.source.clone(),
+        transaction.currency.clone(),
+        amount,
+        "fee".to_string(),
+        transaction.payee.clone(),
+        transaction.memo.clone(),
+    );
+    fee_transaction.sequence = transaction.sequence;
+    // The fee happened with its transaction, in the same account
+    for column in [ExtraColumn::Time, ExtraColumn::Account, ExtraColumn::Id] {
+        if let Some(value) = transaction.extra.get(&column) {
+            fee_transaction.extra.insert(column, value.clone());
+        }
+    }
+    if options.type_mappings.is_some() {
+        fee_transaction
+            .extra
+            .insert(ExtraColumn::NormalizedType, "fee".to_string());
+    }
+    Some(fee_transaction)
+}
+
+/// Finish a transaction (see [`finish_transaction`]) and its fee, if any: the fee goes through
+/// the same rules and masking, and is dropped with its transaction
+///
+/// returns: Result<Vec<CsvOutputRow>, Error> with the fee followed by the rows of the transaction
+fn finish_with_fee(
+    transaction: CsvOutputRow,
+    fee: Option<CsvOutputRow>,
+    options: &MergeOptions,
+    memo_template: Option<(&str, HashMap<&str, String>)>,
+    skipped_rows: &mut Vec<(SkipReason, CsvOutputRow)>,
+) -> io::Result<Vec<CsvOutputRow>> {
+    let skipped_before = skipped_rows.len();
+    let finished = finish_transaction(transaction, options, memo_template, skipped_rows)?;
+    let Some(fee) = fee else {
+        return Ok(finished);
+    };
+    if finished.is_empty() {
+        if let Some(&(reason, _)) = skipped_rows.get(skipped_before) {
+            skipped_rows.push((reason, fee));
+        }
+        return Ok(finished);
+    }
+    let mut rows = finish_transaction(fee, options, None, skipped_rows)?;
+    rows.extend(finished);
+    Ok(rows)
+}
+
+/// Read the file of a registered source with lines above its header, if one of them recognizes it
+/// (see [`SourceSpec::header_after_preamble`]); the lines above the header of built-in formats
+/// are skipped when the separator is detected
+fn read_after_preamble(
+    file_path: &Path,
+    options: &MergeOptions,
+) -> Result<Option<FileContent>, BankCsvError> {
+    let sources = &options.sources;
+    if sources
+        .specs()
+        .all(|spec| spec.format().is_some() || spec.header_after_preamble().is_none())
+    {
+        return Ok(None);
+    }
+    let content = std::fs::read(file_path)?;
+    let detected_encoding = detect_encoding(&content);
+    let (text, _, _) = detected_encoding.decode(&content);
+    let Some(spec) = sources
+        .detect_after_preamble(&text)
+        .filter(|spec| spec.format().is_none())
+    else {
+        return Ok(None);
+    };
+    let temp_file = NamedTempFile::new()?;
+    spec.edit_file(
+        file_path,
+        &temp_file,
+        spec.encoding().unwrap_or(detected_encoding),
+    )?;
+    let (separator, _) = detect_separator(temp_file.path())?;
+    let (df_csv, _, _) = read_data_frame(temp_file.path(), separator, None, Some(UTF_8), sources)?;
+    read_registered_file(file_path, &df_csv, spec, options).map(Some)
+}
+
+/// Read a bank export whose header matches a registered source, e.g. a format descriptor
+fn read_registered_file(
+    file_path: &Path,
+    df_csv: &DataFrame,
+    spec: &dyn SourceSpec,
+    options: &MergeOptions,
+) -> Result<FileContent, BankCsvError> {
+    let upper_currency = options.currency.to_uppercase();
+    let source = spec.source_name();
+    let column_names = df_csv.get_column_names();
+    // Date, currency, amount, type, payee and memo, followed by the extra columns of the source
+    let columns: Vec<Option<&Series>> = spec
+        .columns(&column_names, &upper_currency)
+        .into_iter()
+        .map(|name| match name {
+            "" => Ok(None),
+            name => df_csv
+                .column(name)
+                .map(Some)
+                .map_err(|_| BankCsvError::MissingColumn {
+                    file_path: file_path.to_path_buf(),
+                    column: name.to_string(),
+                    columns: column_names.iter().map(|name| name.to_string()).collect(),
+                }),
+        })
+        .collect::<Result<_, _>>()?;
+    let column = |index: usize| columns.get(index).copied().flatten();
+    // The date and amount are required; the other columns can be left empty in the spec
+    let required = |index: usize, name: &str| {
+        column(index).ok_or_else(|| BankCsvError::MissingColumn {
+            file_path: file_path.to_path_buf(),
+            column: name.to_string(),
+            columns: column_names.iter().map(|name| name.to_string()).collect(),
+        })
+    };
+    let date_column = required(0, "date")?;
+    let amount_column = required(2, "amount")?;
+    let fee_column = spec
+        .fee_column()
+        .map(|name| {
+            df_csv
+                .column(name)
+                .map_err(|_| BankCsvError::MissingColumn {
+                    file_path: file_path.to_path_buf(),
+                    column: name.to_string(),
+                    columns: column_names.iter().map(|name| name.to_string()).collect(),
+                })
+        })
+        .transpose()?;
+    let memo_template = options.memo_templates.get(&source_key(&source));
+    let source_filter = options.source_filters.get(&source_key(&source));
+    let template_columns: Vec<&Series> = memo_template
+        .map(|template| {
+            template_placeholders(template)
+                .into_iter()
+                .filter_map(|placeholder| df_csv.column(placeholder).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    let date_formats = spec.date_formats();
+
+    let raw_amounts: Vec<String> = amount_column
+        .iter()
+        .map(|value| TextNormalizer::default().value(&value))
+        .collect();
+    let decimal_separator = detect_decimal_separator(raw_amounts.iter().map(String::as_str));
+    let normalizer = TextNormalizer::default().with_decimal_separator(decimal_separator);
+    let value = |series: Option<&Series>, row_index: usize| -> PolarsResult<String> {
+        match series {
+            Some(series) => Ok(normalizer.value(&series.get(row_index)?)),
+            None => Ok(String::new()),
+        }
+    };
+    let mut last_date: Option<NaiveDate> = None;
+    let mut transactions = Vec::new();
+    let mut skipped_rows = Vec::new();
+    for row_index in 0..df_csv.height() {
+        let mut source_row = SourceRow {
+            currency: value(column(1), row_index)?,
+            amount: value(Some(amount_column), row_index)?,
+            transaction_type: value(column(3), row_index)?,
+            payee: value(column(4), row_index)?,
+            memo: value(column(5), row_index)?,
+            extra: (NUM_SELECT_COLUMNS..columns.len())
+                .map(|index| value(column(index), row_index))
+                .collect::<PolarsResult<_>>()?,
+        };
+        if source_filter
+            .is_some_and(|filter| filter.excludes(&source_row.transaction_type, &source_row.payee))
+        {
+            continue;
+        }
+        if !spec.post_process(&mut source_row, &upper_currency)? {
+            continue;
+        }
+        let currency = source_row.currency.to_uppercase();
+        let other_currency = currency != upper_currency && !options.no_filter;
+        if other_currency && !options.read_other_currencies {
+            continue;
+        }
+        let date_value = date_column.get(row_index)?;
+        let naive_date = match polars_date(&date_value) {
+            Some(date) => date,
+            None => {
+                let text = normalizer.value(&date_value);
+                parse_date_str(&text, &date_formats).ok_or_else(|| BankCsvError::DateParse {
+                    file_path: file_path.to_path_buf(),
+                    line: find_line(file_path, &text),
+                    value: text.clone(),
+                    format: spec.name(),
+                    date_formats: date_formats.iter().map(|f| f.to_string()).collect(),
+                })?
+            }
+        };
+        let mut transaction = CsvOutputRow::new(
+            naive_date,
+            source.clone(),
+            currency,
+            normalizer.amount(&source_row.amount),
+            source_row.transaction_type,
+            source_row.payee,
+            source_row.memo,
+        );
+        transaction.sequence = Some((0, row_index));
+        let fee_row = match fee_column {
+            Some(series) => {
+                let fee = normalizer.amount(&value(Some(series), row_index)?);
+                fee_transaction(&transaction, &fee, options)
+            }
+            None => None,
+        };
+        let skip_reason = Some(SkipReason::Currency)
+            .filter(|_| other_currency)
+            .or_else(|| skip_reason(&transaction, options))
+            .or_else(|| internal_transfer(spec, &mut transaction, options));
+        if let Some(reason) = skip_reason {
+            // The fee is skipped with its transaction
+            skipped_rows.extend(fee_row.map(|fee| (reason, fee)));
+            skipped_rows.push((reason, transaction));
+            continue;
+        }
+        let template_values = memo_template.map(|template| {
+            let values = template_columns
+                .iter()
+                .map(|series| Ok((series.name(), value(Some(series), row_index)?)))
+                .collect::<PolarsResult<_>>();
+            values.map(|values| (template.as_str(), values))
+        });
+        let finished = finish_with_fee(
+            transaction,
+            fee_row,
+            options,
+            template_values.transpose()?,
+            &mut skipped_rows,
+        )?;
+        last_date = last_date.max(finished.iter().map(|row| row.date).max());
+        transactions.extend(finished);
+    }
+    Ok(FileContent::Custom {
+        name: spec.name(),
+        source,
+        transactions,
+        last_date,
+        skipped_rows,
+    })
+}
+
+/// Convert transactions into a data frame with one column per output field, followed by the extra
+/// columns that have values; amounts are numbers, other columns are strings
+pub fn transactions_to_dataframe(transactions: &[CsvOutputRow]) -> PolarsResult<DataFrame> {
+    let header = CsvOutputRow::header(Locale::En, &[]);
+    let name = |index: usize| header.get(index).unwrap_or_default();
+    let mut columns = vec![
+        Series::new(
+            name(0),
+            transactions
+                .iter()
+                .map(|transaction| transaction.date)
+                .collect::<Vec<NaiveDate>>(),
+        ),
+        Series::new(
+            name(1),
+            transactions
+                .iter()
+                .map(|transaction| transaction.source.clone())
+                .collect::<Vec<String>>(),
+        ),
+        Series::new(
+            name(2),
+            transactions
+                .iter()
+                .map(|transaction| transaction.currency.clone())
+                .collect::<Vec<String>>(),
+        ),
+        Series::new(
+            name(3),
+            transactions
+                .iter()
+                .map(|transaction| transaction.amount.replace(',', ".").parse::<f64>().ok())
+                .collect::<Vec<Option<f64>>>(),
+        ),
+        Series::new(
+            name(4),
+            transactions
+                .iter()
+                .map(|transaction| transaction.transaction_type.clone())
+                .collect::<Vec<String>>(),
+        ),
+        Series::new(
+            name(5),
+            transactions
+                .iter()
+                .map(|transaction| transaction.payee.clone())
+                .collect::<Vec<String>>(),
+        ),
+        Series::new(
+            name(6),
+            transactions
+                .iter()
+                .map(|transaction| transaction.memo.clone())
+                .collect::<Vec<String>>(),
+        ),
+    ];
+    for column in ExtraColumn::ALL {
+        if transactions
+            .iter()
+            .any(|transaction| transaction.extra.contains_key(&column))
+        {
+            columns.push(Series::new(
+                column.label(Locale::En),
+                transactions
+                    .iter()
+                    .map(|transaction| transaction.extra.get(&column).cloned())
+                    .collect::<Vec<Option<String>>>(),
+            ));
+        }
+    }
+    DataFrame::new(columns)
+}
+
+/// The transactions merged by [`merge_files`], and the files that were not read
+#[derive(Default)]
+pub struct MergedFiles {
+    /// The normalized transactions of all files, sorted by date
+    pub transactions: Vec<CsvOutputRow>,
+    /// The files that were not read (missing, skipped, unknown format, ...), with the reason
+    pub ignored: Vec<(PathBuf, BankCsvError)>,
+}
+
+/// Hooks of [`merge_files_with`], for callers that handle each file on their own, like the `merge` command;
+/// the defaults read all files like [`merge_files`]
+pub trait MergeHandler {
+    /// The options to read a file with, by its index in the list of files; by default, the given ones
+    fn options<'a>(
+        &mut self,
+        _index: usize,
+        _file_path: &Path,
+        options: &'a MergeOptions,
+    ) -> Cow<'a, MergeOptions> {
+        Cow::Borrowed(options)
+    }
+
+    /// Choose the format of a file that can't be detected, when `options.assume` is not set;
+    /// by default (or returning `None`) the file is not read
+    fn choose_format(
+        &mut self,
+        _index: usize,
+        _file_path: &Path,
+        _candidates: &[CsvFormat],
+    ) -> io::Result<Option<CsvFormat>> {
+        Ok(None)
+    }
+
+    /// Called with the content of a file before its transactions are merged, which can be changed here;
+    /// `sha256` is the hash of the file (see [`file_hash`])
+    fn read(
+        &mut self,
+        _index: usize,
+        _file_path: &Path,
+        _sha256: &str,
+        _content: &mut FileContent,
+    ) {
+    }
+
+    /// Called with each file that is not read, and why; `sha256` is the hash of the file, if it could be read
+    fn ignored(
+        &mut self,
+        _index: usize,
+        _file_path: &Path,
+        _sha256: Option<&str>,
+        _err: &BankCsvError,
+    ) {
+    }
+}
+
+/// The hooks of [`merge_files`], all with their defaults
+struct DefaultHandler;
+
+impl MergeHandler for DefaultHandler {}
+
+/// Read bank CSV files and merge their transactions, sorted by date, without printing or writing anything
+///
+/// Files with the same content are read only once; identical transactions are removed with `options.dedupe`.
+/// Files that can't be read (unknown format, missing or unreadable files, ...) are not merged, and returned with the reason;
+/// set `options.assume` to read files whose format can't be detected.
+///
+/// # Arguments
+///
+/// * `file_paths`: bank CSV files or files generated by a previous run
+/// * `options`: currency, optional columns and transformations
+///
+/// returns: Result<MergedFiles, BankCsvError> with the normalized transactions,
+/// like the rows of the files written by the `merge` command, and the ignored files; a file that can't be hashed
+/// (e.g. permission denied) is ignored too, instead of failing the whole merge
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::merge::{merge_files, MergeOptions};
+/// let merged = merge_files(&["missing.csv".into()], &MergeOptions::default()).unwrap();
+/// assert!(merged.transactions.is_empty());
+/// let (path, err) = &merged.ignored[0];
+/// assert_eq!(path.to_str(), Some("missing.csv"));
+/// assert_eq!(err.reason(), "the file does not exist");
+/// ```
+pub fn merge_files(
+    file_paths: &[PathBuf],
+    options: &MergeOptions,
+) -> Result<MergedFiles, BankCsvError> {
+    merge_files_with(file_paths, options, &mut DefaultHandler)
+}
+
+/// Read bank CSV files and merge their transactions like [`merge_files`], calling the hooks of `handler`
+/// for each file, e.g. to read each file with its own options or to log what was read
+pub fn merge_files_with(
+    file_paths: &[PathBuf],
+    options: &MergeOptions,
+    handler: &mut dyn MergeHandler,
+) -> Result<MergedFiles, BankCsvError> {
+    let mut merged: TransactionSet<CsvOutputRow> = TransactionSet::new(options.dedupe);
+    let mut hashes: HashMap<String, &Path> = HashMap::new();
+    let mut ignored = Vec::new();
+    for (file_index, file_path) in file_paths.iter().enumerate() {
+        let (sha256, err) = if !file_path.exists() {
+            let err = io::Error::new(io::ErrorKind::NotFound, "the file does not exist");
+            (None, err.into())
+        } else {
+            // A file that exists but can't be read (e.g. permission denied) is ignored like a missing one
+            let sha256 = match file_hash(file_path) {
+                Ok(sha256) => sha256,
+                Err(err) => {
+                    handler.ignored(file_index, file_path, None, &err);
+                    ignored.push((file_path.clone(), err));
+                    continue;
+                }
+            };
+            let content = match hashes.get(&sha256) {
+                Some(first_path) => Err(BankCsvError::Duplicate {
+                    file_path: file_path.clone(),
+                    first_path: first_path.to_path_buf(),
+                }),
+                None => {
+                    hashes.insert(sha256.clone(), file_path);
+                    let file_options = handler.options(file_index, file_path, options);
+                    read_file(file_path, &file_options, &mut |candidates| {
+                        handler.choose_format(file_index, file_path, candidates)
+                    })
+                }
+            };
+            let err = match content {
+                Ok(FileContent::Skipped(reason)) => BankCsvError::Skipped {
+                    file_path: file_path.clone(),
+                    reason,
+                },
+                Ok(FileContent::UnknownFormat(err)) | Err(err) => err,
+                Ok(mut content) => {
+                    handler.read(file_index, file_path, &sha256, &mut content);
+                    let transactions = match content {
+                        FileContent::Transactions { transactions, .. }
+                        | FileContent::Custom { transactions, .. }
+                        | FileContent::Output(transactions) => transactions,
+                        FileContent::Skipped(_) | FileContent::UnknownFormat(_) => Vec::new(),
+                    };
+                    for mut transaction in transactions {
+                        if let Some(sequence) = transaction.sequence.as_mut() {
+                            sequence.0 = file_index;
+                        }
+                        merged.push(transaction);
+                    }
+                    continue;
+                }
+            };
+            (Some(sha256), err)
+        };
+        handler.ignored(file_index, file_path, sha256.as_deref(), &err);
+        ignored.push((file_path.clone(), err));
+    }
+    Ok(MergedFiles {
+        transactions: merged.iter().cloned().collect(),
+        ignored,
+    })
+}
+
+/// Read bank CSV files and merge their transactions into a single data frame, sorted by date,
+/// see [`merge_files`]; the files that can't be read are left out
+///
+/// returns: Result<DataFrame, BankCsvError> with the columns described in [`transactions_to_dataframe`]
+pub fn merge_to_dataframe(
+    file_paths: &[PathBuf],
+    options: &MergeOptions,
+) -> Result<DataFrame, BankCsvError> {
+    let merged = merge_files(file_paths, options)?;
+    Ok(transactions_to_dataframe(&merged.transactions)?)
+}
+
+/// Count the transactions of a bank CSV file (or a file generated by a previous run) per currency,
+/// e.g. to offer a choice of currencies before merging
+///
+/// N26 rows without a currency and all rows of DKB files are counted as euros;
+/// Kraken asset codes are converted to currencies (e.g. `ZEUR` to `EUR`, `XXBT` to `BTC`).
+///
+/// # Arguments
+///
+/// * `file_path`: path to the CSV file
+/// * `sources`: the sources to detect the format with, see [`MergeOptions::sources`]
+///
+/// returns: Result<HashMap<String, usize>, BankCsvError> with the number of rows per currency, in uppercase;
+/// an error if the format can't be detected
+pub fn currencies_in_file(
+    file_path: &Path,
+    sources: &SourceRegistry,
+) -> Result<HashMap<String, usize>, BankCsvError> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    if is_output_file(file_path).unwrap_or(false) {
+        for transaction in read_output_file(file_path)? {
+            *counts.entry(transaction.currency).or_default() += 1;
+        }
+        return Ok(counts);
+    }
+    let (separator, detected_source) = detect_separator(file_path)?;
+    let (info, df_csv, _) = read_detected(file_path, separator, detected_source, sources)?;
+    let format = info.format.ok_or_else(|| BankCsvError::UnknownFormat {
+        file_path: file_path.to_path_buf(),
+        reason: format!("unknown CSV format (candidates: {:?})", info.candidates),
+        columns: df_csv
+            .get_column_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    })?;
+    Ok(count_currencies(df_csv, format)?)
+}
+
+/// Pair the legs of PayPal currency conversions into one transaction in the requested currency;
+/// both amounts and the rate go to the memo
+///
+/// Legs are paired among the rows with the same date and time: each debit with the next credit,
+/// in the order of the file, so conversions at the same second are not mixed up.
+///
+/// returns: the conversions and the number of legs without a counterpart, which are left out
+fn paypal_conversions(
+    df: &DataFrame,
+    format: CsvFormat,
+    upper_currency: &str,
+    file_path: &Path,
+) -> Result<(Vec<CsvOutputRow>, usize), BankCsvError> {
+    let type_column = if format == CsvFormat::PayPal {
+        "Type"
+    } else {
+        "Description"
+    };
+    let normalizer = TextNormalizer::default();
+    let text = |name: &str, index: usize| -> PolarsResult<String> {
+        Ok(normalizer.value(&df.column(name)?.get(index)?))
+    };
+    let mut legs: BTreeMap<(String, String), Vec<usize>> = BTreeMap::new();
+    for index in 0..df.height() {
+        if PAYPAL_CURRENCY_CONVERSION.contains(&text(type_column, index)?.as_str()) {
+            legs.entry((text("Date", index)?, text("Time", index)?))
+                .or_default()
+                .push(index);
+        }
+    }
+    let raw_amounts: Vec<String> = legs
+        .values()
+        .flatten()
+        .map(|&index| text("Gross", index))
+        .collect::<PolarsResult<_>>()?;
+    let decimal_separator = detect_decimal_separator(raw_amounts.iter().map(String::as_str));
+
+    let mut transactions = Vec::new();
+    let mut unpaired = 0;
+    for indexes in legs.values() {
+        let mut debits = Vec::new();
+        let mut credits = Vec::new();
+        for &index in indexes {
+            let amount = normalizer
+                .with_decimal_separator(decimal_separator)
+                .amount(&text("Gross", index)?);
+            let leg = (index, text("Currency", index)?.to_uppercase(), amount);
+            if leg.2.starts_with('-') {
+                debits.push(leg);
+            } else {
+                credits.push(leg);
+            }
+        }
+        // Conversions without a counterpart can't be described
+        unpaired += debits.len().abs_diff(credits.len());
+        for ((first, from_currency, from_amount), (_, to_currency, to_amount)) in
+            debits.into_iter().zip(credits)
+        {
+            let from = (from_currency, from_amount);
+            let to = (to_currency, to_amount);
+            let requested = if from.0 == upper_currency {
+                &from
+            } else if to.0 == upper_currency {
+                &to
+            } else {
+                continue;
+            };
+            let Some(memo) = conversion_memo((&from.0, &from.1), (&to.0, &to.1)) else {
+                continue;
+            };
+            let date_value = df.column("Date")?.get(first)?;
+            transactions.push(CsvOutputRow::new(
+                parse_date(&date_value, format, file_path)?,
+                Source::PayPal.to_string(),
+                requested.0.clone(),
+                requested.1.clone(),
+                text(type_column, first)?,
+                String::new(),
+                memo,
+            ));
+        }
+    }
+    Ok((transactions, unpaired))
+}
+
+/// Count the rows of a data frame per currency, according to the currency column of its format
+fn count_currencies(df: &DataFrame, format: CsvFormat) -> PolarsResult<HashMap<String, usize>> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let spec = format.spec();
+    let default_currency = spec.default_currency(&df.get_column_names());
+    let Some(currency_column) = spec.currency_column() else {
+        counts.insert(default_currency, df.height());
+        return Ok(counts);
+    };
+    for value in df.column(currency_column)?.iter() {
+        let currency = match value.get_str() {
+            Some(text) if !text.trim().is_empty() => spec.currency_code(text),
+            _ => default_currency.clone(),
+        };
+        *counts.entry(currency).or_default() += 1;
+    }
+    Ok(counts)
+}
@@ -0,0 +1,132 @@
+//! Accountant package: the files of a merge run in one ZIP archive, e.g. to hand over to a tax advisor
+use crate::index::IndexEntry;
+use crate::unzip::to_io_error;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Name of the run summary in the package: the inputs, outputs and settings of the run
+pub const SUMMARY_FILE_NAME: &str = "summary.json";
+
+/// Name of the overview in the package, with `--package-overview`
+pub const OVERVIEW_FILE_NAME: &str = "overview.html";
+
+/// Write a ZIP archive with the given files, replacing an existing archive
+///
+/// # Arguments
+///
+/// * `zip_file`: path of the archive
+/// * `files`: name of each file inside the archive, and its content
+pub fn write_package(zip_file: &Path, files: &[(String, Vec<u8>)]) -> io::Result<()> {
+    let mut archive = ZipWriter::new(File::create(zip_file)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in files {
+        archive
+            .start_file(name.as_str(), options)
+            .map_err(|err| to_io_error(zip_file, err))?;
+        archive.write_all(content)?;
+    }
+    archive.finish().map_err(|err| to_io_error(zip_file, err))?;
+    Ok(())
+}
+
+/// Escape the characters with a meaning in HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A standalone HTML page with the files of a run, their totals per currency and the skipped
+/// transactions, readable in any browser (or printed to PDF)
+///
+/// # Arguments
+///
+/// * `entries`: the index lines of the files written by the run
+/// * `skipped`: number of dropped transactions per reason
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::index::{IndexEntry, Totals};
+/// use bank_csv::package::overview_html;
+/// let totals = |rows, income, expenses| Totals { rows, income, expenses };
+/// let entries = [
+///     IndexEntry::new("jan.csv".into(), "2024-01".into(), "EUR".into(), totals(3, 100.0, -20.5)),
+///     IndexEntry::new("feb.csv".into(), "2024-02".into(), "EUR".into(), totals(2, 50.0, -70.0)),
+/// ];
+/// let html = overview_html(&entries, &[("zero amount".to_string(), 4)]);
+/// assert!(html.contains("<td>2024-01</td><td>EUR</td><td>3</td><td>100,00</td><td>-20,50</td><td>79,50</td>"));
+/// assert!(html.contains("<th colspan=\"2\">2024-01 to 2024-02</th><th>EUR</th><th>5</th><th>150,00</th><th>-90,50</th><th>59,50</th>"));
+/// assert!(html.contains("<td>zero amount</td><td>4</td>"));
+/// ```
+pub fn overview_html(entries: &[IndexEntry], skipped: &[(String, usize)]) -> String {
+    let amount = |value: f64| format!("{:.2}", value).replace('.', ",");
+    let parse = |value: &str| value.replace(',', ".").parse::<f64>().unwrap_or_default();
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>bank-csv overview</title>\n\
+         <style>body { font-family: sans-serif; } table { border-collapse: collapse; } \
+         td, th { border: 1px solid #999; padding: 0.2em 0.6em; } td:nth-child(n+4) { text-align: right; }</style>\n\
+         </head>\n<body>\n<h1>Transactions</h1>\n<table>\n\
+         <tr><th>File</th><th>Period</th><th>Currency</th><th>Rows</th><th>Income</th><th>Expenses</th><th>Total</th></tr>\n",
+    );
+    // First and last period, rows, income and expenses per currency
+    let mut totals: BTreeMap<&str, (&str, &str, usize, f64, f64)> = BTreeMap::new();
+    for entry in entries {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&entry.file),
+            escape_html(&entry.period),
+            escape_html(&entry.currency),
+            entry.rows,
+            entry.income,
+            entry.expenses,
+            entry.total
+        ));
+        let total =
+            totals
+                .entry(&entry.currency)
+                .or_insert((&entry.period, &entry.period, 0, 0.0, 0.0));
+        total.0 = total.0.min(&entry.period);
+        total.1 = total.1.max(&entry.period);
+        total.2 += entry.rows;
+        total.3 += parse(&entry.income);
+        total.4 += parse(&entry.expenses);
+    }
+    for (currency, (first, last, rows, income, expenses)) in totals {
+        let period = if first == last {
+            first.to_string()
+        } else {
+            format!("{} to {}", first, last)
+        };
+        html.push_str(&format!(
+            "<tr><th colspan=\"2\">{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr>\n",
+            escape_html(&period),
+            escape_html(currency),
+            rows,
+            amount(income),
+            amount(expenses),
+            amount(income + expenses)
+        ));
+    }
+    html.push_str("</table>\n");
+    if !skipped.is_empty() {
+        html.push_str(
+            "<h1>Skipped transactions</h1>\n<table>\n<tr><th>Reason</th><th>Rows</th></tr>\n",
+        );
+        for (reason, count) in skipped {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td></tr>\n",
+                escape_html(reason),
+                count
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
@@ -0,0 +1,71 @@
+//! Filter transactions by a list of payees, e.g. to extract the ones of tax-relevant merchants
+use crate::error::BankCsvError;
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A list of payees, used to keep only the matching transactions or to drop them
+///
+/// Each payee is an exact name or a glob pattern (`*` for any text, `?` for one character),
+/// compared ignoring case and surrounding spaces.
+#[derive(Clone, Debug)]
+pub struct PayeeFilter {
+    /// One anchored pattern per payee of the list
+    patterns: Vec<Regex>,
+    /// Drop the matching transactions instead of keeping only them
+    pub exclude: bool,
+}
+
+impl PayeeFilter {
+    /// Parse a list with one payee per line; blank lines and lines starting with `#` are ignored
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::payees::PayeeFilter;
+    /// let filter = PayeeFilter::parse("# Tax-relevant\nFinanzamt\nTechniker*\n\n", false);
+    /// assert!(filter.keeps("finanzamt"));
+    /// assert!(filter.keeps("Techniker Krankenkasse"));
+    /// assert!(!filter.keeps("Finanzamt Hamburg"));
+    /// assert!(!PayeeFilter::parse("Finanzamt", true).keeps("Finanzamt"));
+    /// ```
+    pub fn parse(content: &str, exclude: bool) -> Self {
+        let patterns = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let pattern: String = line
+                    .chars()
+                    .map(|c| match c {
+                        '*' => ".*".to_string(),
+                        '?' => ".".to_string(),
+                        _ => regex::escape(&c.to_string()),
+                    })
+                    .collect();
+                // Escaped text is always a valid pattern
+                Regex::new(&format!("(?i)^{}$", pattern)).unwrap()
+            })
+            .collect();
+        Self { patterns, exclude }
+    }
+
+    /// Read the list of payees from a file, see [`PayeeFilter::parse`]
+    pub fn load(file_path: &Path, exclude: bool) -> Result<Self, BankCsvError> {
+        let content = fs::read_to_string(file_path).map_err(|err| {
+            BankCsvError::Io(io::Error::new(
+                err.kind(),
+                format!("{}: {}", file_path.display(), err),
+            ))
+        })?;
+        Ok(Self::parse(&content, exclude))
+    }
+
+    /// Check if a transaction with this payee is kept
+    pub fn keeps(&self, payee: &str) -> bool {
+        let payee = payee.trim();
+        let listed = self.patterns.iter().any(|pattern| pattern.is_match(payee));
+        listed != self.exclude
+    }
+}
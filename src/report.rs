@@ -0,0 +1,165 @@
+//! Checks and statistics on the monthly files generated by `merge`
+use crate::index::Totals;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A month with fewer transactions than this fraction of its neighbors' average is suspicious
+const FEW_TRANSACTIONS_RATIO: f64 = 0.5;
+/// Neighbors with fewer transactions than this (on average) are too quiet to compare with
+const MIN_NEIGHBOR_AVERAGE: f64 = 4.0;
+
+/// A problem found when checking the months of the generated files
+#[derive(Debug, PartialEq)]
+pub enum MonthWarning {
+    /// No file for this month, although there are files before and after it
+    Missing {
+        /// Year of the missing month
+        year: i32,
+        /// Missing month (1-12)
+        month: u32,
+    },
+    /// Much fewer transactions than the neighboring months, maybe an export was forgotten
+    FewTransactions {
+        /// Year of the month
+        year: i32,
+        /// Month (1-12)
+        month: u32,
+        /// Number of transactions in the month
+        count: usize,
+        /// Average number of transactions of the previous and next months
+        neighbor_average: f64,
+    },
+}
+
+impl fmt::Display for MonthWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MonthWarning::Missing { year, month } => {
+                write!(f, "{:04}-{:02}: missing month", year, month)
+            }
+            MonthWarning::FewTransactions {
+                year,
+                month,
+                count,
+                neighbor_average,
+            } => write!(
+                f,
+                "{:04}-{:02}: only {} transaction(s), neighbor months have {:.0} on average",
+                year, month, count, neighbor_average
+            ),
+        }
+    }
+}
+
+/// The month after the given one
+fn next_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    }
+}
+
+/// Check the number of transactions per month for missing months and months with suspiciously few
+/// transactions compared to their neighbors
+///
+/// # Arguments
+///
+/// * `counts`: number of transactions per (year, month)
+///
+/// returns: Vec<MonthWarning>
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::report::{check_months, MonthWarning};
+/// use std::collections::BTreeMap;
+/// let counts = BTreeMap::from([((2023, 11), 30), ((2024, 1), 3), ((2024, 2), 28)]);
+/// assert_eq!(check_months(&counts), vec![
+///     MonthWarning::Missing { year: 2023, month: 12 },
+///     MonthWarning::FewTransactions { year: 2024, month: 1, count: 3, neighbor_average: 29.0 },
+/// ]);
+/// ```
+pub fn check_months(counts: &BTreeMap<(i32, u32), usize>) -> Vec<MonthWarning> {
+    let mut warnings = Vec::new();
+    let (Some(first), Some(last)) = (counts.keys().next(), counts.keys().last()) else {
+        return warnings;
+    };
+
+    let mut current = *first;
+    while current <= *last {
+        let (year, month) = current;
+        match counts.get(&current) {
+            None => warnings.push(MonthWarning::Missing { year, month }),
+            Some(&count) => {
+                let previous = counts.range(..current).next_back().map(|(_, c)| *c);
+                let next = counts
+                    .range(next_month(year, month)..)
+                    .next()
+                    .map(|(_, c)| *c);
+                let neighbors: Vec<usize> = previous.into_iter().chain(next).collect();
+                if !neighbors.is_empty() {
+                    let neighbor_average =
+                        neighbors.iter().sum::<usize>() as f64 / neighbors.len() as f64;
+                    if neighbor_average >= MIN_NEIGHBOR_AVERAGE
+                        && (count as f64) < neighbor_average * FEW_TRANSACTIONS_RATIO
+                    {
+                        warnings.push(MonthWarning::FewTransactions {
+                            year,
+                            month,
+                            count,
+                            neighbor_average,
+                        });
+                    }
+                }
+            }
+        }
+        current = next_month(year, month);
+    }
+    warnings
+}
+
+/// Running totals of each month since the start of its year (year to date)
+///
+/// # Arguments
+///
+/// * `months`: totals of each (year, month)
+///
+/// returns: BTreeMap<(i32, u32), Totals> with the sum of the totals of the month
+/// and the previous months of the same year
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::index::Totals;
+/// use bank_csv::report::year_to_date;
+/// use std::collections::BTreeMap;
+/// let totals = |rows, income, expenses| Totals { rows, income, expenses };
+/// let months = BTreeMap::from([
+///     ((2023, 12), totals(2, 100.0, -50.0)),
+///     ((2024, 1), totals(3, 2000.0, -800.0)),
+///     ((2024, 2), totals(1, 0.0, -300.0)),
+/// ]);
+/// assert_eq!(year_to_date(&months), BTreeMap::from([
+///     ((2023, 12), totals(2, 100.0, -50.0)),
+///     ((2024, 1), totals(3, 2000.0, -800.0)),
+///     ((2024, 2), totals(4, 2000.0, -1100.0)),
+/// ]));
+/// ```
+pub fn year_to_date(months: &BTreeMap<(i32, u32), Totals>) -> BTreeMap<(i32, u32), Totals> {
+    let mut running = Totals::default();
+    let mut running_year = None;
+    months
+        .iter()
+        .map(|(&(year, month), totals)| {
+            if running_year != Some(year) {
+                running = Totals::default();
+                running_year = Some(year);
+            }
+            running.rows += totals.rows;
+            running.income += totals.income;
+            running.expenses += totals.expenses;
+            ((year, month), running)
+        })
+        .collect()
+}
@@ -0,0 +1,198 @@
+//! Find which rules of the configuration match a transaction, to debug them before a merge
+use crate::config::Config;
+use crate::split::SplitRule;
+use crate::tags::TagRule;
+use crate::{CsvOutputRow, DEFAULT_PAYEE_RULES};
+use regex::Regex;
+use serde::Deserialize;
+use std::fmt;
+
+/// The conditions of a tag or split rule
+///
+/// All the conditions that are given must match (case-insensitive substrings);
+/// a condition without any of them matches nothing.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RuleCondition {
+    /// The source must be this one (N26, DKB, ...)
+    pub source: Option<String>,
+    /// The payee must contain this text
+    pub payee: Option<String>,
+    /// The memo must contain this text
+    pub memo: Option<String>,
+    /// The transaction type must contain this text
+    #[serde(rename = "type")]
+    pub transaction_type: Option<String>,
+}
+
+/// Check if `text` contains `pattern`, ignoring case; a missing pattern always matches
+fn contains(text: &str, pattern: &Option<String>) -> bool {
+    match pattern {
+        Some(pattern) => text.to_lowercase().contains(&pattern.to_lowercase()),
+        None => true,
+    }
+}
+
+impl RuleCondition {
+    /// Check if the conditions apply to a transaction
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::rules::RuleCondition;
+    /// use bank_csv::CsvOutputRow;
+    /// use chrono::NaiveDate;
+    /// let condition = RuleCondition {
+    ///     source: Some("n26".to_string()),
+    ///     payee: Some("rewe".to_string()),
+    ///     ..RuleCondition::default()
+    /// };
+    /// let date = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+    /// assert!(condition.matches(&CsvOutputRow::builder(date).source("N26").payee("REWE Markt").build()));
+    /// assert!(!condition.matches(&CsvOutputRow::builder(date).source("DKB").payee("REWE Markt").build()));
+    /// assert!(!RuleCondition::default().matches(&CsvOutputRow::builder(date).build()));
+    /// ```
+    pub fn matches(&self, transaction: &CsvOutputRow) -> bool {
+        if self.source.is_none()
+            && self.payee.is_none()
+            && self.memo.is_none()
+            && self.transaction_type.is_none()
+        {
+            return false;
+        }
+        let same_source = match &self.source {
+            Some(source) => source.eq_ignore_ascii_case(&transaction.source),
+            None => true,
+        };
+        same_source
+            && contains(&transaction.payee, &self.payee)
+            && contains(&transaction.memo, &self.memo)
+            && contains(&transaction.transaction_type, &self.transaction_type)
+    }
+}
+
+/// The rules applied to each transaction with `--clean-payees`, `--tags` and the split rules
+#[derive(Clone, Debug, Default)]
+pub struct RuleSet {
+    /// Payee rules: the configured ones, followed by the built-in ones
+    pub payee_rules: Vec<(Regex, String)>,
+    /// How many of the payee rules come from the configuration
+    pub configured_payee_rules: usize,
+    /// Tag rules; the tags of all matching rules are added
+    pub tag_rules: Vec<TagRule>,
+    /// Split rules; the first matching rule is applied
+    pub split_rules: Vec<SplitRule>,
+}
+
+/// A rule that matched a transaction; rules are numbered from 1, in the order of the configuration
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuleMatch {
+    /// A payee rule, with the cleaned payee; built-in rules have no number
+    Payee(Option<usize>, String),
+    /// A tag rule, with its tags
+    Tag(usize, Vec<String>),
+    /// A split rule, with the labels of its parts
+    Split(usize, Vec<String>),
+}
+
+impl fmt::Display for RuleMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleMatch::Payee(Some(number), payee) => {
+                write!(f, "payee rule #{} ({})", number, payee)
+            }
+            RuleMatch::Payee(None, payee) => write!(f, "built-in payee rule ({})", payee),
+            RuleMatch::Tag(number, tags) => write!(f, "tag rule #{} ({})", number, tags.join(", ")),
+            RuleMatch::Split(number, labels) => {
+                write!(f, "split rule #{} ({})", number, labels.join(", "))
+            }
+        }
+    }
+}
+
+impl RuleSet {
+    /// The rules of a configuration, with the built-in payee rules after the configured ones
+    pub fn from_config(config: &Config) -> Result<Self, regex::Error> {
+        let payee_rules = config
+            .payee_rules
+            .iter()
+            .map(|rule| (rule.pattern.as_str(), rule.replacement.as_str()))
+            .chain(DEFAULT_PAYEE_RULES)
+            .map(|(pattern, replacement)| Ok((Regex::new(pattern)?, replacement.to_string())))
+            .collect::<Result<_, regex::Error>>()?;
+        Ok(Self {
+            payee_rules,
+            configured_payee_rules: config.payee_rules.len(),
+            tag_rules: config.tag_rules.clone(),
+            split_rules: config.split_rules.clone(),
+        })
+    }
+
+    /// The rules that match a transaction, in the order they are applied during a merge
+    ///
+    /// Like in a merge, tag and split rules see the payee cleaned by the payee rules.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::rules::{RuleCondition, RuleMatch, RuleSet};
+    /// use bank_csv::tags::TagRule;
+    /// use bank_csv::CsvOutputRow;
+    /// use chrono::NaiveDate;
+    /// use regex::Regex;
+    /// let rules = RuleSet {
+    ///     payee_rules: vec![(Regex::new("(?i)^HVV.*").unwrap(), "HVV".to_string())],
+    ///     configured_payee_rules: 1,
+    ///     tag_rules: vec![TagRule {
+    ///         condition: RuleCondition {
+    ///             payee: Some("hvv".to_string()),
+    ///             ..RuleCondition::default()
+    ///         },
+    ///         tags: vec!["commute".to_string()],
+    ///     }],
+    ///     split_rules: Vec::new(),
+    /// };
+    /// let date = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+    /// let ticket = CsvOutputRow::builder(date).payee("HVV Hamburg 1234").build();
+    /// assert_eq!(
+    ///     rules.matches(&ticket),
+    ///     vec![
+    ///         RuleMatch::Payee(Some(1), "HVV".to_string()),
+    ///         RuleMatch::Tag(1, vec!["commute".to_string()]),
+    ///     ]
+    /// );
+    /// assert_eq!(rules.matches(&CsvOutputRow::builder(date).payee("Bakery").build()), vec![]);
+    /// ```
+    pub fn matches(&self, transaction: &CsvOutputRow) -> Vec<RuleMatch> {
+        let mut matches = Vec::new();
+        let mut cleaned = transaction.clone();
+        let payee = transaction.payee.trim();
+        if let Some((index, (pattern, replacement))) = self
+            .payee_rules
+            .iter()
+            .enumerate()
+            .find(|(_, (pattern, _))| pattern.is_match(payee))
+        {
+            cleaned.payee = pattern
+                .replace(payee, replacement.as_str())
+                .trim()
+                .to_string();
+            let number = (index < self.configured_payee_rules).then_some(index + 1);
+            matches.push(RuleMatch::Payee(number, cleaned.payee.clone()));
+        }
+        for (index, rule) in self.tag_rules.iter().enumerate() {
+            if rule.condition.matches(&cleaned) {
+                matches.push(RuleMatch::Tag(index + 1, rule.tags.clone()));
+            }
+        }
+        if let Some((index, rule)) = self
+            .split_rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.condition.matches(&cleaned))
+        {
+            let labels = rule.parts.iter().map(|part| part.label.clone()).collect();
+            matches.push(RuleMatch::Split(index + 1, labels));
+        }
+        matches
+    }
+}
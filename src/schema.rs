@@ -0,0 +1,50 @@
+//! Version of the layout of the monthly files, recorded next to them so older files can be upgraded
+//!
+//! Versions:
+//! 1. Files written before the version was recorded; optional columns in the order of the flags
+//! 2. Optional columns in the order of [`ExtraColumn::ALL`]; `upgrade` also gives all the files
+//!    of a directory the same optional columns, so they can be appended to and consolidated alike
+use crate::ExtraColumn;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Version of the layout written by this version of the crate
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// File in the output directory with the schema version of its monthly files
+pub const SCHEMA_FILE_NAME: &str = ".bank-csv-schema";
+
+/// The schema version of the monthly files of a directory; 1 if it was never recorded
+pub fn schema_version(dir: &Path) -> io::Result<u32> {
+    let path = dir.join(SCHEMA_FILE_NAME);
+    if !path.exists() {
+        return Ok(1);
+    }
+    fs::read_to_string(&path)?.trim().parse().map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), err),
+        )
+    })
+}
+
+/// Record that the monthly files of a directory have the current schema version
+pub fn write_schema_version(dir: &Path) -> io::Result<()> {
+    fs::write(dir.join(SCHEMA_FILE_NAME), format!("{}\n", SCHEMA_VERSION))
+}
+
+/// Sort optional columns in the order of the current schema
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::schema::sort_columns;
+/// use bank_csv::ExtraColumn;
+/// let mut columns = vec![ExtraColumn::Tags, ExtraColumn::Id, ExtraColumn::Time];
+/// sort_columns(&mut columns);
+/// assert_eq!(columns, [ExtraColumn::Time, ExtraColumn::Id, ExtraColumn::Tags]);
+/// ```
+pub fn sort_columns(columns: &mut [ExtraColumn]) {
+    columns.sort_by_key(|column| ExtraColumn::ALL.iter().position(|known| known == column));
+}
@@ -0,0 +1,256 @@
+//! Rhai scripts to transform the normalized transactions, available with the `scripting` feature
+//!
+//! The script defines a `transform(row)` function, called for each transaction after the rules
+//! of the configuration. The row is a map with the output fields (`date`, `source`, `currency`,
+//! `amount`, `type`, `payee`, `memo`) and the extra columns by their English name (`Tags`,
+//! `Invoice`, `Normalized Type`, ...). The function returns the changed row, or nothing to drop it:
+//!
+//! ```rhai
+//! fn transform(row) {
+//!     if row.payee == "Main Account" {
+//!         return;
+//!     }
+//!     if row.memo.contains("Amazon") {
+//!         row.Tags = "online";
+//!     }
+//!     row
+//! }
+//! ```
+//!
+//! Format descriptors can have a script too (see [`crate::formats`]), called with the fields of each
+//! row of the bank file (`currency`, `amount`, `type`, `payee`, `memo`) before they are normalized.
+use crate::sources::SourceRow;
+use crate::CsvOutputRow;
+use std::io;
+use std::path::Path;
+
+#[cfg(feature = "scripting")]
+use crate::{ExtraColumn, Locale};
+#[cfg(feature = "scripting")]
+use chrono::NaiveDate;
+#[cfg(feature = "scripting")]
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+#[cfg(feature = "scripting")]
+use std::sync::Arc;
+
+/// Name of the function called for each transaction
+#[cfg(feature = "scripting")]
+const TRANSFORM_FUNCTION: &str = "transform";
+
+/// A compiled script, applied to each transaction of a merge
+#[derive(Clone, Debug)]
+pub struct RowScript {
+    /// Where the script comes from (its file, or a format descriptor), shown in errors
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    origin: String,
+    /// Shared by the clones of the options; with the `sync` feature of Rhai, the options are
+    /// `Send` and `Sync` with scripting like without it
+    #[cfg(feature = "scripting")]
+    engine: Arc<Engine>,
+    #[cfg(feature = "scripting")]
+    ast: AST,
+}
+
+#[cfg(feature = "scripting")]
+impl RowScript {
+    /// Compile a script file; it must define a `transform(row)` function
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Self::compile(&path.display().to_string(), &content)
+    }
+
+    /// Compile the content of a script; it must define a `transform(row)` function
+    ///
+    /// # Arguments
+    ///
+    /// * `origin`: where the script comes from, shown in errors
+    /// * `content`: the code of the script
+    pub fn compile(origin: &str, content: &str) -> io::Result<Self> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile(content)
+            .map_err(|err| invalid_script(origin, err))?;
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == TRANSFORM_FUNCTION && function.params.len() == 1)
+        {
+            return Err(invalid_script(
+                origin,
+                format!("a {}(row) function is missing", TRANSFORM_FUNCTION),
+            ));
+        }
+        Ok(Self {
+            origin: origin.to_string(),
+            engine: Arc::new(engine),
+            ast,
+        })
+    }
+
+    /// Call the `transform` function with a row
+    ///
+    /// returns: Result<Option<Map>, Error> with the changed row, or None if the script dropped it
+    fn call(&self, row: Map) -> io::Result<Option<Map>> {
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, TRANSFORM_FUNCTION, (row,))
+            .map_err(|err| invalid_script(&self.origin, err))?;
+        if result.is_unit() {
+            return Ok(None);
+        }
+        result.try_cast::<Map>().map(Some).ok_or_else(|| {
+            invalid_script(
+                &self.origin,
+                "transform(row) must return the row, or nothing to drop it",
+            )
+        })
+    }
+
+    /// Run the script on the fields of a row of a bank file
+    ///
+    /// returns: Result<bool, Error>; false if the script dropped the row
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::script::RowScript;
+    /// use bank_csv::sources::SourceRow;
+    /// let script = RowScript::compile("example", r#"
+    ///     fn transform(row) {
+    ///         let parts = row.memo.split(" / ");
+    ///         if parts.len() == 2 {
+    ///             row.payee = parts[0];
+    ///             row.memo = parts[1];
+    ///         }
+    ///         row
+    ///     }
+    /// "#).unwrap();
+    /// let mut row = SourceRow { memo: "Albert Heijn / Pinbetaling".to_string(), ..SourceRow::default() };
+    /// assert!(script.apply_to_source_row(&mut row).unwrap());
+    /// assert_eq!((row.payee.as_str(), row.memo.as_str()), ("Albert Heijn", "Pinbetaling"));
+    /// ```
+    pub fn apply_to_source_row(&self, source_row: &mut SourceRow) -> io::Result<bool> {
+        let mut row = Map::new();
+        for (key, value) in [
+            ("currency", &source_row.currency),
+            ("amount", &source_row.amount),
+            ("type", &source_row.transaction_type),
+            ("payee", &source_row.payee),
+            ("memo", &source_row.memo),
+        ] {
+            row.insert(key.into(), Dynamic::from(value.clone()));
+        }
+        let Some(row) = self.call(row)? else {
+            return Ok(false);
+        };
+        for (key, value) in row {
+            let value = value.to_string();
+            match key.as_str() {
+                "currency" => source_row.currency = value,
+                "amount" => source_row.amount = value,
+                "type" => source_row.transaction_type = value,
+                "payee" => source_row.payee = value,
+                "memo" => source_row.memo = value,
+                other => {
+                    return Err(invalid_script(
+                        &self.origin,
+                        format!("unknown field {:?}", other),
+                    ))
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Run the script on a transaction
+    ///
+    /// returns: Result<Option<CsvOutputRow>, Error> with the changed transaction,
+    /// or None if the script dropped it
+    pub fn apply(&self, mut transaction: CsvOutputRow) -> io::Result<Option<CsvOutputRow>> {
+        let mut row = Map::new();
+        for (key, value) in [
+            ("date", transaction.date.to_string()),
+            ("source", transaction.source.clone()),
+            ("currency", transaction.currency.clone()),
+            ("amount", transaction.amount.clone()),
+            ("type", transaction.transaction_type.clone()),
+            ("payee", transaction.payee.clone()),
+            ("memo", transaction.memo.clone()),
+        ] {
+            row.insert(key.into(), Dynamic::from(value));
+        }
+        for (column, value) in &transaction.extra {
+            row.insert(
+                column.label(Locale::En).into(),
+                Dynamic::from(value.clone()),
+            );
+        }
+        let Some(row) = self.call(row)? else {
+            return Ok(None);
+        };
+        transaction.extra.clear();
+        for (key, value) in row {
+            let value = value.to_string();
+            match key.as_str() {
+                "date" => {
+                    transaction.date =
+                        NaiveDate::parse_from_str(&value, "%Y-%m-%d").map_err(|err| {
+                            invalid_script(&self.origin, format!("date {:?}: {}", value, err))
+                        })?
+                }
+                "source" => transaction.source = value,
+                "currency" => transaction.currency = value,
+                "amount" => transaction.amount = value,
+                "type" => transaction.transaction_type = value,
+                "payee" => transaction.payee = value,
+                "memo" => transaction.memo = value,
+                label => {
+                    let column = ExtraColumn::from_label(label).ok_or_else(|| {
+                        invalid_script(&self.origin, format!("unknown column {:?}", label))
+                    })?;
+                    if !value.is_empty() {
+                        transaction.extra.insert(column, value);
+                    }
+                }
+            }
+        }
+        Ok(Some(transaction))
+    }
+}
+
+/// An error of a script, with where it comes from
+#[cfg(feature = "scripting")]
+fn invalid_script(origin: &str, message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", origin, message),
+    )
+}
+
+#[cfg(not(feature = "scripting"))]
+impl RowScript {
+    /// Compile a script file; needs the `scripting` feature
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::compile(&path.display().to_string(), "")
+    }
+
+    /// Compile the content of a script; needs the `scripting` feature
+    pub fn compile(origin: &str, _content: &str) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{}: scripts need bank-csv installed with the `scripting` feature",
+                origin
+            ),
+        ))
+    }
+
+    /// Run the script on the fields of a row of a bank file; needs the `scripting` feature
+    pub fn apply_to_source_row(&self, _source_row: &mut SourceRow) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// Run the script on a transaction; needs the `scripting` feature
+    pub fn apply(&self, transaction: CsvOutputRow) -> io::Result<Option<CsvOutputRow>> {
+        Ok(Some(transaction))
+    }
+}
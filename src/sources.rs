@@ -0,0 +1,406 @@
+//! Specifications of the sources: how to recognize their files and turn their rows into transactions
+//!
+//! Each built-in format ([`crate::CsvFormat`], read with the spec of its bank in [`crate::banks`])
+//! is a [`SourceSpec`], and so is each format descriptor (see [`crate::formats`]). Programs using this
+//! crate can support other banks by implementing the trait and registering them in the
+//! [`SourceRegistry`] of the merge options, after the built-in formats.
+use crate::error::BankCsvError;
+use crate::{
+    preamble_edit_file, BalanceImpact, CsvFormat, StatementMetadata, PREAMBLE_LINES, UTF8_BOM,
+};
+use encoding_rs::Encoding;
+use polars::prelude::*;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use tempfile::NamedTempFile;
+
+/// The fields of a row of a bank file as text, before they are normalized into a transaction
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceRow {
+    /// Currency, empty if the source has no currency column
+    pub currency: String,
+    /// Amount, as written in the file
+    pub amount: String,
+    /// Transaction type
+    pub transaction_type: String,
+    /// Payee
+    pub payee: String,
+    /// Memo
+    pub memo: String,
+    /// Values of the extra columns of the source, see [`SourceSpec::columns`]
+    pub extra: Vec<String>,
+}
+
+/// How to read the files of a bank or service
+pub trait SourceSpec: fmt::Debug {
+    /// Name of the format, shown when reading its files;
+    /// the one of [`SourceSpec::format`] by default, so other sources must implement it
+    fn name(&self) -> String {
+        self.format()
+            .map(|format| format.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Name of the source, written to the output files;
+    /// the bank of [`SourceSpec::format`] by default, so other sources must implement it
+    fn source_name(&self) -> String {
+        self.format()
+            .map(|format| format.source().to_string())
+            .unwrap_or_default()
+    }
+
+    /// The built-in format read by this spec; `None` for other sources
+    fn format(&self) -> Option<CsvFormat> {
+        None
+    }
+
+    /// The first columns of the header of the format, used to detect it and to suggest it
+    /// for files whose columns are in another order; none by default
+    fn first_columns(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Header matcher: check if the column names of a file are the ones of this format;
+    /// by default, if they start with [`SourceSpec::first_columns`]
+    fn matches_header(&self, column_names: &[&str]) -> bool {
+        let first_columns = self.first_columns();
+        !first_columns.is_empty() && column_names.starts_with(first_columns)
+    }
+
+    /// Header translation: the column names known to [`SourceSpec::matches_header`],
+    /// if the given ones are a localized version of them (e.g. an export in another language)
+    fn translate_header(&self, _column_names: &[&str]) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Preamble stripping: the start of the header line (without quotes), for files with lines
+    /// above the header; these lines and the ones below the transactions are skipped
+    fn header_after_preamble(&self) -> Option<&str> {
+        self.format()
+            .and_then(|format| format.source().header_after_preamble())
+    }
+
+    /// Whether the files are copied with [`SourceSpec::edit_file`] before they are read;
+    /// by default, if they have lines above the header (see [`SourceSpec::header_after_preamble`])
+    fn edits_file(&self) -> bool {
+        self.header_after_preamble().is_some()
+    }
+
+    /// File editing: copy a file to `temp_file` in UTF-8, without the lines that can't be read as CSV;
+    /// by default, the lines above the header of [`SourceSpec::header_after_preamble`]
+    /// and below the transactions are skipped
+    ///
+    /// returns: Result<Option<StatementMetadata>, BankCsvError> with the account data found in the skipped lines, if any
+    fn edit_file(
+        &self,
+        file_path: &Path,
+        temp_file: &NamedTempFile,
+        encoding: &'static Encoding,
+    ) -> Result<Option<StatementMetadata>, BankCsvError> {
+        let header_start = self.header_after_preamble().unwrap_or_default();
+        preamble_edit_file(file_path, temp_file, header_start, encoding)?;
+        Ok(None)
+    }
+
+    /// Encoding of the files, if they are not UTF-8; by default, the one of the bank
+    /// of [`SourceSpec::format`], or detected
+    fn encoding(&self) -> Option<&'static Encoding> {
+        self.format().and_then(|format| format.source().encoding())
+    }
+
+    /// Column mapping: the columns of the date, currency, amount, type, payee and memo, in this order,
+    /// followed by extra columns needed by [`SourceSpec::post_process`];
+    /// an empty name when the format has no such column
+    ///
+    /// # Arguments
+    ///
+    /// * `column_names`: the header of the file
+    /// * `upper_currency`: the requested currency, in uppercase
+    fn columns<'a>(&'a self, column_names: &[&'a str], upper_currency: &str) -> Vec<&'a str>;
+
+    /// Formats of the dates, tried in order when the date is text;
+    /// the formats in [`crate::FALLBACK_DATE_FORMATS`] are tried after these
+    fn date_formats(&self) -> Vec<&str>;
+
+    /// Row filter: the rows to select for the requested currency, see [`crate::filter_data_frame`];
+    /// by default, only the ones of [`SourceSpec::currency_filter`]
+    fn row_filter(
+        &self,
+        column_names: &[&str],
+        upper_currency: &str,
+        _balance_impact: &[BalanceImpact],
+    ) -> Option<Expr> {
+        self.currency_filter(column_names, upper_currency)
+    }
+
+    /// Currency filter: the rows in the requested currency, the only filter applied when nothing
+    /// else is filtered (`no_filter`); by default, the ones of [`SourceSpec::currency_column`],
+    /// and the ones with an empty currency for the [`SourceSpec::default_currency`].
+    /// Formats without a currency column are filtered after the selection, in [`SourceSpec::post_process`]
+    fn currency_filter(&self, column_names: &[&str], upper_currency: &str) -> Option<Expr> {
+        let column = self.currency_column()?;
+        let currency_is = col(column).eq(lit(upper_currency));
+        if self.default_currency(column_names) == upper_currency {
+            Some(
+                currency_is
+                    .or(col(column).eq(lit("")))
+                    .or(col(column).is_null()),
+            )
+        } else {
+            Some(currency_is)
+        }
+    }
+
+    /// Currency conversion: the original amount, its currency and the exchange rate
+    /// of a row paid in a foreign currency, from its memo; none by default
+    fn conversion(&self, _memo: &str) -> Option<(String, String, String)> {
+        None
+    }
+
+    /// Row post-processing: fix the fields of a row (signs, currencies, payees, ...)
+    ///
+    /// returns: Result<bool, BankCsvError>; false to drop the row, e.g. when the source can't have the requested currency
+    fn post_process(
+        &self,
+        _row: &mut SourceRow,
+        _upper_currency: &str,
+    ) -> Result<bool, BankCsvError> {
+        Ok(true)
+    }
+
+    /// Check if a transaction moves money between accounts of the same person at this source,
+    /// dropped unless `--keep-internal-transfers` is used
+    fn is_internal_transfer(&self, _transaction_type: &str, _memo: &str) -> bool {
+        false
+    }
+
+    /// Whether the files are securities (depot) exports, only read in securities mode
+    fn is_securities(&self) -> bool {
+        false
+    }
+
+    /// Whether the memo column of securities files is the ISIN of the security;
+    /// by default, for all securities files
+    fn memo_is_isin(&self) -> bool {
+        self.is_securities()
+    }
+
+    /// Whether the memo column is a unique transaction ID
+    fn memo_is_id(&self) -> bool {
+        false
+    }
+
+    /// The column with the value date (Wertstellung), for formats that have one besides the booking date
+    fn value_date_column(&self) -> Option<&str> {
+        None
+    }
+
+    /// The column with the currency of each row, for formats that have one
+    fn currency_column(&self) -> Option<&str> {
+        None
+    }
+
+    /// The currency of a file without a currency column, if it's in the name of its amount columns
+    fn header_currency<'a>(&self, _column_names: &[&'a str]) -> Option<&'a str> {
+        None
+    }
+
+    /// The currency of the rows without one: of all rows of a file without a currency column,
+    /// or of the rows with an empty currency; by default, the one of [`SourceSpec::header_currency`], or euros
+    fn default_currency(&self, column_names: &[&str]) -> String {
+        self.header_currency(column_names)
+            .map_or_else(|| "EUR".to_string(), str::to_uppercase)
+    }
+
+    /// The currency of a value of [`SourceSpec::currency_column`], e.g. for sources with their own codes;
+    /// by default, the value in uppercase
+    fn currency_code(&self, value: &str) -> String {
+        value.trim().to_uppercase()
+    }
+
+    /// The column with a fee that is not included in the amount, written as a separate expense
+    fn fee_column(&self) -> Option<&str> {
+        None
+    }
+
+    /// The columns with the time of each row and its time zone, for formats with a separate time;
+    /// other formats have either a timestamp in the date column or no time at all
+    fn time_columns(&self) -> Option<(&str, &str)> {
+        None
+    }
+
+    /// The column with the category of each row given by the source, for formats that have one
+    fn category_column(&self) -> Option<&str> {
+        None
+    }
+
+    /// The column with the name of the account of each row, for formats that have one
+    fn account_column(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Sources tried to detect the format of a file, in the order they were registered;
+/// the default registry has the built-in formats, in the order of [`CsvFormat::ALL`]
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::error::BankCsvError;
+/// use bank_csv::sources::{SourceRegistry, SourceRow, SourceSpec};
+///
+/// #[derive(Debug)]
+/// struct Bunq;
+///
+/// impl SourceSpec for Bunq {
+///     fn name(&self) -> String {
+///         "bunq".to_string()
+///     }
+///     fn source_name(&self) -> String {
+///         "bunq".to_string()
+///     }
+///     fn matches_header(&self, column_names: &[&str]) -> bool {
+///         column_names.starts_with(&["Date", "Interest Date", "Amount"])
+///     }
+///     fn columns<'a>(&'a self, _column_names: &[&'a str], _upper_currency: &str) -> Vec<&'a str> {
+///         vec!["Date", "", "Amount", "", "Name", "Description"]
+///     }
+///     fn date_formats(&self) -> Vec<&str> {
+///         vec!["%Y-%m-%d"]
+///     }
+///     fn post_process(&self, row: &mut SourceRow, upper_currency: &str) -> Result<bool, BankCsvError> {
+///         row.currency = "EUR".to_string();
+///         Ok(upper_currency == "EUR")
+///     }
+/// }
+///
+/// let mut registry = SourceRegistry::default();
+/// registry.register(Bunq);
+/// let spec = registry.detect(&["Date", "Interest Date", "Amount", "Name"]).unwrap();
+/// assert_eq!(spec.name(), "bunq");
+/// assert_eq!(spec.format(), None);
+/// let mut row = SourceRow::default();
+/// assert!(spec.post_process(&mut row, "EUR").unwrap());
+/// assert_eq!(row.currency, "EUR");
+/// assert!(registry.detect(&["Date", "Amount"]).is_none());
+/// // The built-in formats are registered first
+/// let paypal = registry.detect(&["Date", "Time", "TimeZone", "Name", "Type", "Status"]).unwrap();
+/// assert_eq!(paypal.format(), Some(bank_csv::CsvFormat::PayPal));
+/// ```
+#[derive(Clone, Debug)]
+pub struct SourceRegistry {
+    built_in: Vec<&'static dyn SourceSpec>,
+    specs: Vec<Rc<dyn SourceSpec>>,
+}
+
+impl Default for SourceRegistry {
+    fn default() -> Self {
+        SourceRegistry {
+            built_in: CsvFormat::ALL.iter().map(CsvFormat::spec).collect(),
+            specs: Vec::new(),
+        }
+    }
+}
+
+impl SourceRegistry {
+    /// Register a source, tried after the ones already registered
+    pub fn register(&mut self, spec: impl SourceSpec + 'static) {
+        self.specs.push(Rc::new(spec));
+    }
+
+    /// The registered sources, in order, after the built-in formats
+    pub fn specs(&self) -> impl Iterator<Item = &dyn SourceSpec> {
+        self.built_in
+            .iter()
+            .copied()
+            .chain(self.specs.iter().map(Rc::as_ref))
+    }
+
+    /// The first source whose header matches the column names of a file
+    pub fn detect(&self, column_names: &[&str]) -> Option<&dyn SourceSpec> {
+        self.specs().find(|spec| spec.matches_header(column_names))
+    }
+
+    /// The built-in format of a file, detected from the column names of its header
+    ///
+    /// returns: Result<CsvFormat, Vec<CsvFormat>>; the candidates (see [`SourceRegistry::candidates`])
+    /// if no built-in format matches, which can be empty or have more than one item
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::sources::SourceRegistry;
+    /// use bank_csv::CsvFormat;
+    /// let sources = SourceRegistry::default();
+    /// assert_eq!(sources.detect_format(&["Date", "Time", "TimeZone", "Name", "Type", "Status"]), Ok(CsvFormat::PayPal));
+    /// let n26_business = ["Booking Date", "Value Date", "Partner Name", "Partner Iban", "Type", "Payment Reference",
+    ///     "Account Name", "Amount (EUR)", "Original Amount", "Original Currency", "Exchange Rate", "Cashback (EUR)"];
+    /// assert_eq!(sources.detect_format(&n26_business), Ok(CsvFormat::N26Business));
+    /// assert_eq!(sources.detect_format(&n26_business[..11]), Ok(CsvFormat::N26Sep2024));
+    /// assert_eq!(sources.detect_format(&["Time", "Date", "TimeZone", "Name", "Type"]), Err(vec![CsvFormat::PayPal]));
+    /// assert_eq!(sources.detect_format(&["Foo", "Bar"]), Err(vec![]));
+    /// ```
+    pub fn detect_format(&self, column_names: &[&str]) -> Result<CsvFormat, Vec<CsvFormat>> {
+        self.detect(column_names)
+            .and_then(|spec| spec.format())
+            .ok_or_else(|| self.candidates(column_names))
+    }
+
+    /// The built-in formats whose first columns are all in the column names of a file, in any order;
+    /// the candidates to choose from when the format is not detected
+    pub fn candidates(&self, column_names: &[&str]) -> Vec<CsvFormat> {
+        self.specs()
+            .filter(|spec| {
+                spec.first_columns()
+                    .iter()
+                    .all(|column| column_names.contains(column))
+            })
+            .filter_map(|spec| spec.format())
+            .collect()
+    }
+
+    /// The column names of a file translated by the first source that knows their language,
+    /// see [`SourceSpec::translate_header`]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::sources::SourceRegistry;
+    /// let sources = SourceRegistry::default();
+    /// let header = ["Datum", "Empfänger", "Kontonummer", "Transaktionstyp", "Verwendungszweck", "Kategorie"];
+    /// assert_eq!(
+    ///     sources.translate_header(&header).unwrap(),
+    ///     vec!["Date", "Payee", "Account number", "Transaction type", "Payment reference", "Category"]
+    /// );
+    /// // Headers that are already in English, or from other banks, are not translated
+    /// assert_eq!(sources.translate_header(&["Date", "Payee", "Account number"]), None);
+    /// let paypal = ["Datum", "Uhrzeit", "Zeitzone", "Name", "Typ", "Status", "Währung", "Brutto"];
+    /// assert_eq!(
+    ///     sources.translate_header(&paypal).unwrap(),
+    ///     vec!["Date", "Time", "TimeZone", "Name", "Type", "Status", "Currency", "Gross"]
+    /// );
+    /// assert_eq!(sources.translate_header(&["Buchungstag", "Wertstellung", "Buchungstext"]), None);
+    /// let dkb = ["Booking date", "Value date", "Status", "Payer", "Payee", "Purpose", "Amount (€)"];
+    /// assert_eq!(
+    ///     sources.translate_header(&dkb).unwrap(),
+    ///     vec!["Buchungsdatum", "Wertstellung", "Status", "Zahlungspflichtige*r", "Zahlungsempfänger*in", "Verwendungszweck", "Betrag (€)"]
+    /// );
+    /// ```
+    pub fn translate_header(&self, column_names: &[&str]) -> Option<Vec<String>> {
+        self.specs()
+            .find_map(|spec| spec.translate_header(column_names))
+    }
+
+    /// The first source whose header starts one of the first lines of the content of a file,
+    /// see [`SourceSpec::header_after_preamble`]
+    pub fn detect_after_preamble(&self, text: &str) -> Option<&dyn SourceSpec> {
+        text.lines().take(PREAMBLE_LINES + 1).find_map(|line| {
+            let line = line.trim_start_matches(UTF8_BOM).replace('"', "");
+            self.specs().find(|spec| {
+                spec.header_after_preamble()
+                    .is_some_and(|header| line.starts_with(header))
+            })
+        })
+    }
+}
@@ -0,0 +1,151 @@
+//! Rules to split one transaction into several output rows, e.g. rent shared between housing and office
+use crate::rules::RuleCondition;
+use crate::{CsvOutputRow, ExtraColumn};
+use serde::Deserialize;
+
+/// One part of a split transaction
+#[derive(Clone, Debug, Deserialize)]
+pub struct SplitPart {
+    /// Fraction of the amount left after the fixed amounts, e.g. `0.3` for 30%
+    pub fraction: Option<f64>,
+    /// Fixed amount, with the same sign as the original transaction
+    pub amount: Option<f64>,
+    /// Label appended to the memo of the part, e.g. `office`
+    pub label: String,
+}
+
+/// A rule that splits matching transactions into parts
+#[derive(Clone, Debug, Deserialize)]
+pub struct SplitRule {
+    /// The transactions the rule applies to
+    #[serde(flatten)]
+    pub condition: RuleCondition,
+    /// Parts of the transaction; the last one gets whatever is left,
+    /// so the parts always add up to the original amount
+    pub parts: Vec<SplitPart>,
+}
+
+/// Columns with a part of the amount (VAT, net, gross, euro amount), split like the amount itself
+const AMOUNT_COLUMNS: [ExtraColumn; 4] = [
+    ExtraColumn::VatAmount,
+    ExtraColumn::NetAmount,
+    ExtraColumn::GrossAmount,
+    ExtraColumn::AmountEur,
+];
+
+/// Parse an amount with a decimal comma into cents
+fn to_cents(amount: &str) -> Option<i64> {
+    amount
+        .replace(',', ".")
+        .parse::<f64>()
+        .ok()
+        .map(|value| (value * 100.0).round() as i64)
+}
+
+/// Format cents as an amount with a decimal comma, like the rest of the output
+fn from_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{},{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+impl SplitRule {
+    /// Split a transaction into one row per part
+    ///
+    /// Fixed amounts are taken first, then the fractions are applied to the rest.
+    /// Amounts are rounded to cents and the last part gets the rounding difference.
+    /// The VAT, net, gross and euro amounts are split in the same proportion as the amount.
+    /// The transaction is returned unchanged if the rule has no parts or the amount is not a number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::split::SplitRule;
+    /// use bank_csv::{CsvOutputRow, ExtraColumn};
+    /// use chrono::NaiveDate;
+    /// let rule: SplitRule = toml::from_str(r#"
+    ///     payee = "hausverwaltung"
+    ///     parts = [{ fraction = 0.7, label = "housing" }, { fraction = 0.3, label = "office" }]
+    /// "#).unwrap();
+    /// let rent = CsvOutputRow::new(
+    ///     NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+    ///     "N26".to_string(),
+    ///     "EUR".to_string(),
+    ///     "-1000.01".to_string(),
+    ///     "Debit Transfer".to_string(),
+    ///     "Hausverwaltung GmbH".to_string(),
+    ///     "Miete Januar".to_string(),
+    /// );
+    /// assert!(rule.condition.matches(&rent));
+    /// let parts = rule.split(&rent);
+    /// assert_eq!(parts[0].amount, "-700,01");
+    /// assert_eq!(parts[0].memo, "Miete Januar [housing]");
+    /// assert_eq!(parts[1].amount, "-300,00");
+    /// assert_eq!(parts[1].memo, "Miete Januar [office]");
+    ///
+    /// // The VAT of the transaction is not repeated on every part
+    /// let mut invoice = rent.clone();
+    /// invoice.amount = "-119,00".to_string();
+    /// invoice.memo = "Rechnung 42 inkl. 19% MwSt".to_string();
+    /// invoice.extra.insert(ExtraColumn::VatAmount, "-19,00".to_string());
+    /// let parts = rule.split(&invoice);
+    /// assert_eq!(parts[0].extra[&ExtraColumn::VatAmount], "-13,30");
+    /// assert_eq!(parts[1].extra[&ExtraColumn::VatAmount], "-5,70");
+    /// ```
+    pub fn split(&self, transaction: &CsvOutputRow) -> Vec<CsvOutputRow> {
+        let Some(total) = to_cents(&transaction.amount).filter(|_| !self.parts.is_empty()) else {
+            return vec![transaction.clone()];
+        };
+        let sign = if total < 0 { -1 } else { 1 };
+        let fixed: i64 = self
+            .parts
+            .iter()
+            .filter_map(|part| part.amount)
+            .map(|amount| sign * (amount.abs() * 100.0).round() as i64)
+            .sum();
+        let rest = total - fixed;
+        // Total and assigned cents of each amount column; columns that are not numbers are left out of the parts
+        let mut columns: Vec<(ExtraColumn, Option<i64>, i64)> = AMOUNT_COLUMNS
+            .into_iter()
+            .filter_map(|column| {
+                let value = transaction.extra.get(&column)?;
+                Some((column, to_cents(value), 0))
+            })
+            .collect();
+
+        let mut rows = Vec::with_capacity(self.parts.len());
+        let mut assigned = 0;
+        for (index, part) in self.parts.iter().enumerate() {
+            let is_last = index == self.parts.len() - 1;
+            let cents = if is_last {
+                total - assigned
+            } else {
+                match (part.amount, part.fraction) {
+                    (Some(amount), _) => sign * (amount.abs() * 100.0).round() as i64,
+                    (None, Some(fraction)) => (rest as f64 * fraction).round() as i64,
+                    (None, None) => 0,
+                }
+            };
+            assigned += cents;
+            let mut row = transaction.clone();
+            row.amount = from_cents(cents);
+            for (column, column_total, column_assigned) in columns.iter_mut() {
+                let Some(column_total) = *column_total else {
+                    row.extra.remove(column);
+                    continue;
+                };
+                let column_cents = if is_last {
+                    column_total - *column_assigned
+                } else if total == 0 {
+                    0
+                } else {
+                    (column_total as f64 * cents as f64 / total as f64).round() as i64
+                };
+                *column_assigned += column_cents;
+                row.extra.insert(*column, from_cents(column_cents));
+            }
+            row.memo = format!("{} [{}]", transaction.memo, part.label);
+            rows.push(row);
+        }
+        rows
+    }
+}
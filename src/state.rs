@@ -0,0 +1,97 @@
+//! State kept between runs, to export only new transactions
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The latest transaction date already exported, per source and account
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct State {
+    /// Latest exported transaction date, keyed by source (N26, DKB, ...),
+    /// followed by the account label when the transactions have one (`N26/Joint`)
+    pub last_dates: BTreeMap<String, NaiveDate>,
+}
+
+impl State {
+    /// The default location of the state file: `~/.config/bank-csv/state.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".config").join("bank-csv").join("state.toml"))
+    }
+
+    /// Load the state from a TOML file; a missing file means nothing was exported yet
+    pub fn load(file_path: &Path) -> io::Result<Self> {
+        if !file_path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(file_path)?;
+        toml::from_str(&content).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", file_path.display(), err),
+            )
+        })
+    }
+
+    /// Save the state to a TOML file, creating its directory if needed
+    pub fn save(&self, file_path: &Path) -> io::Result<()> {
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = toml::to_string(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        fs::write(file_path, content)
+    }
+
+    /// The key of a source and account in [`State::last_dates`]
+    fn key(source: &str, account: Option<&str>) -> String {
+        match account {
+            Some(account) => format!("{}/{}", source, account),
+            None => source.to_string(),
+        }
+    }
+
+    /// Check if a transaction is older than the latest date exported for its source and account.
+    /// Transactions on the latest date itself are kept, because a bank export might have been
+    /// downloaded in the middle of that day.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bank_csv::state::State;
+    /// use chrono::NaiveDate;
+    ///
+    /// let mut state = State::default();
+    /// state.record("N26", Some("Personal"), NaiveDate::from_ymd_opt(2024, 3, 20).unwrap());
+    /// state.record("N26", Some("Joint"), NaiveDate::from_ymd_opt(2024, 3, 5).unwrap());
+    ///
+    /// // The dates of one account don't skip the transactions of the other
+    /// let date = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+    /// assert!(state.is_already_exported("N26", Some("Personal"), date));
+    /// assert!(!state.is_already_exported("N26", Some("Joint"), date));
+    /// assert!(!state.is_already_exported("N26", None, date));
+    /// ```
+    pub fn is_already_exported(
+        &self,
+        source: &str,
+        account: Option<&str>,
+        date: NaiveDate,
+    ) -> bool {
+        self.last_dates
+            .get(&Self::key(source, account))
+            .is_some_and(|last_date| date < *last_date)
+    }
+
+    /// Record the date of an exported transaction, if it's the latest for its source and account
+    pub fn record(&mut self, source: &str, account: Option<&str>, date: NaiveDate) {
+        let last_date = self
+            .last_dates
+            .entry(Self::key(source, account))
+            .or_insert(date);
+        if date > *last_date {
+            *last_date = date;
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Rules to tag transactions with free-form tags, e.g. vacation, tax-deductible or reimbursable
+use crate::rules::RuleCondition;
+use crate::CsvOutputRow;
+use serde::Deserialize;
+
+/// Separator of the tags in the Tags column
+pub const TAG_SEPARATOR: &str = ";";
+
+/// A rule that adds tags to matching transactions
+#[derive(Clone, Debug, Deserialize)]
+pub struct TagRule {
+    /// The transactions the rule applies to
+    #[serde(flatten)]
+    pub condition: RuleCondition,
+    /// Tags added to the matching transactions
+    pub tags: Vec<String>,
+}
+
+/// The tags of all matching rules, without duplicates and in the order of the rules,
+/// joined with [`TAG_SEPARATOR`]
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::rules::RuleCondition;
+/// use bank_csv::tags::{tags_for, TagRule};
+/// use bank_csv::CsvOutputRow;
+/// use chrono::NaiveDate;
+/// let rule = |payee: &str, tags: &[&str]| TagRule {
+///     condition: RuleCondition {
+///         payee: Some(payee.to_string()),
+///         ..RuleCondition::default()
+///     },
+///     tags: tags.iter().map(|tag| tag.to_string()).collect(),
+/// };
+/// let rules = [rule("hotel", &["vacation", "reimbursable"]), rule("lisbon", &["vacation"])];
+/// let transaction = CsvOutputRow::new(
+///     NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(),
+///     "N26".to_string(),
+///     "EUR".to_string(),
+///     "-250.00".to_string(),
+///     "Presentment".to_string(),
+///     "Hotel Lisbon".to_string(),
+///     "".to_string(),
+/// );
+/// assert_eq!(tags_for(&transaction, &rules), "vacation;reimbursable");
+/// assert_eq!(tags_for(&transaction, &[]), "");
+/// ```
+pub fn tags_for(transaction: &CsvOutputRow, rules: &[TagRule]) -> String {
+    let mut tags: Vec<&str> = Vec::new();
+    for rule in rules
+        .iter()
+        .filter(|rule| rule.condition.matches(transaction))
+    {
+        for tag in &rule.tags {
+            if !tags.contains(&tag.as_str()) {
+                tags.push(tag);
+            }
+        }
+    }
+    tags.join(TAG_SEPARATOR)
+}
+
+/// Add tags to the ones a transaction already has, without duplicates; the tags of both are
+/// separated by [`TAG_SEPARATOR`] and empty ones are dropped
+///
+/// # Examples
+///
+/// ```
+/// use bank_csv::tags::add_tags;
+/// assert_eq!(add_tags("vacation", "reimbursable; vacation"), "vacation;reimbursable");
+/// assert_eq!(add_tags("", "tax-deductible;"), "tax-deductible");
+/// assert_eq!(add_tags("vacation", ""), "vacation");
+/// ```
+pub fn add_tags(tags: &str, more: &str) -> String {
+    let mut all: Vec<&str> = Vec::new();
+    for tag in tags.split(TAG_SEPARATOR).chain(more.split(TAG_SEPARATOR)) {
+        let tag = tag.trim();
+        if !tag.is_empty() && !all.contains(&tag) {
+            all.push(tag);
+        }
+    }
+    all.join(TAG_SEPARATOR)
+}
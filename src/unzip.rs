@@ -0,0 +1,99 @@
+//! ZIP archives as input, for banks that deliver their statements zipped (sometimes with a password)
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// Convert a ZIP error into an I/O error, mentioning the archive
+pub(crate) fn to_io_error(zip_file: &Path, err: ZipError) -> io::Error {
+    match err {
+        ZipError::Io(err) => err,
+        err => io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", zip_file.display(), err),
+        ),
+    }
+}
+
+/// Check if a file is a ZIP archive, by its extension
+pub fn is_zip_file(file_path: &Path) -> bool {
+    file_path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// Check if any file inside a ZIP archive is encrypted and needs a password
+pub fn needs_password(zip_file: &Path) -> io::Result<bool> {
+    let mut archive =
+        ZipArchive::new(File::open(zip_file)?).map_err(|err| to_io_error(zip_file, err))?;
+    for index in 0..archive.len() {
+        match archive.by_index(index) {
+            Ok(_) => {}
+            Err(ZipError::UnsupportedArchive(message))
+                if message == ZipError::PASSWORD_REQUIRED =>
+            {
+                return Ok(true)
+            }
+            Err(err) => return Err(to_io_error(zip_file, err)),
+        }
+    }
+    Ok(false)
+}
+
+/// Extract the CSV files of a ZIP archive into a directory; other files are ignored
+///
+/// Files keep their folders inside the archive, so files with the same name in different folders
+/// (e.g. `2024/statement.csv` and `2025/statement.csv`) don't overwrite each other.
+///
+/// # Arguments
+///
+/// * `zip_file`: path to the ZIP archive
+/// * `target_dir`: directory to extract the files to, usually a temporary one
+/// * `password`: password of the archive, if its files are encrypted
+///
+/// returns: Result<Vec<PathBuf>, Error> with the paths of the extracted CSV files
+pub fn extract_csv_files(
+    zip_file: &Path,
+    target_dir: &Path,
+    password: Option<&str>,
+) -> io::Result<Vec<PathBuf>> {
+    let mut archive =
+        ZipArchive::new(File::open(zip_file)?).map_err(|err| to_io_error(zip_file, err))?;
+    let mut extracted_paths = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = match password {
+            Some(password) => archive
+                .by_index_decrypt(index, password.as_bytes())
+                .map_err(|err| to_io_error(zip_file, err))?
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!("{}: invalid password", zip_file.display()),
+                    )
+                })?,
+            None => archive
+                .by_index(index)
+                .map_err(|err| to_io_error(zip_file, err))?,
+        };
+        // Entries with absolute paths or ".." have no enclosed name,
+        // so nothing is written outside the target directory
+        let Some(relative_path) = entry.enclosed_name().map(PathBuf::from) else {
+            continue;
+        };
+        if !entry.is_file()
+            || !relative_path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+        {
+            continue;
+        }
+        let extracted_path = target_dir.join(relative_path);
+        if let Some(parent) = extracted_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&extracted_path)?)?;
+        extracted_paths.push(extracted_path);
+    }
+    Ok(extracted_paths)
+}
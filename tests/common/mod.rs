@@ -0,0 +1,10 @@
+//! Helpers shared by the integration tests
+use std::path::PathBuf;
+
+/// Path of a file in `tests/fixtures`
+pub fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
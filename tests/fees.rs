@@ -0,0 +1,131 @@
+//! Fees of Revolut (and Stripe) written as separate rows, which are normalized like their transactions
+mod common;
+
+use bank_csv::merge::{merge_files, read_file, FileContent, MergeOptions, SkipReason};
+use bank_csv::payees::PayeeFilter;
+use bank_csv::ExtraColumn;
+use chrono::NaiveDate;
+use common::fixture;
+
+#[test]
+fn pseudonymize_the_payees_of_fees() {
+    let options = MergeOptions {
+        pseudonym_key: Some("secret".to_string()),
+        ..MergeOptions::default()
+    };
+    let merged = merge_files(&[fixture("revolut-fees.csv")], &options).unwrap();
+    assert!(merged.ignored.is_empty(), "{:?}", merged.ignored);
+    assert_eq!(merged.transactions.len(), 4);
+    let fees: Vec<_> = merged
+        .transactions
+        .iter()
+        .filter(|transaction| transaction.transaction_type == "fee")
+        .collect();
+    assert_eq!(fees.len(), 2);
+    for transaction in &merged.transactions {
+        assert!(transaction.payee.starts_with("Payee "), "{:?}", transaction);
+        assert_ne!(transaction.payee, "Jane Doe");
+        assert_ne!(transaction.payee, "Coffee Shop");
+    }
+}
+
+#[test]
+fn drop_the_fee_with_its_transaction() {
+    let options = MergeOptions {
+        payee_filter: Some(PayeeFilter::parse("Coffee Shop", true)),
+        ..MergeOptions::default()
+    };
+    let merged = merge_files(&[fixture("revolut-fees.csv")], &options).unwrap();
+    assert_eq!(merged.transactions.len(), 2);
+    assert!(merged
+        .transactions
+        .iter()
+        .all(|transaction| transaction.payee == "Jane Doe"));
+    let fee = merged
+        .transactions
+        .iter()
+        .find(|transaction| transaction.transaction_type == "fee")
+        .unwrap();
+    assert_eq!(fee.amount.replace(',', ".").parse::<f64>().unwrap(), -1.5);
+}
+
+#[test]
+fn read_fees_with_a_decimal_comma_and_a_currency_symbol() {
+    let merged = merge_files(
+        &[fixture("revolut-fees-decimal-comma.csv")],
+        &MergeOptions::default(),
+    )
+    .unwrap();
+    assert!(merged.ignored.is_empty(), "{:?}", merged.ignored);
+    let fee = merged
+        .transactions
+        .iter()
+        .find(|transaction| transaction.transaction_type == "fee")
+        .unwrap();
+    assert_eq!(fee.amount, "-1,50");
+}
+
+#[test]
+fn skip_the_fee_with_its_transaction() {
+    // The transfer to Jane Doe is dated before the plausible dates
+    let options = MergeOptions {
+        plausible_dates: Some((
+            NaiveDate::from_ymd_opt(2024, 9, 4).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 12, 31).unwrap(),
+        )),
+        ..MergeOptions::default()
+    };
+    let Ok(FileContent::Transactions {
+        transactions,
+        skipped_rows,
+        ..
+    }) = read_file(&fixture("revolut-fees.csv"), &options, &mut |_| Ok(None))
+    else {
+        panic!("the Revolut file was not read");
+    };
+    assert_eq!(transactions.len(), 2);
+    assert!(transactions
+        .iter()
+        .all(|transaction| transaction.payee == "Coffee Shop"));
+    let skipped: Vec<_> = skipped_rows
+        .iter()
+        .map(|(reason, transaction)| (*reason, transaction.transaction_type.as_str()))
+        .collect();
+    assert_eq!(
+        skipped,
+        [
+            (SkipReason::ImplausibleDate, "fee"),
+            (SkipReason::ImplausibleDate, "TRANSFER")
+        ]
+    );
+}
+
+#[test]
+fn copy_the_id_of_the_transaction_to_its_fee() {
+    let merged = merge_files(&[fixture("stripe-fees.csv")], &MergeOptions::default()).unwrap();
+    assert!(merged.ignored.is_empty(), "{:?}", merged.ignored);
+    assert_eq!(merged.transactions.len(), 2);
+    for transaction in &merged.transactions {
+        assert_eq!(
+            transaction.extra[&ExtraColumn::Id],
+            "txn_1",
+            "{:?}",
+            transaction
+        );
+    }
+}
+
+#[test]
+fn refunded_fees_are_incomes() {
+    let merged = merge_files(
+        &[fixture("stripe-refunded-fee.csv")],
+        &MergeOptions::default(),
+    )
+    .unwrap();
+    let fee = merged
+        .transactions
+        .iter()
+        .find(|transaction| transaction.transaction_type == "fee")
+        .unwrap();
+    assert_eq!(fee.amount, "1,60");
+}
@@ -0,0 +1,41 @@
+//! Reading all rows of a file with `no_filter`: only the currency of each row decides where it goes
+mod common;
+
+use bank_csv::merge::{merge_files, MergeOptions};
+use common::fixture;
+
+#[test]
+fn keep_pending_rows_and_rows_without_a_currency() {
+    let options = MergeOptions {
+        no_filter: true,
+        ..MergeOptions::default()
+    };
+    let merged = merge_files(&[fixture("revolut-pending.csv")], &options).unwrap();
+    assert!(merged.ignored.is_empty(), "{:?}", merged.ignored);
+    let mut payees: Vec<_> = merged
+        .transactions
+        .iter()
+        .map(|transaction| (transaction.payee.as_str(), transaction.currency.as_str()))
+        .collect();
+    payees.sort();
+    assert_eq!(
+        payees,
+        [
+            ("Bakery", "EUR"),
+            ("Book Store", "EUR"),
+            ("Coffee Shop", "EUR")
+        ]
+    );
+}
+
+#[test]
+fn drop_pending_rows_by_default() {
+    let merged = merge_files(&[fixture("revolut-pending.csv")], &MergeOptions::default()).unwrap();
+    let mut payees: Vec<_> = merged
+        .transactions
+        .iter()
+        .map(|transaction| transaction.payee.as_str())
+        .collect();
+    payees.sort();
+    assert_eq!(payees, ["Book Store", "Coffee Shop"]);
+}
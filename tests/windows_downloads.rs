@@ -0,0 +1,43 @@
+//! Files downloaded with some browsers on Windows, with a byte order mark and CRLF line endings
+mod common;
+
+use bank_csv::merge::{detect_source, merge_files, MergeOptions};
+use bank_csv::sources::SourceRegistry;
+use bank_csv::{CsvFormat, Source};
+use chrono::NaiveDate;
+use common::fixture;
+
+#[test]
+fn detect_n26_with_bom_and_crlf() {
+    let info = detect_source(&fixture("n26-bom-crlf.csv"), &SourceRegistry::default()).unwrap();
+    assert_eq!(info.separator, b',');
+    assert_eq!(info.source, Some(Source::N26));
+    assert_eq!(info.format, Some(CsvFormat::N26Sep2024));
+}
+
+#[test]
+fn merge_n26_with_bom_and_crlf() {
+    let merged = merge_files(&[fixture("n26-bom-crlf.csv")], &MergeOptions::default()).unwrap();
+    assert!(merged.ignored.is_empty(), "{:?}", merged.ignored);
+    assert_eq!(merged.transactions.len(), 2);
+
+    let payment = &merged.transactions[0];
+    assert_eq!(payment.date, NaiveDate::from_ymd_opt(2024, 9, 2).unwrap());
+    assert_eq!(payment.source, "N26");
+    assert_eq!(payment.payee, "REWE Markt");
+    assert_eq!(
+        payment.amount.replace(',', ".").parse::<f64>().unwrap(),
+        -23.45
+    );
+
+    // Neither the quotes nor the carriage returns are left in the values
+    let transfer = &merged.transactions[1];
+    assert_eq!(transfer.date, NaiveDate::from_ymd_opt(2024, 9, 5).unwrap());
+    assert_eq!(transfer.transaction_type, "Credit Transfer");
+    assert_eq!(transfer.payee, "Jane Doe");
+    assert_eq!(transfer.memo, "Rent share");
+    assert_eq!(
+        transfer.amount.replace(',', ".").parse::<f64>().unwrap(),
+        450.0
+    );
+}